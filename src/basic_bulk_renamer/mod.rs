@@ -1,9 +1,18 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::env;
 use std::ffi::OsString;
 use std::fs;
 use std::io::Error as IoError;
-use std::path::PathBuf;
+use std::iter;
+use std::os::unix::fs::symlink;
+use std::path::{Component, Path, PathBuf};
+use strum_macros::EnumString;
 use thiserror;
 
+mod plan;
+pub use plan::*;
+
 /// Rename Mapping Pair
 ///
 /// first responds to source path and last responds to target path.
@@ -21,17 +30,100 @@ pub enum RenameOverwriteMode {
     Error,
 }
 
+/// Operation to create the target files
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+pub enum RenameOperation {
+    /// Move the source files to the targets
+    Move,
+    /// Copy the source files to the targets
+    Copy,
+    /// Create hard links to the source files at the targets
+    Hardlink,
+    /// Create symbolic links to the source files at the targets
+    Symlink,
+}
+
+impl Default for RenameOperation {
+    fn default() -> Self {
+        Self::Move
+    }
+}
+
+/// Phase of `BulkRename::execute`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RenamePhase {
+    /// Moving (or copying) the files to temporary names
+    Prepare,
+    /// Moving the files from the temporary names to the targets
+    Rename,
+}
+
+/// Progress of `BulkRename::execute`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RenameProgress {
+    pub phase: RenamePhase,
+    /// Number of the files processed in this phase
+    pub done: usize,
+    /// Number of the files to be processed in this phase
+    pub total: usize,
+    /// File being processed
+    pub current: PathBuf,
+}
+
+/// Result of renaming each pair
+#[derive(Debug)]
+pub enum RenamePairResult {
+    /// Renamed to the path
+    Renamed(PathBuf),
+    /// Nothing to do
+    Skipped,
+    /// Failed to rename and left as it was
+    Failed(RenameError),
+}
+
 /// Bulk rename
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BulkRename {
     pub pairs: Vec<RenameMapPair>,
+    /// Create the missing directories of the target paths if `true`
+    ///
+    /// The directories can be created only under the parent directory of the source file.
+    pub create_directories: bool,
+    /// Operation to create the target files
+    ///
+    /// The source files are left untouched unless it is `RenameOperation::Move`.
+    pub operation: RenameOperation,
+    /// Directory to put the target files in instead of the parent directory of the source files
+    pub output_directory: Option<PathBuf>,
+    /// Rename the rest of the files even if some files fail to be renamed
+    pub continue_on_error: bool,
     undo_pairs: Option<Vec<RenameMapPair>>,
+    renamed_files: Option<Vec<PathBuf>>,
+    /// Failed pairs in terms of the locations after execution
+    retry_pairs: Vec<RenameMapPair>,
+    created_directories: Vec<PathBuf>,
+    /// Files to be removed on execution
+    cleanup_files: Vec<PathBuf>,
+    /// Directories to be removed after execution if they are empty
+    cleanup_directories: Vec<PathBuf>,
 }
 
 impl BulkRename {
     pub fn new(pairs: Vec<RenameMapPair>) -> Self {
         let undo_pairs = Some(Vec::with_capacity(pairs.len()));
-        Self { pairs, undo_pairs }
+        Self {
+            pairs,
+            create_directories: false,
+            operation: RenameOperation::default(),
+            output_directory: None,
+            continue_on_error: false,
+            undo_pairs,
+            renamed_files: None,
+            retry_pairs: Vec::new(),
+            created_directories: Vec::new(),
+            cleanup_files: Vec::new(),
+            cleanup_directories: Vec::new(),
+        }
     }
 
     fn fix_target_file_path(target: &PathBuf) -> Result<PathBuf, RenameError> {
@@ -67,83 +159,613 @@ impl BulkRename {
         Ok(())
     }
 
+    /// Reject the pairs whose source is located under the source of another pair
+    /// unless the operation is `RenameOperation::Move`.
+    ///
+    /// The copies or links of such files would not follow the copy of their ancestor.
+    fn check_nested_source_files(&self) -> Result<(), RenameError> {
+        if self.operation == RenameOperation::Move {
+            return Ok(());
+        }
+        let sources = self
+            .pairs
+            .iter()
+            .map(|(source, _)| source.as_path())
+            .collect::<HashSet<_>>();
+        let nested_source_files = self
+            .pairs
+            .iter()
+            .filter(|(source, _)| source.ancestors().skip(1).any(|dir| sources.contains(dir)))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !nested_source_files.is_empty() {
+            return Err(RenameError::NestedSourceFile(nested_source_files));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the directory where the target of `source` is based on.
+    fn base_directory_of<'a>(&'a self, source: &'a Path) -> &'a Path {
+        self.output_directory
+            .as_deref()
+            .unwrap_or_else(|| source.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Returns the target path of the pair taking `output_directory` into account.
+    fn target_of(&self, (source, target): &RenameMapPair) -> PathBuf {
+        if let Some(output_directory) = self.output_directory.as_ref() {
+            let source_parent = source.parent().unwrap_or(Path::new(""));
+            match target.strip_prefix(source_parent) {
+                Ok(relative_target) => output_directory.join(relative_target),
+                Err(_) => output_directory.join(target.file_name().unwrap_or_default()),
+            }
+        } else {
+            target.clone()
+        }
+    }
+
+    fn check_not_found_target_directories(&self) -> Result<(), RenameError> {
+        let not_found_target_directories = self
+            .pairs
+            .iter()
+            .filter(|&pair| match self.target_of(pair).parent() {
+                Some(target_parent) if !target_parent.exists() => {
+                    !self.create_directories
+                        || !Self::is_creatable_directory(
+                            self.base_directory_of(&pair.0),
+                            target_parent,
+                        )
+                }
+                _ => false,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if !not_found_target_directories.is_empty() {
+            return Err(RenameError::TargetDirectoryNotFound(
+                not_found_target_directories,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if `directory` is located under `base_directory`.
+    fn is_creatable_directory(base_directory: &Path, directory: &Path) -> bool {
+        directory
+            .strip_prefix(base_directory)
+            .map(|rest| {
+                rest.components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Create the missing ancestor directories of `target`.
+    fn create_target_directories(
+        target: &Path,
+        created_directories: &mut Vec<PathBuf>,
+    ) -> Result<(), IoError> {
+        let mut missing_directories = target
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.exists())
+            .collect::<Vec<_>>();
+        missing_directories.reverse();
+        for dir in missing_directories {
+            fs::create_dir(dir)?;
+            created_directories.push(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Copy `source` to `target` recursively, keeping symbolic links as they are.
+    fn copy_recursively(source: &Path, target: &Path) -> Result<(), IoError> {
+        let file_type = fs::symlink_metadata(source)?.file_type();
+        if file_type.is_symlink() {
+            symlink(fs::read_link(source)?, target)
+        } else if file_type.is_dir() {
+            fs::create_dir(target)?;
+            for entry in fs::read_dir(source)? {
+                let entry = entry?;
+                Self::copy_recursively(&entry.path(), &target.join(entry.file_name()))?;
+            }
+            Ok(())
+        } else {
+            fs::copy(source, target).map(|_| ())
+        }
+    }
+
+    /// Create the file at `temp_file` from `source` according to the operation.
+    ///
+    /// `temp_file` is an empty file on calling and is replaced by the created one.
+    fn create_temp_file(&self, source: &Path, temp_file: &Path) -> Result<(), IoError> {
+        match self.operation {
+            RenameOperation::Move => {
+                if !source.is_file() {
+                    // Remove temp_file before moving because fs::rename does not work for directory.
+                    fs::remove_file(temp_file)?;
+                }
+                fs::rename(source, temp_file)
+            }
+            RenameOperation::Copy => {
+                fs::remove_file(temp_file)?;
+                Self::copy_recursively(source, temp_file)
+            }
+            RenameOperation::Hardlink => {
+                fs::remove_file(temp_file)?;
+                fs::hard_link(source, temp_file)
+            }
+            RenameOperation::Symlink => {
+                fs::remove_file(temp_file)?;
+                if source.is_absolute() {
+                    symlink(source, temp_file)
+                } else {
+                    symlink(env::current_dir()?.join(source), temp_file)
+                }
+            }
+        }
+    }
+
+    /// Remove the file at `path`; directories are removed recursively.
+    fn remove_file(path: &Path) -> Result<(), IoError> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
     /// Execute renaming
+    ///
+    /// Both source and target paths are interpreted as they are before execution.
+    /// Hence, when a directory and its descendants are moved at once,
+    /// the descendants are moved inside the moved directory.
+    ///
+    /// If `continue_on_error` is `true`, the files are renamed as many as possible
+    /// and `RenameError::PartiallyFailed` is returned in case of some failures.
     pub fn execute(&mut self, over_write_mode: RenameOverwriteMode) -> Result<(), RenameError> {
+        self.execute_with_progress(over_write_mode, &mut |_| true)
+    }
+
+    /// Execute renaming with reporting the progress
+    ///
+    /// `progress` is called before processing each file. If it returns `false`,
+    /// renaming stops and `RenameError::Cancelled` is returned;
+    /// the files processed so far are restored by `undo_bulk_rename`.
+    pub fn execute_with_progress(
+        &mut self,
+        over_write_mode: RenameOverwriteMode,
+        progress: &mut dyn FnMut(&RenameProgress) -> bool,
+    ) -> Result<(), RenameError> {
         if self.undo_pairs.as_ref().map_or(true, |v| v.len() > 0) {
             return Err(RenameError::Executed);
         }
-        self.check_not_found_source_files()?;
-
-        // Step 1 Move the all files to temporary name.
-        let mut temp_filenames = Vec::with_capacity(self.pairs.len());
-        for pair in self.pairs.iter() {
-            let target_parent = pair.1.parent().ok_or(RenameError::IllegalOperation)?;
-            let temp_file = tempfile::Builder::new()
-                .prefix(pair.1.file_name().unwrap_or_default())
-                .tempfile_in(target_parent)
-                .map_err(|error| RenameError::TargetDirectoryNotWritable(pair.clone(), error))?;
-            let temp_file_path = temp_file.into_temp_path();
-            let temp_file_path = temp_file_path
-                .keep()
-                .map_err(|_| RenameError::IllegalOperation)?;
-
-            if !pair.0.is_file() {
-                // Remove temp_file before moving because fs::rename does not work for directory.
-                fs::remove_file(&temp_file_path)
-                    .map_err(|error| RenameError::IoError(pair.clone(), error))?;
+        self.check_nested_source_files()?;
+        if !self.continue_on_error {
+            self.check_not_found_source_files()?;
+            self.check_not_found_target_directories()?;
+        }
+        self.remove_cleanup_files()?;
+
+        let mut order = (0..self.pairs.len()).collect::<Vec<_>>();
+        if self.operation == RenameOperation::Move {
+            // Move the deepest files first so that their ancestors are still available.
+            order.sort_by_key(|&i| Reverse(self.pairs[i].0.components().count()));
+        } else {
+            // Copy the shallowest files first not to copy the temporary files of descendants.
+            order.sort_by_key(|&i| self.pairs[i].0.components().count());
+        }
+
+        let mut state = RenameState::new(
+            order
+                .iter()
+                .map(|&i| (self.pairs[i].0.clone(), self.target_of(&self.pairs[i]))),
+        );
+        // Keep the directories created by the preceding runs, e.g. the retried one.
+        state.created_directories = self.created_directories.clone();
+        if self.continue_on_error {
+            self.check_pairs_in_advance(&order, &mut state);
+        }
+        let result = self.execute_in_order(over_write_mode, &order, &mut state, progress);
+        if result.is_ok() {
+            let mut renamed_files = vec![PathBuf::new(); order.len()];
+            for (pos, &i) in order.iter().enumerate() {
+                renamed_files[i] = state.locations[pos].clone();
             }
-            fs::rename(&pair.0, &temp_file_path)
-                .map_err(|error| RenameError::IoError(pair.clone(), error))?;
-            if let Some(undo_pairs) = self.undo_pairs.as_mut() {
-                undo_pairs.push((temp_file_path.clone(), pair.0.clone()));
+            self.renamed_files = Some(renamed_files);
+        }
+        self.created_directories = state.created_directories;
+        if self.undo_pairs.is_some() && state.undoable {
+            self.undo_pairs = Some(state.undo_pairs.into_iter().flatten().collect());
+        } else {
+            self.undo_pairs = None;
+        }
+        if result.is_ok() {
+            self.remove_cleanup_directories();
+        }
+        result?;
+
+        let mut results = Vec::with_capacity(order.len());
+        let mut retry_pairs = Vec::new();
+        for (pos, result) in state.results.into_iter().enumerate() {
+            let result = result.unwrap_or(RenamePairResult::Skipped);
+            if let RenamePairResult::Failed(_) = result {
+                let pair = (state.locations[pos].clone(), state.targets[pos].clone());
+                retry_pairs.push((order[pos], pair));
             }
-            temp_filenames.push(temp_file_path);
+            results.push((order[pos], result));
+        }
+        results.sort_by_key(|&(i, _)| i);
+        retry_pairs.sort_by_key(|&(i, _)| i);
+        self.retry_pairs = retry_pairs.into_iter().map(|(_, pair)| pair).collect();
+        if self.retry_pairs.is_empty() {
+            Ok(())
+        } else {
+            Err(RenameError::PartiallyFailed(
+                results
+                    .into_iter()
+                    .map(|(i, result)| (self.pairs[i].clone(), result))
+                    .collect(),
+            ))
         }
+    }
 
-        // Step 2 Move them to target
-        for (i, pair) in self.pairs.iter().enumerate() {
-            let target_temp_file = &temp_filenames[i];
-            let target_file = match over_write_mode {
-                RenameOverwriteMode::ChangeFileName => Self::fix_target_file_path(&pair.1),
-                RenameOverwriteMode::Overwrite => {
-                    if pair.1.exists() {
-                        self.undo_pairs = None; // Mark not undoable
+    /// Mark the pairs that cannot be or need not be renamed.
+    fn check_pairs_in_advance(&self, order: &[usize], state: &mut RenameState) {
+        for (pos, &i) in order.iter().enumerate() {
+            let pair = &self.pairs[i];
+            let target = &state.targets[pos];
+            state.results[pos] = if !pair.0.exists() {
+                Some(RenamePairResult::Failed(RenameError::SourceFileNotFound(
+                    vec![pair.clone()],
+                )))
+            } else if &pair.0 == target && self.operation == RenameOperation::Move {
+                Some(RenamePairResult::Skipped)
+            } else {
+                match target.parent() {
+                    Some(target_parent)
+                        if !target_parent.exists()
+                            && (!self.create_directories
+                                || !Self::is_creatable_directory(
+                                    self.base_directory_of(&pair.0),
+                                    target_parent,
+                                )) =>
+                    {
+                        Some(RenamePairResult::Failed(
+                            RenameError::TargetDirectoryNotFound(vec![pair.clone()]),
+                        ))
                     }
-                    Ok(pair.1.clone())
+                    _ => None,
                 }
-                RenameOverwriteMode::Error => {
-                    if pair.1.exists() {
-                        Err(RenameError::TargetFileAlreadyExists(pair.clone()))
-                    } else {
-                        Ok(pair.1.clone())
-                    }
-                }
-            }?;
+            };
+        }
+    }
+
+    /// Remove the files to be cleaned up.
+    fn remove_cleanup_files(&self) -> Result<(), RenameError> {
+        for file in self.cleanup_files.iter() {
+            if fs::symlink_metadata(file).is_ok() {
+                Self::remove_file(file)
+                    .map_err(|error| RenameError::IoError((file.clone(), file.clone()), error))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the empty directories to be cleaned up, the deepest first.
+    fn remove_cleanup_directories(&self) {
+        let mut directories = self.cleanup_directories.iter().collect::<Vec<_>>();
+        directories.sort_by_key(|dir| Reverse(dir.components().count()));
+        for dir in directories {
+            // Non-empty directories are kept as-is.
+            fs::remove_dir(dir).unwrap_or_default();
+        }
+    }
 
-            if target_file.exists() && !(target_temp_file.is_file() && target_file.is_file()) {
-                // Remove target before moving because fs::rename does not work for directory.
-                if target_file.is_dir() {
-                    fs::remove_dir_all(&target_file)
+    fn execute_in_order(
+        &self,
+        over_write_mode: RenameOverwriteMode,
+        order: &[usize],
+        state: &mut RenameState,
+        progress: &mut dyn FnMut(&RenameProgress) -> bool,
+    ) -> Result<(), RenameError> {
+        let total = state.results.iter().filter(|v| v.is_none()).count();
+
+        // Step 1 Move (or copy) the all files to temporary name.
+        let mut done = 0;
+        for (pos, &i) in order.iter().enumerate() {
+            if state.results[pos].is_some() {
+                continue;
+            }
+            Self::report_progress(
+                progress,
+                RenamePhase::Prepare,
+                done,
+                total,
+                &state.locations[pos],
+            )?;
+            done += 1;
+            if let Err(error) = self.execute_step1(&self.pairs[i], pos, state) {
+                if self.continue_on_error {
+                    state.results[pos] = Some(RenamePairResult::Failed(error));
                 } else {
-                    fs::remove_file(&target_file)
+                    return Err(error);
                 }
-                .map_err(|error| RenameError::IoError(pair.clone(), error))?;
             }
-            fs::rename(target_temp_file, &target_file)
-                .map_err(|error| RenameError::IoError(pair.clone(), error))?;
-            if let Some(undo_pairs) = self.undo_pairs.as_mut() {
-                undo_pairs[i].0 = target_file;
+        }
+
+        // Step 2 Move them to target, the shallowest first
+        let mut done = 0;
+        for (pos, &i) in order.iter().enumerate().rev() {
+            if state.results[pos].is_some() {
+                continue;
+            }
+            Self::report_progress(
+                progress,
+                RenamePhase::Rename,
+                done,
+                total,
+                &state.targets[pos],
+            )?;
+            done += 1;
+            match self.execute_step2(&self.pairs[i], pos, over_write_mode, state) {
+                Ok(()) => {
+                    state.results[pos] =
+                        Some(RenamePairResult::Renamed(state.locations[pos].clone()));
+                }
+                Err(error) if self.continue_on_error => {
+                    self.restore_temp_file(pos, state);
+                    state.results[pos] = Some(RenamePairResult::Failed(error));
+                }
+                Err(error) => return Err(error),
             }
         }
 
         Ok(())
     }
 
+    /// Report the progress and returns `RenameError::Cancelled` if cancelled.
+    fn report_progress(
+        progress: &mut dyn FnMut(&RenameProgress) -> bool,
+        phase: RenamePhase,
+        done: usize,
+        total: usize,
+        current: &Path,
+    ) -> Result<(), RenameError> {
+        let progress_state = RenameProgress {
+            phase,
+            done,
+            total,
+            current: current.to_path_buf(),
+        };
+        if progress(&progress_state) {
+            Ok(())
+        } else {
+            Err(RenameError::Cancelled)
+        }
+    }
+
+    fn execute_step1(
+        &self,
+        pair: &RenameMapPair,
+        pos: usize,
+        state: &mut RenameState,
+    ) -> Result<(), RenameError> {
+        let source = state.locations[pos].clone();
+        let target = state.targets[pos].clone();
+        let target_parent = target.parent().ok_or(RenameError::IllegalOperation)?;
+        if self.create_directories {
+            Self::create_target_directories(&target, &mut state.created_directories)
+                .map_err(|error| RenameError::IoError(pair.clone(), error))?;
+        }
+        let temp_file = tempfile::Builder::new()
+            .prefix(target.file_name().unwrap_or_default())
+            .tempfile_in(target_parent)
+            .map_err(|error| RenameError::TargetDirectoryNotWritable(pair.clone(), error))?;
+        let temp_file_path = temp_file.into_temp_path();
+        let temp_file_path = temp_file_path
+            .keep()
+            .map_err(|_| RenameError::IllegalOperation)?;
+
+        if let Err(error) = self.create_temp_file(&source, &temp_file_path) {
+            // Do not leave the temporary file behind.
+            fs::remove_file(&temp_file_path).unwrap_or_default();
+            return Err(RenameError::IoError(pair.clone(), error));
+        }
+        state.undo_pairs[pos] = Some((temp_file_path.clone(), source.clone()));
+        if self.operation == RenameOperation::Move {
+            state.moved(pos, &source, &temp_file_path);
+        } else {
+            state.locations[pos] = temp_file_path;
+        }
+        Ok(())
+    }
+
+    fn execute_step2(
+        &self,
+        pair: &RenameMapPair,
+        pos: usize,
+        over_write_mode: RenameOverwriteMode,
+        state: &mut RenameState,
+    ) -> Result<(), RenameError> {
+        let target_temp_file = state.locations[pos].clone();
+        let target = state.targets[pos].clone();
+        let target_file = match over_write_mode {
+            RenameOverwriteMode::ChangeFileName => Self::fix_target_file_path(&target),
+            RenameOverwriteMode::Overwrite => {
+                if target.exists() {
+                    state.undoable = false; // Mark not undoable
+                }
+                Ok(target)
+            }
+            RenameOverwriteMode::Error => {
+                if target.exists() {
+                    Err(RenameError::TargetFileAlreadyExists(pair.clone()))
+                } else {
+                    Ok(target)
+                }
+            }
+        }?;
+
+        if target_file.exists() && !(target_temp_file.is_file() && target_file.is_file()) {
+            // Remove target before moving because fs::rename does not work for directory.
+            if target_file.is_dir() {
+                fs::remove_dir_all(&target_file)
+            } else {
+                fs::remove_file(&target_file)
+            }
+            .map_err(|error| RenameError::IoError(pair.clone(), error))?;
+        }
+        fs::rename(&target_temp_file, &target_file)
+            .map_err(|error| RenameError::IoError(pair.clone(), error))?;
+        if let Some(undo_pair) = state.undo_pairs[pos].as_mut() {
+            undo_pair.0 = target_file.clone();
+        }
+        state.moved(pos, &target_temp_file, &target_file);
+        Ok(())
+    }
+
+    /// Revert the file at `pos` from the temporary name after failure.
+    fn restore_temp_file(&self, pos: usize, state: &mut RenameState) {
+        if let Some((temp_file, source)) = state.undo_pairs[pos].clone() {
+            let restored = if self.operation == RenameOperation::Move {
+                fs::rename(&temp_file, &source)
+            } else {
+                Self::remove_file(&temp_file)
+            };
+            if restored.is_ok() {
+                // Otherwise, keep the undo pair to restore it on undoing.
+                state.undo_pairs[pos] = None;
+                if self.operation == RenameOperation::Move {
+                    state.moved(pos, &temp_file, &source);
+                } else {
+                    state.locations[pos] = source;
+                }
+            }
+        }
+    }
+
+    /// Returns the locations of the renamed files in the order of `pairs`.
+    /// Returns `None` if renaming has not been completed.
+    pub fn renamed_files(&self) -> Option<&[PathBuf]> {
+        self.renamed_files.as_deref()
+    }
+
+    /// Returns a bulk provider for retrying the failed files. Returns `None` if nothing failed.
+    ///
+    /// The undo of the retry also removes the directories created by this renaming if they end up empty.
+    pub fn retry_bulk_rename(&self) -> Option<BulkRename> {
+        if self.retry_pairs.is_empty() {
+            None
+        } else {
+            let pairs = self
+                .retry_pairs
+                .iter()
+                .map(|(source, target)| match self.output_directory.as_ref() {
+                    // Make the target relative to the source again, as `target_of` resolves it.
+                    Some(output_directory) => {
+                        let relative_target = target
+                            .strip_prefix(output_directory)
+                            .map(Path::to_path_buf)
+                            .unwrap_or_else(|_| {
+                                PathBuf::from(target.file_name().unwrap_or_default())
+                            });
+                        let source_parent = source.parent().unwrap_or(Path::new(""));
+                        (source.clone(), source_parent.join(relative_target))
+                    }
+                    None => (source.clone(), target.clone()),
+                })
+                .collect();
+            let mut retry = BulkRename::new(pairs);
+            retry.create_directories = self.create_directories;
+            retry.operation = self.operation;
+            retry.output_directory = self.output_directory.clone();
+            retry.continue_on_error = self.continue_on_error;
+            retry.created_directories = self.created_directories.clone();
+            Some(retry)
+        }
+    }
+
     /// Returns a bulk provider for undoing. Returns `None` if it is not undoable.
+    ///
+    /// The undo removes the directories created by this renaming if they end up empty.
+    /// Unless the operation is `RenameOperation::Move`, the undo removes the created files.
     pub fn undo_bulk_rename(&self) -> Option<BulkRename> {
-        self.undo_pairs
-            .as_ref()
-            .map(|undo_pairs| BulkRename::new(undo_pairs.clone()))
+        self.undo_pairs.as_ref().map(|undo_pairs| {
+            let mut undo = if self.operation == RenameOperation::Move {
+                BulkRename::new(undo_pairs.clone())
+            } else {
+                let mut undo = BulkRename::new(Vec::new());
+                undo.cleanup_files = undo_pairs.iter().map(|(file, _)| file.clone()).collect();
+                undo
+            };
+            undo.cleanup_directories = self.created_directories.clone();
+            undo
+        })
+    }
+}
+
+/// Progress of `BulkRename::execute`
+///
+/// All the vectors are in the order of execution.
+struct RenameState {
+    /// Current location of each file
+    locations: Vec<PathBuf>,
+    /// Target of each file, relocated as its ancestor directories move
+    targets: Vec<PathBuf>,
+    /// Pairs to revert the moves done so far
+    undo_pairs: Vec<Option<RenameMapPair>>,
+    /// Result of each file, `None` if not processed yet
+    results: Vec<Option<RenamePairResult>>,
+    /// `false` if some file has been overwritten
+    undoable: bool,
+    /// Directories created for the targets
+    created_directories: Vec<PathBuf>,
+}
+
+impl RenameState {
+    fn new<I: Iterator<Item = RenameMapPair>>(pairs: I) -> Self {
+        let (locations, targets): (Vec<_>, Vec<_>) = pairs.unzip();
+        let undo_pairs = vec![None; locations.len()];
+        let results = iter::repeat_with(|| None).take(locations.len()).collect();
+        Self {
+            locations,
+            targets,
+            undo_pairs,
+            results,
+            undoable: true,
+            created_directories: Vec::new(),
+        }
+    }
+
+    /// Record that the file at `pos` has been moved from `from` to `to`.
+    fn moved(&mut self, pos: usize, from: &Path, to: &Path) {
+        self.locations[pos] = to.to_path_buf();
+        if to.is_dir() {
+            let paths = self
+                .locations
+                .iter_mut()
+                .chain(self.targets.iter_mut())
+                .chain(self.created_directories.iter_mut());
+            let undo_paths = self
+                .undo_pairs
+                .iter_mut()
+                .flatten()
+                .flat_map(|(current, original)| iter::once(current).chain(iter::once(original)));
+            for path in paths.chain(undo_paths) {
+                Self::relocate_descendant(path, from, to);
+            }
+        }
+    }
+
+    /// Rewrite `path` to point into `to` if it is located under the directory `from`.
+    fn relocate_descendant(path: &mut PathBuf, from: &Path, to: &Path) {
+        if let Ok(rest) = path.strip_prefix(from) {
+            if !rest.as_os_str().is_empty() {
+                *path = to.join(rest);
+            }
+        }
     }
 }
 
@@ -319,6 +941,399 @@ mod test {
             assert_eq!(fs::read_to_string(target_path).unwrap(), format!("{}", i));
         }
     }
+
+    #[test]
+    pub fn test_execute_with_nested_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir1_path = path_buf_join(temp_dir.path(), "1.d");
+        let dir2_path = path_buf_join(&dir1_path, "2.d");
+        let file1_path = path_buf_join(&dir1_path, "1.txt");
+        let file2_path = path_buf_join(&dir2_path, "2.txt");
+        fs::create_dir_all(&dir2_path).unwrap();
+        fs::write(&file1_path, "1").unwrap();
+        fs::write(&file2_path, "2").unwrap();
+
+        let pairs = vec![
+            (dir1_path.clone(), path_buf_join(temp_dir.path(), "a.d")),
+            (file1_path.clone(), path_buf_join(&dir1_path, "a.txt")),
+            (dir2_path.clone(), path_buf_join(&dir1_path, "b.d")),
+            (file2_path.clone(), path_buf_join(&dir2_path, "b.txt")),
+        ];
+        let mut rename = BulkRename::new(pairs);
+        rename.execute(RenameOverwriteMode::Error).unwrap();
+
+        let new_dir1_path = path_buf_join(temp_dir.path(), "a.d");
+        let new_dir2_path = path_buf_join(&new_dir1_path, "b.d");
+        assert_eq!(
+            rename.renamed_files().unwrap(),
+            &[
+                new_dir1_path.clone(),
+                path_buf_join(&new_dir1_path, "a.txt"),
+                new_dir2_path.clone(),
+                path_buf_join(&new_dir2_path, "b.txt"),
+            ]
+        );
+        assert!(!dir1_path.exists());
+        assert_eq!(
+            fs::read_to_string(path_buf_join(&new_dir1_path, "a.txt")).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            fs::read_to_string(path_buf_join(&new_dir2_path, "b.txt")).unwrap(),
+            "2"
+        );
+
+        let mut undo = rename.undo_bulk_rename().unwrap();
+        undo.execute(RenameOverwriteMode::Error).unwrap();
+
+        assert!(!new_dir1_path.exists());
+        assert_eq!(fs::read_to_string(&file1_path).unwrap(), "1");
+        assert_eq!(fs::read_to_string(&file2_path).unwrap(), "2");
+    }
+
+    #[test]
+    pub fn test_execute_with_creating_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file1_path = path_buf_join(temp_dir.path(), "1.txt");
+        let file2_path = path_buf_join(temp_dir.path(), "2.txt");
+        fs::write(&file1_path, "1").unwrap();
+        fs::write(&file2_path, "2").unwrap();
+        let dir_path = path_buf_join(temp_dir.path(), "2023");
+        let new_file1_path = path_buf_join(&dir_path, "05/1.txt");
+        let new_file2_path = path_buf_join(&dir_path, "06/2.txt");
+        let pairs = vec![
+            (file1_path.clone(), new_file1_path.clone()),
+            (file2_path.clone(), new_file2_path.clone()),
+        ];
+
+        let mut rename = BulkRename::new(pairs.clone());
+        assert!(matches!(
+            rename.execute(RenameOverwriteMode::Error),
+            Err(RenameError::TargetDirectoryNotFound(_pairs))
+        ));
+        assert!(file1_path.exists());
+        assert!(!dir_path.exists());
+
+        let mut rename = BulkRename::new(vec![(
+            file1_path.clone(),
+            path_buf_join(temp_dir.path(), "a/../../1.txt"),
+        )]);
+        rename.create_directories = true;
+        assert!(matches!(
+            rename.execute(RenameOverwriteMode::Error),
+            Err(RenameError::TargetDirectoryNotFound(_pairs))
+        ));
+
+        let mut rename = BulkRename::new(pairs);
+        rename.create_directories = true;
+        rename.execute(RenameOverwriteMode::Error).unwrap();
+        assert_eq!(fs::read_to_string(&new_file1_path).unwrap(), "1");
+        assert_eq!(fs::read_to_string(&new_file2_path).unwrap(), "2");
+
+        fs::write(path_buf_join(&dir_path, "06/3.txt"), "3").unwrap();
+        let mut undo = rename.undo_bulk_rename().unwrap();
+        undo.execute(RenameOverwriteMode::Error).unwrap();
+        assert_eq!(fs::read_to_string(&file1_path).unwrap(), "1");
+        assert_eq!(fs::read_to_string(&file2_path).unwrap(), "2");
+        assert!(!path_buf_join(&dir_path, "05").exists());
+        assert!(path_buf_join(&dir_path, "06/3.txt").exists());
+    }
+
+    #[test]
+    pub fn test_execute_with_operations() {
+        for &operation in &[
+            RenameOperation::Copy,
+            RenameOperation::Hardlink,
+            RenameOperation::Symlink,
+        ] {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let output_dir = tempfile::tempdir().unwrap();
+            let file1_path = path_buf_join(temp_dir.path(), "1.txt");
+            let file2_path = path_buf_join(temp_dir.path(), "2.txt");
+            fs::write(&file1_path, "1").unwrap();
+            fs::write(&file2_path, "2").unwrap();
+            let pairs = vec![
+                (file1_path.clone(), file2_path.clone()),
+                (
+                    file2_path.clone(),
+                    path_buf_join(temp_dir.path(), "a/3.txt"),
+                ),
+            ];
+
+            let mut rename = BulkRename::new(pairs);
+            rename.operation = operation;
+            rename.create_directories = true;
+            rename.output_directory = Some(output_dir.path().to_path_buf());
+            rename.execute(RenameOverwriteMode::Error).unwrap();
+
+            let new_file2_path = path_buf_join(output_dir.path(), "2.txt");
+            let new_file3_path = path_buf_join(output_dir.path(), "a/3.txt");
+            assert_eq!(fs::read_to_string(&file1_path).unwrap(), "1");
+            assert_eq!(fs::read_to_string(&file2_path).unwrap(), "2");
+            assert_eq!(fs::read_to_string(&new_file2_path).unwrap(), "1");
+            assert_eq!(fs::read_to_string(&new_file3_path).unwrap(), "2");
+            assert_eq!(
+                fs::symlink_metadata(&new_file2_path)
+                    .unwrap()
+                    .file_type()
+                    .is_symlink(),
+                operation == RenameOperation::Symlink
+            );
+
+            let mut undo = rename.undo_bulk_rename().unwrap();
+            undo.execute(RenameOverwriteMode::Error).unwrap();
+            assert_eq!(fs::read_to_string(&file1_path).unwrap(), "1");
+            assert_eq!(fs::read_to_string(&file2_path).unwrap(), "2");
+            assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    pub fn test_execute_nested_with_operations() {
+        for &operation in &[
+            RenameOperation::Copy,
+            RenameOperation::Hardlink,
+            RenameOperation::Symlink,
+        ] {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let dir_path = path_buf_join(temp_dir.path(), "D");
+            let file_path = path_buf_join(temp_dir.path(), "D/c");
+            fs::create_dir(&dir_path).unwrap();
+            fs::write(&file_path, "c").unwrap();
+            let nested_pair = (file_path.clone(), path_buf_join(temp_dir.path(), "D/c2"));
+            let pairs = vec![
+                (dir_path.clone(), path_buf_join(temp_dir.path(), "Dnew")),
+                nested_pair.clone(),
+            ];
+
+            let mut rename = BulkRename::new(pairs);
+            rename.operation = operation;
+            rename.continue_on_error = true;
+            match rename.execute(RenameOverwriteMode::Error) {
+                Err(RenameError::NestedSourceFile(nested_pairs)) => {
+                    assert_eq!(nested_pairs, vec![nested_pair])
+                }
+                result => panic!("NestedSourceFile expected: {:?}", result),
+            }
+            assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+            assert_eq!(fs::read_dir(&dir_path).unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    pub fn test_execute_with_continue_on_error() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file1_path = path_buf_join(temp_dir.path(), "1.txt");
+        let file2_path = path_buf_join(temp_dir.path(), "2.txt");
+        let file3_path = path_buf_join(temp_dir.path(), "3.txt");
+        let file4_path = path_buf_join(temp_dir.path(), "4.txt");
+        let file5_path = path_buf_join(temp_dir.path(), "5.txt");
+        let missing_path = path_buf_join(temp_dir.path(), "missing.txt");
+        for path in &[&file1_path, &file2_path, &file3_path, &file5_path] {
+            fs::write(path, path.file_name().unwrap().to_str().unwrap()).unwrap();
+        }
+        let pairs = vec![
+            (file1_path.clone(), file2_path.clone()),
+            (file3_path.clone(), file4_path.clone()),
+            (missing_path.clone(), file1_path.clone()),
+            (file5_path.clone(), file5_path.clone()),
+        ];
+
+        let mut rename = BulkRename::new(pairs.clone());
+        rename.continue_on_error = true;
+        let results = match rename.execute(RenameOverwriteMode::Error) {
+            Err(RenameError::PartiallyFailed(results)) => results,
+            _ => panic!("PartiallyFailed expected"),
+        };
+        assert_eq!(
+            results.iter().map(|(pair, _)| pair).collect::<Vec<_>>(),
+            pairs.iter().collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            results[0].1,
+            RenamePairResult::Failed(RenameError::TargetFileAlreadyExists(_))
+        ));
+        assert!(matches!(&results[1].1, RenamePairResult::Renamed(path) if path == &file4_path));
+        assert!(matches!(
+            results[2].1,
+            RenamePairResult::Failed(RenameError::SourceFileNotFound(_))
+        ));
+        assert!(matches!(results[3].1, RenamePairResult::Skipped));
+        assert_eq!(fs::read_to_string(&file1_path).unwrap(), "1.txt");
+        assert_eq!(fs::read_to_string(&file2_path).unwrap(), "2.txt");
+        assert_eq!(fs::read_to_string(&file4_path).unwrap(), "3.txt");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 4);
+        assert_eq!(
+            rename.renamed_files().unwrap(),
+            &[
+                file1_path.clone(),
+                file4_path.clone(),
+                missing_path.clone(),
+                file5_path.clone()
+            ]
+        );
+
+        fs::remove_file(&file2_path).unwrap();
+        let mut retry = rename.retry_bulk_rename().unwrap();
+        assert_eq!(
+            retry.pairs,
+            vec![
+                (file1_path.clone(), file2_path.clone()),
+                (missing_path.clone(), file1_path.clone())
+            ]
+        );
+        assert!(retry.execute(RenameOverwriteMode::Error).is_err());
+        assert_eq!(fs::read_to_string(&file2_path).unwrap(), "1.txt");
+
+        let mut undo = rename.undo_bulk_rename().unwrap();
+        assert_eq!(undo.pairs, vec![(file4_path.clone(), file3_path.clone())]);
+        undo.execute(RenameOverwriteMode::Error).unwrap();
+        assert_eq!(fs::read_to_string(&file3_path).unwrap(), "3.txt");
+    }
+
+    #[test]
+    pub fn test_retry_with_output_directory() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let file1_path = path_buf_join(temp_dir.path(), "1.txt");
+        let file2_path = path_buf_join(temp_dir.path(), "2.txt");
+        fs::write(&file2_path, "2").unwrap();
+        let pairs = vec![
+            (
+                file1_path.clone(),
+                path_buf_join(temp_dir.path(), "a/3.txt"),
+            ),
+            (
+                file2_path.clone(),
+                path_buf_join(temp_dir.path(), "b/4.txt"),
+            ),
+        ];
+
+        let mut rename = BulkRename::new(pairs);
+        rename.operation = RenameOperation::Copy;
+        rename.create_directories = true;
+        rename.continue_on_error = true;
+        rename.output_directory = Some(output_dir.path().to_path_buf());
+        assert!(matches!(
+            rename.execute(RenameOverwriteMode::Error),
+            Err(RenameError::PartiallyFailed(_))
+        ));
+
+        fs::write(&file1_path, "1").unwrap();
+        let mut retry = rename.retry_bulk_rename().unwrap();
+        assert_eq!(
+            retry.pairs,
+            vec![(
+                file1_path.clone(),
+                path_buf_join(temp_dir.path(), "a/3.txt")
+            )]
+        );
+        assert_eq!(retry.output_directory, rename.output_directory);
+        retry.execute(RenameOverwriteMode::Error).unwrap();
+        assert_eq!(
+            fs::read_to_string(path_buf_join(output_dir.path(), "a/3.txt")).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            fs::read_to_string(path_buf_join(output_dir.path(), "b/4.txt")).unwrap(),
+            "2"
+        );
+
+        // The undo of the retry also cleans up the directories created by the first run.
+        let mut undo_retry = retry.undo_bulk_rename().unwrap();
+        assert_eq!(
+            undo_retry.cleanup_directories,
+            vec![
+                path_buf_join(output_dir.path(), "b"),
+                path_buf_join(output_dir.path(), "a")
+            ]
+        );
+        undo_retry.execute(RenameOverwriteMode::Error).unwrap();
+        assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 1);
+        rename
+            .undo_bulk_rename()
+            .unwrap()
+            .execute(RenameOverwriteMode::Error)
+            .unwrap();
+        assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    pub fn test_execute_with_progress() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pairs = (1..=3)
+            .map(|i| {
+                let source = path_buf_join(temp_dir.path(), format!("{}.txt", i).as_str());
+                let target = path_buf_join(temp_dir.path(), format!("{}.dat", i).as_str());
+                fs::write(&source, i.to_string()).unwrap();
+                (source, target)
+            })
+            .collect::<Vec<_>>();
+
+        let mut rename = BulkRename::new(pairs.clone());
+        let mut reports = Vec::new();
+        rename
+            .execute_with_progress(RenameOverwriteMode::Error, &mut |progress| {
+                reports.push((progress.phase, progress.done, progress.total));
+                true
+            })
+            .unwrap();
+        assert_eq!(
+            reports,
+            vec![
+                (RenamePhase::Prepare, 0, 3),
+                (RenamePhase::Prepare, 1, 3),
+                (RenamePhase::Prepare, 2, 3),
+                (RenamePhase::Rename, 0, 3),
+                (RenamePhase::Rename, 1, 3),
+                (RenamePhase::Rename, 2, 3),
+            ]
+        );
+        for (_, target) in pairs.iter() {
+            assert!(target.exists());
+        }
+        rename
+            .undo_bulk_rename()
+            .unwrap()
+            .execute(RenameOverwriteMode::Error)
+            .unwrap();
+
+        let mut rename = BulkRename::new(pairs.clone());
+        let result = rename.execute_with_progress(RenameOverwriteMode::Error, &mut |progress| {
+            progress.phase == RenamePhase::Prepare || progress.done < 1
+        });
+        assert!(matches!(result, Err(RenameError::Cancelled)));
+        rename
+            .undo_bulk_rename()
+            .unwrap()
+            .execute(RenameOverwriteMode::Error)
+            .unwrap();
+        for (source, target) in pairs.iter() {
+            assert!(source.exists());
+            assert!(!target.exists());
+        }
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    pub fn test_copy_recursively() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir1_path = path_buf_join(temp_dir.path(), "1.d");
+        fs::create_dir_all(path_buf_join(&dir1_path, "sub")).unwrap();
+        fs::write(path_buf_join(&dir1_path, "sub/1.txt"), "1").unwrap();
+        symlink("sub/1.txt", path_buf_join(&dir1_path, "link")).unwrap();
+
+        let dir2_path = path_buf_join(temp_dir.path(), "2.d");
+        BulkRename::copy_recursively(&dir1_path, &dir2_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(path_buf_join(&dir2_path, "sub/1.txt")).unwrap(),
+            "1"
+        );
+        assert_eq!(
+            fs::read_link(path_buf_join(&dir2_path, "link")).unwrap(),
+            PathBuf::from("sub/1.txt")
+        );
+    }
 }
 
 /// Rename processing error
@@ -334,6 +1349,20 @@ pub enum RenameError {
         .collect::<Vec<_>>()
         .join(", "))]
     SourceFileNotFound(Vec<RenameMapPair>),
+    /// Some target directories was not found.
+    #[error("Target Directory Not Found: {}", .0
+        .iter()
+        .map(|(_, target)| target.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", "))]
+    TargetDirectoryNotFound(Vec<RenameMapPair>),
+    /// Some source files are located under another source, which is supported only on moving.
+    #[error("Nested Source Not Supported: {}", .0
+        .iter()
+        .map(|(source, _)| source.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", "))]
+    NestedSourceFile(Vec<RenameMapPair>),
     /// Target files is already available.
     #[error("Target File Already Exists: {}", (.0).1.display().to_string())]
     TargetFileAlreadyExists(RenameMapPair),
@@ -343,6 +1372,15 @@ pub enum RenameError {
     /// General IO Error
     #[error("IO Error: {} -> {}", (.0).0.display().to_string(), (.0).1.display().to_string())]
     IoError(RenameMapPair, #[source] IoError),
+    /// Some files failed to be renamed; the rest of the files are renamed.
+    #[error("Failed to rename {} of {} files", .0
+        .iter()
+        .filter(|(_, result)| matches!(result, RenamePairResult::Failed(_)))
+        .count(), .0.len())]
+    PartiallyFailed(Vec<(RenameMapPair, RenamePairResult)>),
+    /// Renaming is cancelled.
+    #[error("Cancelled")]
+    Cancelled,
     /// General Operation Error
     #[error("Illegal Format")]
     IllegalOperation,
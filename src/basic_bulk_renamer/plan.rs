@@ -0,0 +1,403 @@
+use super::{BulkRename, RenameMapPair, RenameOperation, RenameState};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use strum_macros::EnumIter;
+
+/// Format of the exported rename plan
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumIter)]
+pub enum PlanFormat {
+    /// POSIX shell script of the commands of the operation, e.g. `mv -n`
+    Shell,
+    /// CSV of the source and the target paths
+    Csv,
+    /// JSON array of the source and the target paths
+    Json,
+}
+
+impl PlanFormat {
+    /// File extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlanFormat::Shell => "sh",
+            PlanFormat::Csv => "csv",
+            PlanFormat::Json => "json",
+        }
+    }
+
+    /// Returns the format of the file extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension().and_then(OsStr::to_str)?.to_lowercase();
+        match extension.as_str() {
+            "sh" => Some(PlanFormat::Shell),
+            "csv" => Some(PlanFormat::Csv),
+            "json" => Some(PlanFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Export the rename plan of `renamer` in `format`.
+///
+/// The targets are located in the output directory of `renamer` if it is set.
+/// The pairs whose source and target are the same are omitted.
+pub fn export_plan(renamer: &BulkRename, format: PlanFormat) -> String {
+    let pairs = renamer
+        .pairs
+        .iter()
+        .map(|pair| (pair.0.clone(), renamer.target_of(pair)))
+        .filter(|(source, target)| source != target)
+        .collect::<Vec<_>>();
+    match format {
+        PlanFormat::Shell => export_shell(&pairs, renamer.operation, renamer.create_directories),
+        PlanFormat::Csv => export_csv(&pairs),
+        PlanFormat::Json => export_json(&pairs),
+    }
+}
+
+/// Export as a shell script in the same way as `BulkRename::execute`.
+///
+/// The missing directories are created first if `create_directories` is `true`.
+/// With `RenameOperation::Move`, the files are moved the deepest first. The files of which
+/// the target is another source, e.g. swapping or shifting names, are moved through the
+/// temporary names. With the other operations, the files are copied or linked the shallowest first.
+fn export_shell(
+    pairs: &[RenameMapPair],
+    operation: RenameOperation,
+    create_directories: bool,
+) -> String {
+    let mut lines = vec![
+        "#!/bin/sh".to_string(),
+        "# Rename plan exported by gtk3-basic-bulk-renamer".to_string(),
+        "set -e".to_string(),
+    ];
+    if create_directories {
+        let directories = pairs
+            .iter()
+            .filter_map(|(source, target)| target.parent().filter(|&v| Some(v) != source.parent()))
+            .collect::<BTreeSet<_>>();
+        lines.extend(directories.into_iter().map(|directory| {
+            format!(
+                "mkdir -p -- {}",
+                shell_quote(&directory.display().to_string())
+            )
+        }));
+    }
+
+    let mut order = (0..pairs.len()).collect::<Vec<_>>();
+    if operation == RenameOperation::Move {
+        let sources = pairs
+            .iter()
+            .map(|(source, _)| source)
+            .collect::<HashSet<_>>();
+        let targets = pairs
+            .iter()
+            .map(|(_, target)| target)
+            .collect::<HashSet<_>>();
+
+        // Current location and target of each file, relocated as their ancestors move
+        let (mut locations, mut moved_targets): (Vec<_>, Vec<_>) = pairs.iter().cloned().unzip();
+
+        order.sort_by_key(|&i| Reverse(pairs[i].0.components().count()));
+        let mut temp_indices = Vec::new();
+        for &i in order.iter() {
+            let (source, target) = &pairs[i];
+            let to = if sources.contains(target) || targets.contains(source) {
+                temp_indices.push(i);
+                temp_file_of(&locations[i], i)
+            } else {
+                moved_targets[i].clone()
+            };
+            lines.push(shell_mv(i, to, &mut locations, &mut moved_targets));
+        }
+        for &i in temp_indices.iter().rev() {
+            let to = moved_targets[i].clone();
+            lines.push(shell_mv(i, to, &mut locations, &mut moved_targets));
+        }
+    } else {
+        order.sort_by_key(|&i| pairs[i].0.components().count());
+        for &i in order.iter() {
+            let (source, target) = &pairs[i];
+            lines.push(shell_command(operation, source, target));
+        }
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Command to move the `index`-th file to `to`, in the same way as `RenameState::moved`.
+///
+/// The locations and the targets under the moved file are relocated into `to`.
+fn shell_mv(
+    index: usize,
+    to: PathBuf,
+    locations: &mut [PathBuf],
+    targets: &mut [PathBuf],
+) -> String {
+    let from = std::mem::replace(&mut locations[index], to.clone());
+    for path in locations.iter_mut().chain(targets.iter_mut()) {
+        RenameState::relocate_descendant(path, &from, &to);
+    }
+    shell_command(RenameOperation::Move, &from, &to)
+}
+
+/// Temporary name of `source` for the `index`-th pair
+fn temp_file_of(source: &Path, index: usize) -> PathBuf {
+    let mut file_name = source.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".rename-{}.tmp", index));
+    source.with_file_name(file_name)
+}
+
+/// Command to create `target` from `source` by `operation`
+fn shell_command(operation: RenameOperation, source: &Path, target: &Path) -> String {
+    let command = match operation {
+        RenameOperation::Move => "mv -n",
+        RenameOperation::Copy => "cp -R -n",
+        RenameOperation::Hardlink => "ln",
+        RenameOperation::Symlink => "ln -s",
+    };
+    format!(
+        "{} -- {} {}",
+        command,
+        shell_quote(&source.display().to_string()),
+        shell_quote(&target.display().to_string())
+    )
+}
+
+/// Quote the text with single quotes for POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn export_csv(pairs: &[RenameMapPair]) -> String {
+    let mut lines = vec!["source,target".to_string()];
+    lines.extend(pairs.iter().map(|(source, target)| {
+        format!(
+            "{},{}",
+            csv_quote(&source.display().to_string()),
+            csv_quote(&target.display().to_string())
+        )
+    }));
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+/// Quote the field of CSV if needed.
+fn csv_quote(text: &str) -> String {
+    if text.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn export_json(pairs: &[RenameMapPair]) -> String {
+    let items = pairs
+        .iter()
+        .map(|(source, target)| {
+            format!(
+                "  {{\"source\": {}, \"target\": {}}}",
+                json_quote(&source.display().to_string()),
+                json_quote(&target.display().to_string())
+            )
+        })
+        .collect::<Vec<_>>();
+    if items.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[\n{}\n]\n", items.join(",\n"))
+    }
+}
+
+/// Quote the text as a JSON string.
+fn json_quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pair(source: &str, target: &str) -> RenameMapPair {
+        (PathBuf::from(source), PathBuf::from(target))
+    }
+
+    fn renamer(pairs: &[RenameMapPair]) -> BulkRename {
+        BulkRename::new(pairs.to_vec())
+    }
+
+    #[test]
+    fn test_plan_format_from_path() {
+        assert_eq!(
+            PlanFormat::from_path(Path::new("plan.sh")),
+            Some(PlanFormat::Shell)
+        );
+        assert_eq!(
+            PlanFormat::from_path(Path::new("plan.CSV")),
+            Some(PlanFormat::Csv)
+        );
+        assert_eq!(
+            PlanFormat::from_path(Path::new("plan.json")),
+            Some(PlanFormat::Json)
+        );
+        assert_eq!(PlanFormat::from_path(Path::new("plan.txt")), None);
+        assert_eq!(PlanFormat::from_path(Path::new("plan")), None);
+    }
+
+    #[test]
+    fn test_export_shell() {
+        assert_eq!(
+            export_plan(
+                &renamer(&[
+                    pair("/tmp/a.txt", "/tmp/b.txt"),
+                    pair("/tmp/b.txt", "/tmp/a.txt"),
+                    pair("/tmp/it's.txt", "/tmp/it is.txt"),
+                    pair("/tmp/dir/c.txt", "/tmp/dir/d.txt"),
+                    pair("/tmp/e.txt", "/tmp/e.txt"),
+                ]),
+                PlanFormat::Shell
+            ),
+            [
+                "#!/bin/sh",
+                "# Rename plan exported by gtk3-basic-bulk-renamer",
+                "set -e",
+                "mv -n -- '/tmp/dir/c.txt' '/tmp/dir/d.txt'",
+                "mv -n -- '/tmp/a.txt' '/tmp/a.txt.rename-0.tmp'",
+                "mv -n -- '/tmp/b.txt' '/tmp/b.txt.rename-1.tmp'",
+                "mv -n -- '/tmp/it'\\''s.txt' '/tmp/it is.txt'",
+                "mv -n -- '/tmp/b.txt.rename-1.tmp' '/tmp/a.txt'",
+                "mv -n -- '/tmp/a.txt.rename-0.tmp' '/tmp/b.txt'",
+                "",
+            ]
+            .join("\n")
+        );
+
+        // The files under a renamed directory follow the directory.
+        assert_eq!(
+            export_plan(
+                &renamer(&[
+                    pair("/tmp/D", "/tmp/E"),
+                    pair("/tmp/D/x", "/tmp/D/y"),
+                    pair("/tmp/D/y", "/tmp/D/x"),
+                ]),
+                PlanFormat::Shell
+            ),
+            [
+                "#!/bin/sh",
+                "# Rename plan exported by gtk3-basic-bulk-renamer",
+                "set -e",
+                "mv -n -- '/tmp/D/x' '/tmp/D/x.rename-1.tmp'",
+                "mv -n -- '/tmp/D/y' '/tmp/D/y.rename-2.tmp'",
+                "mv -n -- '/tmp/D' '/tmp/E'",
+                "mv -n -- '/tmp/E/y.rename-2.tmp' '/tmp/E/x'",
+                "mv -n -- '/tmp/E/x.rename-1.tmp' '/tmp/E/y'",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_export_shell_with_operations() {
+        let mut copy_renamer = renamer(&[
+            pair("/tmp/a.txt", "/tmp/sub/b.txt"),
+            pair("/tmp/dir/c.txt", "/tmp/dir/d.txt"),
+            pair("/tmp/e.txt", "/tmp/e.txt"),
+        ]);
+        copy_renamer.operation = RenameOperation::Copy;
+        copy_renamer.create_directories = true;
+        copy_renamer.output_directory = Some(PathBuf::from("/out"));
+        assert_eq!(
+            export_plan(&copy_renamer, PlanFormat::Shell),
+            [
+                "#!/bin/sh",
+                "# Rename plan exported by gtk3-basic-bulk-renamer",
+                "set -e",
+                "mkdir -p -- '/out'",
+                "mkdir -p -- '/out/sub'",
+                "cp -R -n -- '/tmp/a.txt' '/out/sub/b.txt'",
+                "cp -R -n -- '/tmp/e.txt' '/out/e.txt'",
+                "cp -R -n -- '/tmp/dir/c.txt' '/out/d.txt'",
+                "",
+            ]
+            .join("\n")
+        );
+
+        for &(operation, command) in &[
+            (RenameOperation::Hardlink, "ln"),
+            (RenameOperation::Symlink, "ln -s"),
+        ] {
+            let mut link_renamer = renamer(&[pair("/tmp/a.txt", "/tmp/b.txt")]);
+            link_renamer.operation = operation;
+            assert_eq!(
+                export_plan(&link_renamer, PlanFormat::Shell),
+                [
+                    "#!/bin/sh",
+                    "# Rename plan exported by gtk3-basic-bulk-renamer",
+                    "set -e",
+                    format!("{} -- '/tmp/a.txt' '/tmp/b.txt'", command).as_str(),
+                    "",
+                ]
+                .join("\n")
+            );
+        }
+    }
+
+    #[test]
+    fn test_export_csv() {
+        assert_eq!(
+            export_plan(
+                &renamer(&[
+                    pair("/tmp/a.txt", "/tmp/b.txt"),
+                    pair("/tmp/a,b.txt", "/tmp/\"c\".txt"),
+                ]),
+                PlanFormat::Csv
+            ),
+            [
+                "source,target",
+                "/tmp/a.txt,/tmp/b.txt",
+                "\"/tmp/a,b.txt\",\"/tmp/\"\"c\"\".txt\"",
+                "",
+            ]
+            .join("\r\n")
+        );
+    }
+
+    #[test]
+    fn test_export_json() {
+        assert_eq!(export_plan(&renamer(&[]), PlanFormat::Json), "[]\n");
+        assert_eq!(
+            export_plan(
+                &renamer(&[
+                    pair("/tmp/a.txt", "/tmp/b.txt"),
+                    pair("/tmp/\"c\"\\.txt", "/tmp/d\t.txt"),
+                ]),
+                PlanFormat::Json
+            ),
+            [
+                "[",
+                "  {\"source\": \"/tmp/a.txt\", \"target\": \"/tmp/b.txt\"},",
+                "  {\"source\": \"/tmp/\\\"c\\\"\\\\.txt\", \"target\": \"/tmp/d\\t.txt\"}",
+                "]",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::basic_bulk_renamer::RenameError;
 use regex::Error as RegexError;
+use std::io::Error as IoError;
 use thiserror;
 
 #[derive(thiserror::Error, Debug)]
@@ -8,4 +9,8 @@ pub(crate) enum Error {
     Rename(#[from] RenameError),
     #[error(transparent)]
     Regex(#[from] RegexError),
+    #[error(transparent)]
+    Io(#[from] IoError),
+    #[error("Invalid time shift: {0}")]
+    InvalidTimeShift(String),
 }
@@ -0,0 +1,558 @@
+use crate::utils::find_box;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use strum_macros::{EnumString, IntoStaticStr};
+
+/// Block type of the Vorbis comment in FLAC
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+/// Maximum size of the Vorbis comment read from the Ogg file
+const MAX_OGG_COMMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Field of the audio tag, named after the Vorbis comment
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum AudioTagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    TrackNumber,
+    TrackTotal,
+    DiscNumber,
+    DiscTotal,
+    Date,
+    Year,
+    Genre,
+    Composer,
+}
+
+/// Tags of the audio file
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct AudioTags(HashMap<AudioTagField, String>);
+
+impl AudioTags {
+    pub fn get(&self, field: AudioTagField) -> Option<&str> {
+        self.0.get(&field).map(|v| v.as_str())
+    }
+
+    /// Set the value unless it is empty or the field is already set.
+    fn set(&mut self, field: AudioTagField, value: &str) {
+        let value = value.trim_end_matches('\0').trim();
+        if value.is_empty() || self.0.contains_key(&field) {
+            return;
+        }
+        if field == AudioTagField::Date && value.chars().take(4).all(|c| c.is_ascii_digit()) {
+            if let Some(year) = value.get(..4) {
+                self.set(AudioTagField::Year, year);
+            }
+        }
+        self.0.insert(field, value.to_string());
+    }
+
+    /// Set the number such as "3" or "3/12" with its total.
+    fn set_number(&mut self, field: AudioTagField, total_field: AudioTagField, value: &str) {
+        match value.split_once('/') {
+            Some((number, total)) => {
+                self.set(field, number);
+                self.set(total_field, total);
+            }
+            None => self.set(field, value),
+        }
+    }
+
+    /// Set the field named by the Vorbis comment such as "TRACKNUMBER".
+    fn set_vorbis_comment(&mut self, name: &str, value: &str) {
+        match name.to_uppercase().as_str() {
+            "TITLE" => self.set(AudioTagField::Title, value),
+            "ARTIST" => self.set(AudioTagField::Artist, value),
+            "ALBUM" => self.set(AudioTagField::Album, value),
+            "ALBUMARTIST" | "ALBUM ARTIST" => self.set(AudioTagField::AlbumArtist, value),
+            "TRACKNUMBER" => {
+                self.set_number(AudioTagField::TrackNumber, AudioTagField::TrackTotal, value)
+            }
+            "TRACKTOTAL" | "TOTALTRACKS" => self.set(AudioTagField::TrackTotal, value),
+            "DISCNUMBER" => {
+                self.set_number(AudioTagField::DiscNumber, AudioTagField::DiscTotal, value)
+            }
+            "DISCTOTAL" | "TOTALDISCS" => self.set(AudioTagField::DiscTotal, value),
+            "DATE" => self.set(AudioTagField::Date, value),
+            "GENRE" => self.set(AudioTagField::Genre, value),
+            "COMPOSER" => self.set(AudioTagField::Composer, value),
+            _ => {}
+        }
+    }
+}
+
+/// Read the tags of the MP3 (ID3v2/ID3v1), FLAC, Ogg Vorbis/Opus or MP4/M4A file
+pub(crate) fn read_audio_tags<R: Read + Seek>(reader: &mut R) -> Result<Option<AudioTags>> {
+    let mut magic = [0u8; 8];
+    let magic_size = reader.read(&mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let tags = match &magic[..magic_size] {
+        [b'f', b'L', b'a', b'C', ..] => read_flac_tags(reader)?,
+        [b'O', b'g', b'g', b'S', ..] => read_ogg_tags(reader)?,
+        [_, _, _, _, b'f', b't', b'y', b'p'] => read_mp4_tags(reader)?,
+        _ => read_id3_tags(reader)?,
+    };
+    Ok(tags.filter(|tags| !tags.0.is_empty()))
+}
+
+fn read_id3_tags<R: Read + Seek>(reader: &mut R) -> Result<Option<AudioTags>> {
+    use id3::TagLike;
+
+    let tag = match id3::v1v2::read_from(reader) {
+        Ok(tag) => tag,
+        Err(id3::Error {
+            kind: id3::ErrorKind::Io(e),
+            ..
+        }) => return Err(e),
+        Err(_) => return Ok(None),
+    };
+
+    let mut tags = AudioTags::default();
+    let number = |number: Option<u32>| number.map(|v| v.to_string()).unwrap_or_default();
+    tags.set(AudioTagField::Title, tag.title().unwrap_or_default());
+    tags.set(AudioTagField::Artist, tag.artist().unwrap_or_default());
+    tags.set(AudioTagField::Album, tag.album().unwrap_or_default());
+    tags.set(
+        AudioTagField::AlbumArtist,
+        tag.album_artist().unwrap_or_default(),
+    );
+    tags.set(AudioTagField::TrackNumber, &number(tag.track()));
+    tags.set(AudioTagField::TrackTotal, &number(tag.total_tracks()));
+    tags.set(AudioTagField::DiscNumber, &number(tag.disc()));
+    tags.set(AudioTagField::DiscTotal, &number(tag.total_discs()));
+    if let Some(date) = tag.date_recorded() {
+        tags.set(AudioTagField::Date, &date.to_string());
+    }
+    let year = tag.year().map(|v| v.to_string()).unwrap_or_default();
+    tags.set(AudioTagField::Year, &year);
+    tags.set(AudioTagField::Date, &year);
+    tags.set(
+        AudioTagField::Genre,
+        tag.genre_parsed().as_deref().unwrap_or_default(),
+    );
+    tags.set(
+        AudioTagField::Composer,
+        tag.text_for_frame_id("TCOM").unwrap_or_default(),
+    );
+    Ok(Some(tags))
+}
+
+/// Read the `VORBIS_COMMENT` metadata block of the FLAC file
+fn read_flac_tags<R: Read + Seek>(reader: &mut R) -> Result<Option<AudioTags>> {
+    let mut signature = [0u8; 4];
+    reader.read_exact(&mut signature)?;
+    if &signature != b"fLaC" {
+        return Ok(None);
+    }
+
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        if header[0] & 0x7f == FLAC_VORBIS_COMMENT {
+            let mut data = vec![0u8; length as usize];
+            reader.read_exact(&mut data)?;
+            return Ok(parse_vorbis_comment(&data));
+        } else if is_last {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Current(i64::from(length)))?;
+    }
+}
+
+/// Read the comment header of the first logical stream of the Ogg Vorbis/Opus file
+fn read_ogg_tags<R: Read + Seek>(reader: &mut R) -> Result<Option<AudioTags>> {
+    let mut packets = vec![Vec::new()];
+    let mut serial = None;
+
+    // the comment header is the second packet, which may span pages
+    while packets.len() < 3 {
+        let mut header = [0u8; 27];
+        match reader.read_exact(&mut header) {
+            Ok(()) if &header[..4] == b"OggS" => {}
+            Ok(()) => return Ok(None),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let mut segment_table = vec![0u8; usize::from(header[26])];
+        reader.read_exact(&mut segment_table)?;
+        let page_size = segment_table.iter().map(|v| i64::from(*v)).sum::<i64>();
+
+        let page_serial = [header[14], header[15], header[16], header[17]];
+        if *serial.get_or_insert(page_serial) != page_serial {
+            reader.seek(SeekFrom::Current(page_size))?;
+            continue;
+        }
+
+        for lacing in segment_table {
+            let mut segment = vec![0u8; usize::from(lacing)];
+            reader.read_exact(&mut segment)?;
+            let packet = packets.last_mut().unwrap();
+            packet.extend(segment);
+            if packet.len() > MAX_OGG_COMMENT_SIZE {
+                return Err(Error::new(ErrorKind::InvalidData, "too large Ogg packet"));
+            }
+            if lacing < 255 {
+                packets.push(Vec::new());
+            }
+        }
+    }
+
+    let comment = match (packets[0].as_slice(), packets[1].as_slice()) {
+        (
+            [0x01, b'v', b'o', b'r', b'b', b'i', b's', ..],
+            [0x03, b'v', b'o', b'r', b'b', b'i', b's', comment @ ..],
+        ) => comment,
+        (
+            [b'O', b'p', b'u', b's', b'H', b'e', b'a', b'd', ..],
+            [b'O', b'p', b'u', b's', b'T', b'a', b'g', b's', comment @ ..],
+        ) => comment,
+        _ => return Ok(None),
+    };
+    Ok(parse_vorbis_comment(comment))
+}
+
+/// Parse the Vorbis comment: the vendor string followed by the "NAME=value" comments
+fn parse_vorbis_comment(data: &[u8]) -> Option<AudioTags> {
+    let mut rest = data;
+    let read_u32 = |rest: &mut &[u8]| -> Option<usize> {
+        let (value, remaining) = (rest.get(..4)?, rest.get(4..)?);
+        *rest = remaining;
+        usize::try_from(u32::from_le_bytes([value[0], value[1], value[2], value[3]])).ok()
+    };
+
+    let vendor_length = read_u32(&mut rest)?;
+    rest = rest.get(vendor_length..)?;
+    let count = read_u32(&mut rest)?;
+
+    let mut tags = AudioTags::default();
+    for _ in 0..count {
+        let length = read_u32(&mut rest)?;
+        let comment = String::from_utf8_lossy(rest.get(..length)?);
+        if let Some((name, value)) = comment.split_once('=') {
+            tags.set_vorbis_comment(name, value);
+        }
+        rest = &rest[length..];
+    }
+    Some(tags)
+}
+
+/// Read the iTunes-style metadata items (`moov/udta/meta/ilst`) of the MP4/M4A file
+fn read_mp4_tags<R: Read + Seek>(reader: &mut R) -> Result<Option<AudioTags>> {
+    let moov_size = match find_box(reader, None, b"moov")? {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    let udta_size = match find_box(reader, moov_size, b"udta")? {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    let meta_size = match find_box(reader, udta_size, b"meta")? {
+        Some(Some(size)) if size >= 4 => size,
+        _ => return Ok(None),
+    };
+    // skip the version and flags of the full box
+    reader.seek(SeekFrom::Current(4))?;
+    let mut ilst_size = match find_box(reader, Some(meta_size - 4), b"ilst")? {
+        Some(Some(size)) => size,
+        _ => return Ok(None),
+    };
+
+    let mut tags = AudioTags::default();
+    while ilst_size >= 8 {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let size = u64::from(u32::from_be_bytes([
+            header[0], header[1], header[2], header[3],
+        ]));
+        if size < 8 || size > ilst_size {
+            break;
+        }
+        ilst_size -= size;
+        let payload_size = size - 8;
+
+        let field = match &header[4..] {
+            b"\xa9nam" => Some(AudioTagField::Title),
+            b"\xa9ART" => Some(AudioTagField::Artist),
+            b"\xa9alb" => Some(AudioTagField::Album),
+            b"aART" => Some(AudioTagField::AlbumArtist),
+            b"trkn" => Some(AudioTagField::TrackNumber),
+            b"disk" => Some(AudioTagField::DiscNumber),
+            b"\xa9day" => Some(AudioTagField::Date),
+            b"\xa9gen" => Some(AudioTagField::Genre),
+            b"\xa9wrt" => Some(AudioTagField::Composer),
+            _ => None,
+        };
+        let field = match field {
+            // the cover art and the others are skipped
+            Some(field) if payload_size <= 64 * 1024 => field,
+            _ => {
+                reader.seek(SeekFrom::Current(
+                    i64::try_from(payload_size).unwrap_or(i64::MAX),
+                ))?;
+                continue;
+            }
+        };
+
+        let mut payload = vec![0u8; payload_size as usize];
+        reader.read_exact(&mut payload)?;
+        // "data" box: size, type, data type, locale and the value
+        let value = match payload.get(..16) {
+            Some(data_header) if &data_header[4..8] == b"data" => &payload[16..],
+            _ => continue,
+        };
+        match field {
+            AudioTagField::TrackNumber | AudioTagField::DiscNumber if value.len() >= 6 => {
+                let number = u16::from_be_bytes([value[2], value[3]]);
+                let total = u16::from_be_bytes([value[4], value[5]]);
+                let total_field = if field == AudioTagField::TrackNumber {
+                    AudioTagField::TrackTotal
+                } else {
+                    AudioTagField::DiscTotal
+                };
+                if number > 0 {
+                    tags.set(field, &number.to_string());
+                }
+                if total > 0 {
+                    tags.set(total_field, &total.to_string());
+                }
+            }
+            AudioTagField::TrackNumber | AudioTagField::DiscNumber => {}
+            field => tags.set(field, &String::from_utf8_lossy(value)),
+        }
+    }
+    Ok(Some(tags))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&6u32.to_le_bytes());
+        data.extend_from_slice(b"vendor");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    /// Ogg page of the packets. The last packet continues on the next page if `continued`.
+    fn ogg_page(serial: u8, sequence: u8, packets: &[&[u8]], continued: bool) -> Vec<u8> {
+        let mut segment_table = Vec::new();
+        let mut body = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            for chunk in packet.chunks(255) {
+                segment_table.push(chunk.len() as u8);
+            }
+            if packet.len() % 255 == 0 && !(continued && i == packets.len() - 1) {
+                segment_table.push(0);
+            }
+            body.extend_from_slice(packet);
+        }
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&[0; 8]);
+        page.extend_from_slice(&[serial, 0, 0, 0, sequence, 0, 0, 0, 0, 0, 0, 0]);
+        page.push(segment_table.len() as u8);
+        page.extend(segment_table);
+        page.extend(body);
+        page
+    }
+
+    fn tags(values: &[(AudioTagField, &str)]) -> Option<AudioTags> {
+        Some(AudioTags(
+            values
+                .iter()
+                .map(|&(field, value)| (field, value.to_string()))
+                .collect(),
+        ))
+    }
+
+    #[test]
+    fn test_read_flac_tags() {
+        let mut data = b"fLaC".to_vec();
+        data.extend_from_slice(&[0x00, 0, 0, 34]);
+        data.extend_from_slice(&[0; 34]);
+        let comment = vorbis_comment(&[
+            "TITLE=Song",
+            "artist=Band",
+            "TRACKNUMBER=3/12",
+            "DATE=2021-03-04",
+            "COMMENT=ignored",
+        ]);
+        data.extend_from_slice(&[0x80 | FLAC_VORBIS_COMMENT, 0, 0, comment.len() as u8]);
+        data.extend(comment);
+        data.extend_from_slice(&[0xff, 0xf8]);
+
+        assert_eq!(
+            read_audio_tags(&mut Cursor::new(data)).unwrap(),
+            tags(&[
+                (AudioTagField::Title, "Song"),
+                (AudioTagField::Artist, "Band"),
+                (AudioTagField::TrackNumber, "3"),
+                (AudioTagField::TrackTotal, "12"),
+                (AudioTagField::Date, "2021-03-04"),
+                (AudioTagField::Year, "2021"),
+            ])
+        );
+
+        let mut data = b"fLaC".to_vec();
+        data.extend_from_slice(&[0x80, 0, 0, 34]);
+        data.extend_from_slice(&[0; 34]);
+        assert_eq!(read_audio_tags(&mut Cursor::new(data)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_ogg_tags() {
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend(vorbis_comment(&[
+            "TITLE=Song",
+            "ALBUMARTIST=Various",
+            "DISCNUMBER=2",
+            &format!("DESCRIPTION={}", "x".repeat(300)),
+        ]));
+        comment.push(1);
+        let (first, second) = comment.split_at(255);
+        let mut data = ogg_page(1, 0, &[b"\x01vorbis\0\0\0\0"], false);
+        data.extend(ogg_page(2, 0, &[b"OpusHead"], false));
+        data.extend(ogg_page(1, 1, &[first], true));
+        data.extend(ogg_page(1, 2, &[second, b"\x05vorbis"], false));
+
+        assert_eq!(
+            read_audio_tags(&mut Cursor::new(data)).unwrap(),
+            tags(&[
+                (AudioTagField::Title, "Song"),
+                (AudioTagField::AlbumArtist, "Various"),
+                (AudioTagField::DiscNumber, "2"),
+            ])
+        );
+
+        let mut comment = b"OpusTags".to_vec();
+        comment.extend(vorbis_comment(&["GENRE=Jazz"]));
+        let mut data = ogg_page(1, 0, &[b"OpusHead\x01\x02"], false);
+        data.extend(ogg_page(1, 1, &[&comment], false));
+        assert_eq!(
+            read_audio_tags(&mut Cursor::new(data)).unwrap(),
+            tags(&[(AudioTagField::Genre, "Jazz")])
+        );
+
+        let data = ogg_page(1, 0, &[b"OpusHead\x01\x02"], false);
+        assert_eq!(read_audio_tags(&mut Cursor::new(data)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_mp4_tags() {
+        let item = |item_type: &[u8; 4], value: &[u8]| {
+            let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+            data.extend_from_slice(value);
+            mp4_box(item_type, &mp4_box(b"data", &data))
+        };
+        let mut ilst = item(b"\xa9nam", b"Song");
+        ilst.extend(item(b"\xa9ART", b"Band"));
+        ilst.extend(item(b"covr", &[0xff; 32]));
+        ilst.extend(item(b"trkn", &[0, 0, 0, 3, 0, 12, 0, 0]));
+        ilst.extend(item(b"disk", &[0, 0, 0, 1, 0, 0]));
+        ilst.extend(item(b"\xa9day", b"2021"));
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(mp4_box(b"hdlr", &[0; 25]));
+        meta.extend(mp4_box(b"ilst", &ilst));
+        let mut data = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+        data.extend(mp4_box(
+            b"moov",
+            &[
+                mp4_box(b"mvhd", &[0; 100]),
+                mp4_box(b"udta", &mp4_box(b"meta", &meta)),
+            ]
+            .concat(),
+        ));
+        data.extend(mp4_box(b"mdat", &[0; 32]));
+
+        assert_eq!(
+            read_audio_tags(&mut Cursor::new(data)).unwrap(),
+            tags(&[
+                (AudioTagField::Title, "Song"),
+                (AudioTagField::Artist, "Band"),
+                (AudioTagField::TrackNumber, "3"),
+                (AudioTagField::TrackTotal, "12"),
+                (AudioTagField::DiscNumber, "1"),
+                (AudioTagField::Date, "2021"),
+                (AudioTagField::Year, "2021"),
+            ])
+        );
+
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &[0; 100])));
+        assert_eq!(read_audio_tags(&mut Cursor::new(data)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_id3_tags() {
+        use id3::TagLike;
+
+        let mut tag = id3::Tag::new();
+        tag.set_title("Song");
+        tag.set_artist("Band");
+        tag.set_track(3);
+        tag.set_total_tracks(12);
+        tag.set_year(2021);
+        tag.set_genre("(8)");
+        let mut data = Vec::new();
+        tag.write_to(&mut data, id3::Version::Id3v24).unwrap();
+        data.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+
+        assert_eq!(
+            read_audio_tags(&mut Cursor::new(data)).unwrap(),
+            tags(&[
+                (AudioTagField::Title, "Song"),
+                (AudioTagField::Artist, "Band"),
+                (AudioTagField::TrackNumber, "3"),
+                (AudioTagField::TrackTotal, "12"),
+                (AudioTagField::Date, "2021"),
+                (AudioTagField::Year, "2021"),
+                (AudioTagField::Genre, "Jazz"),
+            ])
+        );
+
+        // ID3v1 at the end of the file
+        let mut data = vec![0xff, 0xfb, 0x90, 0x00];
+        let mut v1 = b"TAG".to_vec();
+        v1.extend(format!("{:\0<30}", "Old Song").bytes());
+        v1.extend(format!("{:\0<30}", "Old Band").bytes());
+        v1.extend([0u8; 30].iter());
+        v1.extend(b"1999");
+        v1.extend([0u8; 28].iter());
+        v1.extend(&[0, 7, 255]);
+        data.extend(v1);
+        assert_eq!(
+            read_audio_tags(&mut Cursor::new(data)).unwrap(),
+            tags(&[
+                (AudioTagField::Title, "Old Song"),
+                (AudioTagField::Artist, "Old Band"),
+                (AudioTagField::TrackNumber, "7"),
+                (AudioTagField::Date, "1999"),
+                (AudioTagField::Year, "1999"),
+            ])
+        );
+
+        let data = b"plain text".to_vec();
+        assert_eq!(read_audio_tags(&mut Cursor::new(data)).unwrap(), None);
+    }
+}
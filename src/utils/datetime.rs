@@ -1,5 +1,7 @@
 use glib::{BoolError, DateTime, TimeZone};
+use regex::{Captures, Regex};
 use std::convert::TryFrom;
+use std::ops::Range;
 use std::time::SystemTime;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
@@ -15,6 +17,104 @@ impl UnixTime {
             .and_then(|v| v.format(format).ok())
             .map(|v| v.to_string())
     }
+    /// Format the time as seen in the time zone
+    pub fn format_in(&self, format: &str, time_zone: &TimeZone) -> Option<String> {
+        DateTime::from_unix_utc(self.0)
+            .and_then(|v| v.to_timezone(time_zone))
+            .ok()
+            .and_then(|v| v.format(format).ok())
+            .map(|v| v.to_string())
+    }
+    /// Move the time by the seconds
+    pub fn shift(&self, seconds: i64) -> Self {
+        Self(self.0.saturating_add(seconds))
+    }
+}
+
+/// Replace `%f` in the format with the microseconds, and `%1f` to `%6f` with their leading digits,
+/// since `DateTime::format` knows only the whole seconds of `UnixTime`.
+pub(crate) fn expand_microsecond(format: &str, microsecond: u32) -> String {
+    let digits = format!("{:06}", microsecond % 1_000_000);
+    let mut expanded = String::with_capacity(format.len());
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some('f') => {
+                chars.next();
+                expanded.push_str(digits.as_str());
+            }
+            Some(n @ '1'..='6') => {
+                let mut lookahead = chars.clone();
+                lookahead.next();
+                if lookahead.peek() == Some(&'f') {
+                    chars.next();
+                    chars.next();
+                    expanded.push_str(&digits[..n.to_digit(10).unwrap() as usize]);
+                } else {
+                    expanded.push(c);
+                }
+            }
+            Some('%') => {
+                chars.next();
+                expanded.push_str("%%");
+            }
+            _ => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+/// Time zone of the fixed offset in minutes east of UTC
+pub(crate) fn offset_time_zone(offset: i16) -> TimeZone {
+    TimeZone::new(Some(offset_identifier(offset).as_str()))
+}
+
+fn offset_identifier(offset: i16) -> String {
+    let offset = i32::from(offset);
+    format!(
+        "{}{:02}:{:02}",
+        if offset >= 0 { '+' } else { '-' },
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+/// Parse the time shift such as "+1:30", "-0:00:45" or "2" (hours) into seconds.
+///
+/// Empty text means no shift.
+pub(crate) fn parse_time_shift(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    let (sign, text) = match text.chars().next() {
+        Some('-') => (-1, &text[1..]),
+        Some('+') => (1, &text[1..]),
+        _ => (1, text),
+    };
+    let fields = text.trim_start().split(':').collect::<Vec<_>>();
+    if fields.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0i64;
+    for (i, field) in fields.iter().enumerate() {
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let value = field.parse::<i64>().ok()?;
+        if i > 0 && value >= 60 {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(value)?;
+    }
+    for _ in fields.len()..3 {
+        seconds = seconds.checked_mul(60)?;
+    }
+    Some(sign * seconds)
 }
 
 impl From<SystemTime> for UnixTime {
@@ -38,30 +138,143 @@ impl From<DateTime> for UnixTime {
     }
 }
 
+/// Pattern to find a date written in a text, such as "%Y%m%d" or "%d.%m.%y"
+///
+/// `%Y`, `%y`, `%m`, `%d`, `%H`, `%M`, `%S` and `%%` are recognized, and the rest is matched literally.
+/// The numbers at the ends of the pattern do not match a part of a longer number.
+pub(crate) struct DatePattern {
+    regex: Regex,
+    /// `true` if the pattern ends with a number, which must not be followed by a digit
+    ends_with_number: bool,
+}
+
+/// Date found by the `DatePattern`, which may not be a valid date
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct DateMatch {
+    pub range: Range<usize>,
+    pub year: i32,
+    pub month: i32,
+    pub day: i32,
+    pub hour: i32,
+    pub minute: i32,
+    pub second: i32,
+}
+
+impl DatePattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        let mut regex = String::new();
+        let mut boundary = "";
+        let mut ends_with_number = false;
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            ends_with_number = false;
+            if c != '%' {
+                regex.push_str(regex::escape(c.to_string().as_str()).as_str());
+                continue;
+            }
+            let is_first = regex.is_empty();
+            let next = chars.next();
+            if let Some('Y' | 'y' | 'm' | 'd' | 'H' | 'M' | 'S') = next {
+                if is_first {
+                    // The regex crate does not support look-behind.
+                    boundary = r"(?:^|[^0-9])";
+                }
+                ends_with_number = true;
+            }
+            match next {
+                Some('Y') => regex.push_str(r"(?P<Y>\d{4})"),
+                Some('y') => regex.push_str(r"(?P<y>\d{2})"),
+                Some('m') => regex.push_str(r"(?P<m>\d{2})"),
+                Some('d') => regex.push_str(r"(?P<d>\d{2})"),
+                Some('H') => regex.push_str(r"(?P<H>\d{2})"),
+                Some('M') => regex.push_str(r"(?P<M>\d{2})"),
+                Some('S') => regex.push_str(r"(?P<S>\d{2})"),
+                Some(c) => {
+                    if c != '%' {
+                        regex.push('%');
+                    }
+                    regex.push_str(regex::escape(c.to_string().as_str()).as_str());
+                }
+                None => regex.push('%'),
+            }
+        }
+        Ok(Self {
+            regex: Regex::new(format!("{}(?P<date>{})", boundary, regex).as_str())?,
+            ends_with_number,
+        })
+    }
+
+    /// Find the first date in the text
+    pub fn find(&self, text: &str) -> Option<DateMatch> {
+        self.find_iter(text).next()
+    }
+
+    /// Find all the dates in the text
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = DateMatch> + 'a {
+        self.regex
+            .captures_iter(text)
+            .filter_map(|captures| Self::date_match_of(&captures))
+            .filter(move |date| {
+                // The regex crate does not support look-ahead either.
+                !self.ends_with_number
+                    || !text[date.range.end..].starts_with(|c: char| c.is_ascii_digit())
+            })
+    }
+
+    fn date_match_of(captures: &Captures) -> Option<DateMatch> {
+        let field = |name: &str| {
+            captures
+                .name(name)
+                .and_then(|v| v.as_str().parse::<i32>().ok())
+        };
+        let year = match (field("Y"), field("y")) {
+            (Some(year), _) => year,
+            // the same as POSIX strptime
+            (None, Some(year)) if year < 69 => 2000 + year,
+            (None, Some(year)) => 1900 + year,
+            (None, None) => return None,
+        };
+        Some(DateMatch {
+            range: captures.name("date")?.range(),
+            year,
+            month: field("m").unwrap_or(1),
+            day: field("d").unwrap_or(1),
+            hour: field("H").unwrap_or(0),
+            minute: field("M").unwrap_or(0),
+            second: field("S").unwrap_or(0),
+        })
+    }
+}
+
+impl DateMatch {
+    /// Time of the date in the time zone, or `None` for the invalid date
+    pub fn to_unix_time(&self, time_zone: &TimeZone) -> Option<UnixTime> {
+        DateTime::new(
+            time_zone,
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second as f64,
+        )
+        .ok()
+        .map(UnixTime::from)
+    }
+}
+
 impl TryFrom<exif::DateTime> for UnixTime {
     type Error = BoolError;
 
     fn try_from(datetime: exif::DateTime) -> Result<Self, Self::Error> {
         DateTime::new(
-            &TimeZone::new(
-                datetime
-                    .offset
-                    .map(|offset| {
-                        format!(
-                            "{}{:02}:{:02}",
-                            if offset >= 0 { '+' } else { '-' },
-                            offset.abs() / 60,
-                            offset.abs() % 60
-                        )
-                    })
-                    .as_deref(),
-            ),
+            &TimeZone::new(datetime.offset.map(offset_identifier).as_deref()),
             datetime.year as i32,
             datetime.month as i32,
             datetime.day as i32,
             datetime.hour as i32,
             datetime.minute as i32,
-            datetime.second as f64 + (datetime.nanosecond.unwrap_or_default() as f64 / 1000000.0),
+            datetime.second as f64 + (datetime.nanosecond.unwrap_or_default() as f64 / 1e9),
         )
         .map(|v| UnixTime::from(v))
     }
@@ -95,4 +308,130 @@ mod test {
         let text = time.format("%Y-%m-%d-%%-%H:%M:%S").unwrap();
         assert!(matcher.is_match(text.as_str()));
     }
+
+    #[test]
+    fn test_format_in() {
+        let time = UnixTime(1234567890);
+        assert_eq!(
+            time.format_in("%Y-%m-%d %H:%M:%S", &TimeZone::utc()),
+            Some("2009-02-13 23:31:30".to_string())
+        );
+        assert_eq!(
+            time.format_in("%Y-%m-%d %H:%M:%S", &offset_time_zone(9 * 60)),
+            Some("2009-02-14 08:31:30".to_string())
+        );
+        assert_eq!(
+            time.format_in("%H:%M %z", &offset_time_zone(-(5 * 60 + 30))),
+            Some("18:01 -0530".to_string())
+        );
+        assert_eq!(
+            time.shift(-3600).format_in("%H:%M:%S", &TimeZone::utc()),
+            Some("22:31:30".to_string())
+        );
+    }
+
+    #[test]
+    fn test_date_pattern() {
+        let pattern = DatePattern::new("%Y%m%d_%H%M%S").unwrap();
+        assert_eq!(
+            pattern.find("IMG_20210304_123456.jpg"),
+            Some(DateMatch {
+                range: 4..19,
+                year: 2021,
+                month: 3,
+                day: 4,
+                hour: 12,
+                minute: 34,
+                second: 56,
+            })
+        );
+        assert_eq!(pattern.find("IMG_2021034_123456.jpg"), None);
+
+        let pattern = DatePattern::new("%d.%m.%y").unwrap();
+        let date = pattern.find("Scan 03.04.21.pdf").unwrap();
+        assert_eq!(date.range, 5..13);
+        assert_eq!((date.year, date.month, date.day), (2021, 4, 3));
+        assert_eq!(date.hour, 0);
+        assert_eq!(pattern.find("Scan 03.04.99.pdf").unwrap().year, 1999);
+        assert_eq!(pattern.find("Scan 03-04-21.pdf"), None);
+        assert_eq!(pattern.find("Scan 103.04.215"), None);
+        assert_eq!(
+            pattern.find("Scan 103.04.21 03.04.21").unwrap().range,
+            15..23
+        );
+
+        let pattern = DatePattern::new("%Y%m%d").unwrap();
+        assert_eq!(pattern.find("Serial 1234202103045678"), None);
+        assert_eq!(
+            pattern
+                .find_iter("20210304_20210305")
+                .map(|v| v.day)
+                .collect::<Vec<_>>(),
+            vec![4, 5]
+        );
+
+        let pattern = DatePattern::new("IMG-%Y%m%d-WA").unwrap();
+        assert_eq!(
+            pattern
+                .find_iter("IMG-20210304-WA0001 IMG-20211231-WA0002")
+                .map(|v| (v.year, v.month, v.day))
+                .collect::<Vec<_>>(),
+            vec![(2021, 3, 4), (2021, 12, 31)]
+        );
+
+        let pattern = DatePattern::new("100%% %m/%d").unwrap();
+        assert_eq!(pattern.find("100% 03/04"), None);
+        let pattern = DatePattern::new("100%% %Y/%m").unwrap();
+        assert_eq!(pattern.find("100% 2021/03").unwrap().month, 3);
+
+        assert!(DatePattern::new("%Y-%Y").is_err());
+    }
+
+    #[test]
+    fn test_date_match_to_unix_time() {
+        let pattern = DatePattern::new("%Y-%m-%d %H:%M:%S").unwrap();
+        let utc = TimeZone::utc();
+        assert_eq!(
+            pattern
+                .find("2009-02-13 23:31:30")
+                .and_then(|v| v.to_unix_time(&utc)),
+            Some(UnixTime(1234567890))
+        );
+        assert_eq!(
+            pattern
+                .find("2009-02-30 23:31:30")
+                .and_then(|v| v.to_unix_time(&utc)),
+            None
+        );
+        assert_eq!(
+            pattern
+                .find("2009-02-13 23:31:30")
+                .and_then(|v| v.to_unix_time(&offset_time_zone(60))),
+            Some(UnixTime(1234567890 - 3600))
+        );
+    }
+
+    #[test]
+    fn test_expand_microsecond() {
+        assert_eq!(expand_microsecond("%H%M%S", 123456), "%H%M%S");
+        assert_eq!(expand_microsecond("%S.%f", 120000), "%S.120000");
+        assert_eq!(expand_microsecond("%S.%3f", 7000), "%S.007");
+        assert_eq!(expand_microsecond("%S%1f%2f", 987654), "%S998");
+        assert_eq!(expand_microsecond("100%%f %7f", 5), "100%%f %7f");
+        assert_eq!(expand_microsecond("%", 5), "%");
+    }
+
+    #[test]
+    fn test_parse_time_shift() {
+        assert_eq!(parse_time_shift(""), Some(0));
+        assert_eq!(parse_time_shift("2"), Some(2 * 3600));
+        assert_eq!(parse_time_shift("+1:30"), Some(3600 + 30 * 60));
+        assert_eq!(parse_time_shift("-0:00:45"), Some(-45));
+        assert_eq!(parse_time_shift(" - 25:01:02 "), Some(-(25 * 3600 + 62)));
+        assert_eq!(parse_time_shift("1:60"), None);
+        assert_eq!(parse_time_shift("1:2:3:4"), None);
+        assert_eq!(parse_time_shift("1h"), None);
+        assert_eq!(parse_time_shift("+"), None);
+        assert_eq!(parse_time_shift("1::"), None);
+    }
 }
@@ -0,0 +1,238 @@
+use crate::utils::UnixTime;
+use glib::DateTime;
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read, Result, Seek, SeekFrom};
+
+/// Seconds between 1904-01-01 (the epoch of QuickTime and MP4) and 1970-01-01
+const MP4_EPOCH_OFFSET: i64 = 2082844800;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Read the creation time of the movie header (`moov/mvhd`) of the MP4/MOV file
+pub(crate) fn read_mvhd_creation_time<R: Read + Seek>(reader: &mut R) -> Result<Option<UnixTime>> {
+    let moov_size = match find_box(reader, None, b"moov")? {
+        Some(size) => size,
+        None => return Ok(None),
+    };
+    if find_box(reader, moov_size, b"mvhd")?.is_none() {
+        return Ok(None);
+    }
+
+    let mut version_flags = [0u8; 4];
+    reader.read_exact(&mut version_flags)?;
+    let creation_time = if version_flags[0] == 1 {
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        u64::from_be_bytes(buf)
+    } else {
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        u64::from(u32::from_be_bytes(buf))
+    };
+
+    // zero is written by the tools that do not know the time
+    Ok(match i64::try_from(creation_time) {
+        Ok(0) | Err(_) => None,
+        Ok(creation_time) => Some(UnixTime(creation_time - MP4_EPOCH_OFFSET)),
+    })
+}
+
+/// Find the box of the type among the sibling boxes within the `limit` bytes and move to its payload.
+///
+/// The payload size is `None` when the box extends to the end of the file.
+pub(crate) fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    limit: Option<u64>,
+    box_type: &[u8; 4],
+) -> Result<Option<Option<u64>>> {
+    let mut remaining = limit;
+    loop {
+        if matches!(remaining, Some(remaining) if remaining < 8) {
+            return Ok(None);
+        }
+
+        let mut header = [0u8; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let (size, current_type) = header.split_at(4);
+        if !current_type
+            .iter()
+            .all(|c| c.is_ascii_graphic() || *c == b' ')
+        {
+            // not an ISO base media file
+            return Ok(None);
+        }
+
+        let size = u64::from(u32::from_be_bytes([size[0], size[1], size[2], size[3]]));
+        let (size, header_size) = if size == 1 {
+            let mut buf = [0u8; 8];
+            reader.read_exact(&mut buf)?;
+            (Some(u64::from_be_bytes(buf)), 16)
+        } else if size == 0 {
+            (None, 8)
+        } else {
+            (Some(size), 8)
+        };
+        let payload_size = match size {
+            Some(size) if size < header_size => return Ok(None),
+            Some(size) => Some(size - header_size),
+            None => None,
+        };
+
+        if current_type == box_type {
+            return Ok(Some(payload_size));
+        }
+        match (size, payload_size) {
+            (Some(size), Some(payload_size)) => {
+                reader.seek(SeekFrom::Current(
+                    i64::try_from(payload_size).unwrap_or(i64::MAX),
+                ))?;
+                remaining = remaining.map(|remaining| remaining.saturating_sub(size));
+            }
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Read the last-modification time of the PNG file from its `tIME` chunk
+pub(crate) fn read_png_time<R: Read + Seek>(reader: &mut R) -> Result<Option<UnixTime>> {
+    let mut signature = [0u8; 8];
+    match reader.read_exact(&mut signature) {
+        Ok(()) if signature == PNG_SIGNATURE => {}
+        Ok(()) => return Ok(None),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    loop {
+        let mut header = [0u8; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        match &header[4..] {
+            b"tIME" if length == 7 => {
+                let mut data = [0u8; 7];
+                reader.read_exact(&mut data)?;
+                // tIME is in UTC
+                return Ok(DateTime::from_utc(
+                    i32::from(u16::from_be_bytes([data[0], data[1]])),
+                    i32::from(data[2]),
+                    i32::from(data[3]),
+                    i32::from(data[4]),
+                    i32::from(data[5]),
+                    f64::from(data[6]),
+                )
+                .ok()
+                .map(UnixTime::from));
+            }
+            b"IEND" => return Ok(None),
+            _ => {
+                // skip the data and the CRC
+                reader.seek(SeekFrom::Current(i64::from(length) + 4))?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_read_mvhd_creation_time() {
+        // 2009-02-13 23:31:30 UTC
+        let creation_time = 1234567890 + MP4_EPOCH_OFFSET;
+
+        let mut mvhd = vec![0, 0, 0, 0];
+        mvhd.extend_from_slice(&(creation_time as u32).to_be_bytes());
+        mvhd.extend_from_slice(&[0; 16]);
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0isomiso2mp41");
+        data.extend(mp4_box(b"free", &[0; 5]));
+        let mut moov = mp4_box(b"udta", &[0; 3]);
+        moov.extend(mp4_box(b"mvhd", &mvhd));
+        data.extend(mp4_box(b"moov", &moov));
+        data.extend(mp4_box(b"mdat", &[0; 32]));
+        assert_eq!(
+            read_mvhd_creation_time(&mut Cursor::new(data)).unwrap(),
+            Some(UnixTime(1234567890))
+        );
+
+        let mut mvhd = vec![1, 0, 0, 0];
+        mvhd.extend_from_slice(&(creation_time as u64).to_be_bytes());
+        mvhd.extend_from_slice(&[0; 24]);
+        let mut data = mp4_box(b"ftyp", b"qt  \0\0\0\0qt  ");
+        data.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+        assert_eq!(
+            read_mvhd_creation_time(&mut Cursor::new(data)).unwrap(),
+            Some(UnixTime(1234567890))
+        );
+
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &[0; 20])));
+        assert_eq!(
+            read_mvhd_creation_time(&mut Cursor::new(data)).unwrap(),
+            None
+        );
+
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend(mp4_box(b"mdat", &[0; 32]));
+        assert_eq!(
+            read_mvhd_creation_time(&mut Cursor::new(data)).unwrap(),
+            None
+        );
+
+        let data = PNG_SIGNATURE.to_vec();
+        assert_eq!(
+            read_mvhd_creation_time(&mut Cursor::new(data)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_png_time() {
+        let chunk = |chunk_type: &[u8; 4], data: &[u8]| {
+            let mut chunk = Vec::new();
+            chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            chunk.extend_from_slice(chunk_type);
+            chunk.extend_from_slice(data);
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend(chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        data.extend(chunk(b"tIME", &[0x07, 0xd9, 2, 13, 23, 31, 30]));
+        data.extend(chunk(b"IEND", &[]));
+        assert_eq!(
+            read_png_time(&mut Cursor::new(data)).unwrap(),
+            Some(UnixTime(1234567890))
+        );
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend(chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        data.extend(chunk(b"IEND", &[]));
+        assert_eq!(read_png_time(&mut Cursor::new(data)).unwrap(), None);
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend(chunk(b"tIME", &[0x07, 0xd9, 2, 30, 23, 31, 30]));
+        assert_eq!(read_png_time(&mut Cursor::new(data)).unwrap(), None);
+
+        let data = b"GIF89a".to_vec();
+        assert_eq!(read_png_time(&mut Cursor::new(data)).unwrap(), None);
+    }
+}
@@ -4,14 +4,22 @@ use gtk::{ListStore, SelectionData};
 use std::iter;
 use std::path::PathBuf;
 
+mod audio_tag;
 mod datetime;
 mod insert_position;
+mod media_time;
 mod observer;
+mod placeholder_template;
+mod replace_template;
+pub(crate) use audio_tag::*;
 pub(crate) use datetime::*;
 pub(crate) use insert_position::*;
+pub(crate) use media_time::*;
 #[cfg(test)]
 pub(crate) use observer::test::CounterObserver;
 pub(crate) use observer::*;
+pub(crate) use placeholder_template::*;
+pub(crate) use replace_template::*;
 
 pub fn value2string(value: &Value) -> String {
     value.get::<String>().unwrap_or_default().clone()
@@ -83,6 +91,15 @@ pub(crate) fn split_file_at_dot(file: &str) -> (&str, Option<&str>) {
     }
 }
 
+/// Format `count` with `noun`, e.g. `1 name` and `2 names`.
+pub(crate) fn format_count(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -168,4 +185,11 @@ mod test {
             ("file.name", Some("txt"))
         );
     }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0, "name"), "0 names");
+        assert_eq!(format_count(1, "name"), "1 name");
+        assert_eq!(format_count(2, "row"), "2 rows");
+    }
 }
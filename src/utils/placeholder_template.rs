@@ -0,0 +1,107 @@
+use gtk::prelude::*;
+use gtk::{Entry, EntryIconPosition};
+use std::mem;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum TemplateItem<T> {
+    Text(String),
+    Placeholder(T),
+}
+
+/// Text with the placeholders such as `{artist} - {title}`
+///
+/// `{{` and `}}` are braces. The unknown placeholders are kept as they are.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PlaceholderTemplate<T> {
+    pub items: Vec<TemplateItem<T>>,
+    pub unknown_placeholders: Vec<String>,
+}
+
+impl<T> PlaceholderTemplate<T> {
+    /// Parse the template. `parse_placeholder` parses the inside of `{…}`, or returns `None` for
+    /// the unknown placeholder.
+    pub fn parse(template: &str, parse_placeholder: impl Fn(&str) -> Option<T>) -> Self {
+        let mut items = Vec::new();
+        let mut unknown_placeholders = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                text.push(c);
+                rest = &rest[2..];
+            } else if let Some(end) = rest.strip_prefix('{').and_then(|v| v.find('}')) {
+                let placeholder = &rest[1..end + 1];
+                if let Some(value) = parse_placeholder(placeholder) {
+                    if !text.is_empty() {
+                        items.push(TemplateItem::Text(mem::take(&mut text)));
+                    }
+                    items.push(TemplateItem::Placeholder(value));
+                } else {
+                    unknown_placeholders.push(placeholder.to_string());
+                    text.push_str(&rest[..end + 2]);
+                }
+                rest = &rest[end + 2..];
+            } else {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        if !text.is_empty() {
+            items.push(TemplateItem::Text(text));
+        }
+
+        Self {
+            items,
+            unknown_placeholders,
+        }
+    }
+}
+
+/// Show the error icon with the unknown placeholders in the template entry, or hide it if none.
+pub(crate) fn set_unknown_placeholders_icon(entry: &Entry, unknown_placeholders: &[String]) {
+    if unknown_placeholders.is_empty() {
+        entry.set_icon_from_icon_name(EntryIconPosition::Secondary, None);
+        entry.set_icon_tooltip_text(EntryIconPosition::Secondary, None);
+    } else {
+        entry.set_icon_from_icon_name(EntryIconPosition::Secondary, Some("error"));
+        entry.set_icon_tooltip_text(
+            EntryIconPosition::Secondary,
+            Some(format!("Unknown placeholders: {}", unknown_placeholders.join(", ")).as_str()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_number(text: &str) -> Option<u32> {
+        text.parse().ok()
+    }
+
+    #[test]
+    fn test_placeholder_template_parse() {
+        assert_eq!(
+            PlaceholderTemplate::parse("{1}_{2}mm {{{3}}} {x} {4", parse_number),
+            PlaceholderTemplate {
+                items: vec![
+                    TemplateItem::Placeholder(1),
+                    TemplateItem::Text("_".to_string()),
+                    TemplateItem::Placeholder(2),
+                    TemplateItem::Text("mm {".to_string()),
+                    TemplateItem::Placeholder(3),
+                    TemplateItem::Text("} {x} {4".to_string()),
+                ],
+                unknown_placeholders: vec!["x".to_string()],
+            }
+        );
+        assert_eq!(
+            PlaceholderTemplate::parse("", parse_number),
+            PlaceholderTemplate {
+                items: vec![],
+                unknown_placeholders: vec![],
+            }
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use regex::Captures;
+use std::mem;
+
+/// Case conversion of the expanded text
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum CaseConversion {
+    Upper,
+    Lower,
+}
+
+impl CaseConversion {
+    fn push_to(self, text: &mut String, c: char) {
+        match self {
+            CaseConversion::Upper => text.extend(c.to_uppercase()),
+            CaseConversion::Lower => text.extend(c.to_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum TemplateItem {
+    /// Text expanded by `Captures::expand` such as `$1` or `${name}`
+    Expand(String),
+    /// `\U` or `\L`: convert the case until `\E`
+    CaseStart(CaseConversion),
+    /// `\E`
+    CaseEnd,
+    /// `\u` or `\l`: convert the case of the next character
+    CaseNext(CaseConversion),
+    /// `${n:03}`: the counter of the file padded to the width
+    Counter { width: usize, zero_padded: bool },
+    /// `${1:pad=4}`: the numeric group padded with zeros to the width
+    PaddedGroup { group: String, width: usize },
+}
+
+/// Replacement of the regular expression with the escapes similar to sed/Perl
+///
+/// In addition to `$1` and `${name}` of `regex::Captures::expand`, the following escapes are
+/// supported:
+///
+/// * `\U…\E`, `\L…\E`: convert the case of the text until `\E`
+/// * `\u`, `\l`: convert the case of the next character
+/// * `${n:03}`: the counter of the file, padded with zeros to 3 characters
+/// * `${1:pad=4}`: the numeric group padded with zeros to 4 characters
+///
+/// The other backslashes are kept as they are.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ReplaceTemplate(Vec<TemplateItem>);
+
+impl ReplaceTemplate {
+    pub fn parse(template: &str) -> Self {
+        let mut items = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(c) = rest.chars().next() {
+            let item = match c {
+                '\\' => {
+                    let item = match rest[1..].chars().next() {
+                        Some('U') => Some(TemplateItem::CaseStart(CaseConversion::Upper)),
+                        Some('L') => Some(TemplateItem::CaseStart(CaseConversion::Lower)),
+                        Some('E') => Some(TemplateItem::CaseEnd),
+                        Some('u') => Some(TemplateItem::CaseNext(CaseConversion::Upper)),
+                        Some('l') => Some(TemplateItem::CaseNext(CaseConversion::Lower)),
+                        _ => None,
+                    };
+                    if item.is_some() {
+                        rest = &rest[2..];
+                    } else {
+                        text.push('\\');
+                        rest = &rest[1..];
+                    }
+                    item
+                }
+                '$' if rest.starts_with("$$") => {
+                    // Keep the escaped dollar for `Captures::expand`.
+                    text.push_str("$$");
+                    rest = &rest[2..];
+                    None
+                }
+                '$' if rest.starts_with("${") => {
+                    let item = rest
+                        .find('}')
+                        .and_then(|end| Some((Self::parse_format(&rest[2..end])?, end)));
+                    if let Some((item, end)) = item {
+                        rest = &rest[end + 1..];
+                        Some(item)
+                    } else {
+                        text.push_str("${");
+                        rest = &rest[2..];
+                        None
+                    }
+                }
+                c => {
+                    text.push(c);
+                    rest = &rest[c.len_utf8()..];
+                    None
+                }
+            };
+
+            if let Some(item) = item {
+                if !text.is_empty() {
+                    items.push(TemplateItem::Expand(mem::take(&mut text)));
+                }
+                items.push(item);
+            }
+        }
+        if !text.is_empty() {
+            items.push(TemplateItem::Expand(text));
+        }
+
+        Self(items)
+    }
+
+    /// Returns the template of `text` without any escapes and placeholders.
+    pub fn literal(text: &str) -> Self {
+        if text.is_empty() {
+            Self(vec![])
+        } else {
+            Self(vec![TemplateItem::Expand(text.replace('$', "$$"))])
+        }
+    }
+
+    /// Parse the inside of `${…}` with the format such as `n:03` or `1:pad=4`.
+    fn parse_format(text: &str) -> Option<TemplateItem> {
+        let (name, format) = text.split_at(text.find(':')?);
+        let format = &format[1..];
+        let is_number = |v: &str| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit());
+
+        if name == "n" && is_number(format) {
+            Some(TemplateItem::Counter {
+                width: format.parse().ok()?,
+                zero_padded: format.starts_with('0'),
+            })
+        } else if !name.is_empty()
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && format.starts_with("pad=")
+            && is_number(&format[4..])
+        {
+            Some(TemplateItem::PaddedGroup {
+                group: name.to_string(),
+                width: format[4..].parse().ok()?,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Expand the template with the captures of the match and the counter of the file.
+    pub fn expand(&self, caps: &Captures, counter: usize) -> String {
+        let mut expanded = String::new();
+        let mut case_conversion = None;
+        let mut next_case_conversion = None;
+
+        for item in self.0.iter() {
+            let text = match item {
+                TemplateItem::Expand(template) => {
+                    let mut text = String::new();
+                    caps.expand(template.as_str(), &mut text);
+                    text
+                }
+                TemplateItem::CaseStart(conversion) => {
+                    case_conversion = Some(*conversion);
+                    continue;
+                }
+                TemplateItem::CaseEnd => {
+                    case_conversion = None;
+                    continue;
+                }
+                TemplateItem::CaseNext(conversion) => {
+                    next_case_conversion = Some(*conversion);
+                    continue;
+                }
+                TemplateItem::Counter {
+                    width,
+                    zero_padded: true,
+                } => format!("{:0width$}", counter, width = width),
+                TemplateItem::Counter {
+                    width,
+                    zero_padded: false,
+                } => format!("{:width$}", counter, width = width),
+                TemplateItem::PaddedGroup { group, width } => {
+                    let value = match group.parse::<usize>() {
+                        Ok(i) => caps.get(i),
+                        Err(_) => caps.name(group.as_str()),
+                    }
+                    .map(|m| m.as_str())
+                    .unwrap_or_default();
+                    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+                        format!("{:0>width$}", value, width = width)
+                    } else {
+                        value.to_string()
+                    }
+                }
+            };
+
+            let mut chars = text.chars();
+            if let Some(conversion) = next_case_conversion {
+                if let Some(c) = chars.next() {
+                    conversion.push_to(&mut expanded, c);
+                    next_case_conversion = None;
+                }
+            }
+            for c in chars {
+                match case_conversion {
+                    Some(conversion) => conversion.push_to(&mut expanded, c),
+                    None => expanded.push(c),
+                }
+            }
+        }
+
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use regex::Regex;
+
+    fn replace(pattern: &str, template: &str, text: &str, counter: usize) -> String {
+        let template = ReplaceTemplate::parse(template);
+        Regex::new(pattern)
+            .unwrap()
+            .replace_all(text, |caps: &Captures| template.expand(caps, counter))
+            .to_string()
+    }
+
+    #[test]
+    fn test_replace_template_parse() {
+        assert_eq!(
+            ReplaceTemplate::parse("a$1\\U${x}\\E$${n:03}\\\\${n:03}${1:pad=4}\\q${y:z}"),
+            ReplaceTemplate(vec![
+                TemplateItem::Expand("a$1".to_string()),
+                TemplateItem::CaseStart(CaseConversion::Upper),
+                TemplateItem::Expand("${x}".to_string()),
+                TemplateItem::CaseEnd,
+                TemplateItem::Expand("$${n:03}\\\\".to_string()),
+                TemplateItem::Counter {
+                    width: 3,
+                    zero_padded: true
+                },
+                TemplateItem::PaddedGroup {
+                    group: "1".to_string(),
+                    width: 4
+                },
+                TemplateItem::Expand("\\q${y:z}".to_string()),
+            ])
+        );
+        assert_eq!(ReplaceTemplate::parse(""), ReplaceTemplate(vec![]));
+        assert_eq!(
+            ReplaceTemplate::literal("\\U$1"),
+            ReplaceTemplate(vec![TemplateItem::Expand("\\U$$1".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_replace_template_expand() {
+        assert_eq!(
+            replace(
+                r"^track (\d+) - (.*)$",
+                r"${1:pad=2}_\L$2",
+                "track 7 - Title",
+                1
+            ),
+            "07_title"
+        );
+        assert_eq!(
+            replace(r"(\w+) (\w+)", r"\U$1\E \u$2", "hello world", 1),
+            "HELLO World"
+        );
+        assert_eq!(
+            replace(r"(\w+)", r"\u\L$1", "hELLO wORLD", 1),
+            "Hello World"
+        );
+        assert_eq!(replace(r"^", r"${n:03}_", "a.txt", 7), "007_a.txt");
+        assert_eq!(replace(r"^", r"${n:3}_", "a.txt", 7), "  7_a.txt");
+        assert_eq!(replace(r"^", r"$${n:03}", "a", 7), "${n:03}a");
+        assert_eq!(
+            replace(r"(?P<no>\w+)", r"${no:pad=3}", "5 a5 12345", 1),
+            "005 a5 12345"
+        );
+        assert_eq!(replace(r"a", r"\\", "bab", 1), "b\\\\b");
+        assert_eq!(replace(r"a", r"\d\", "bab", 1), "b\\d\\b");
+    }
+}
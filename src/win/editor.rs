@@ -0,0 +1,258 @@
+use gio::prelude::*;
+use gtk::prelude::*;
+use gtk::{
+    ApplicationWindow, ButtonsType, MessageDialog, MessageType, PolicyType, ResponseType,
+    ScrolledWindow, TextView,
+};
+use std::env;
+use std::fs;
+use std::io::Error as IoError;
+use std::process::Command;
+use std::time::Duration;
+use thiserror;
+
+/// Interval to check whether the editor exits
+const EDITOR_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Error of editing the new names in the external editor
+#[derive(thiserror::Error, Debug)]
+pub(super) enum EditorError {
+    /// The name contains a line break and cannot be edited line by line.
+    #[error("The new name on row {0} contains a line break")]
+    LineBreakInName(usize),
+    /// The new name is empty, `.`, `..` or contains `/` or NUL.
+    #[error("The new name on row {0} is not a valid file name: {1:?}")]
+    InvalidName(usize, String),
+    /// The number of the lines is changed by the editor.
+    #[error("The number of lines is changed from {0} to {1}")]
+    LineCountMismatch(usize, usize),
+    /// The editor failed to launch or exited with failure.
+    #[error("Editor failed: {0}")]
+    EditorFailed(String),
+    /// Editing is cancelled.
+    #[error("Cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Io(#[from] IoError),
+}
+
+/// Format the names one per line.
+fn format_names(names: &[String]) -> Result<String, EditorError> {
+    if let Some(row) = names
+        .iter()
+        .position(|name| name.contains(&['\n', '\r'][..]))
+    {
+        return Err(EditorError::LineBreakInName(row + 1));
+    }
+    let mut text = names.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+/// Parse the names one per line. The number of the names must be `count`.
+fn parse_names(text: &str, count: usize) -> Result<Vec<String>, EditorError> {
+    let names = text.lines().map(|v| v.to_string()).collect::<Vec<_>>();
+    if names.len() != count {
+        return Err(EditorError::LineCountMismatch(count, names.len()));
+    }
+    if let Some(row) = names.iter().position(|name| {
+        name.is_empty() || name == "." || name == ".." || name.contains(&['/', '\0'][..])
+    }) {
+        return Err(EditorError::InvalidName(row + 1, names[row].clone()));
+    }
+    Ok(names)
+}
+
+/// Returns the differences of the names as lines of `-` old names and `+` new names.
+fn format_name_diff(old_names: &[String], new_names: &[String]) -> String {
+    old_names
+        .iter()
+        .zip(new_names.iter())
+        .enumerate()
+        .filter(|(_, (old_name, new_name))| old_name != new_name)
+        .map(|(i, (old_name, new_name))| {
+            format!("@@ row {} @@\n- {}\n+ {}", i + 1, old_name, new_name)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the command of the editor from `$VISUAL` or `$EDITOR`.
+fn editor_command() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|command| !command.trim().is_empty())
+}
+
+/// Edit `names` one per line in the external editor.
+///
+/// The editor is `$VISUAL`, `$EDITOR` or the default text editor of the desktop.
+/// `on_finished` is called with the edited names after the editor exits.
+pub(super) fn edit_names<F>(main_window: &ApplicationWindow, names: &[String], on_finished: F)
+where
+    F: FnOnce(Result<Vec<String>, EditorError>) + 'static,
+{
+    let count = names.len();
+    let temp_file = match format_names(names).and_then(|text| {
+        let temp_file = tempfile::Builder::new()
+            .prefix("new-names-")
+            .suffix(".txt")
+            .tempfile()?;
+        fs::write(temp_file.path(), text)?;
+        Ok(temp_file)
+    }) {
+        Ok(temp_file) => temp_file,
+        Err(e) => return on_finished(Err(e)),
+    };
+    let path = temp_file.path().to_path_buf();
+    // The temporary file is removed when `read_names` is dropped.
+    let read_names = move || -> Result<Vec<String>, EditorError> {
+        let text = fs::read_to_string(temp_file.path())?;
+        parse_names(text.as_str(), count)
+    };
+
+    if let Some(command) = editor_command() {
+        // Let the shell split the arguments of the command such as "code --wait".
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$1\"", command))
+            .arg("sh")
+            .arg(&path)
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => return on_finished(Err(EditorError::EditorFailed(e.to_string()))),
+        };
+        let mut on_finished = Some((on_finished, read_names));
+        glib::timeout_add_local(EDITOR_POLL_INTERVAL, move || {
+            let result = match child.try_wait() {
+                Ok(None) => return glib::Continue(true),
+                Ok(Some(status)) if status.success() => Ok(()),
+                Ok(Some(status)) => Err(EditorError::EditorFailed(status.to_string())),
+                Err(e) => Err(EditorError::Io(e)),
+            };
+            if let Some((on_finished, read_names)) = on_finished.take() {
+                on_finished(result.and_then(|_| read_names()));
+            }
+            glib::Continue(false)
+        });
+    } else {
+        let uri = gio::File::for_path(&path).uri();
+        if let Err(e) = gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>) {
+            return on_finished(Err(EditorError::EditorFailed(e.to_string())));
+        }
+        // The desktop does not tell when the editor exits.
+        let dialog = MessageDialog::builder()
+            .application(&main_window.application().unwrap())
+            .transient_for(main_window)
+            .buttons(ButtonsType::OkCancel)
+            .message_type(MessageType::Info)
+            .text("Edit the new names")
+            .secondary_text("Save the new names in the editor, one per line, and click OK.")
+            .build();
+        let response = dialog.run();
+        dialog.close();
+        on_finished(if response == ResponseType::Ok {
+            read_names()
+        } else {
+            Err(EditorError::Cancelled)
+        });
+    }
+}
+
+/// Show the differences of the names and returns `true` if the user accepts them.
+pub(super) fn confirm_name_changes(
+    main_window: &ApplicationWindow,
+    old_names: &[String],
+    new_names: &[String],
+) -> bool {
+    let diff = format_name_diff(old_names, new_names);
+    if diff.is_empty() {
+        return false;
+    }
+    let changes = old_names
+        .iter()
+        .zip(new_names.iter())
+        .filter(|(old_name, new_name)| old_name != new_name)
+        .count();
+
+    let dialog = MessageDialog::builder()
+        .application(&main_window.application().unwrap())
+        .transient_for(main_window)
+        .buttons(ButtonsType::OkCancel)
+        .message_type(MessageType::Question)
+        .text(format!("Apply {} changes of the new names?", changes).as_str())
+        .build();
+    let text_view = TextView::builder().editable(false).monospace(true).build();
+    text_view.buffer().unwrap().set_text(diff.as_str());
+    let scrolled_window = ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Automatic)
+        .vscrollbar_policy(PolicyType::Automatic)
+        .min_content_width(480)
+        .min_content_height(240)
+        .child(&text_view)
+        .build();
+    dialog
+        .message_area()
+        .downcast::<gtk::Box>()
+        .unwrap()
+        .add(&scrolled_window);
+    scrolled_window.show_all();
+
+    let response = dialog.run();
+    dialog.close();
+    response == ResponseType::Ok
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(
+            format_names(&names(&["a.txt", "b c.txt"])).unwrap(),
+            "a.txt\nb c.txt\n"
+        );
+        assert!(matches!(
+            format_names(&names(&["a.txt", "b\nc.txt"])),
+            Err(EditorError::LineBreakInName(2))
+        ));
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(
+            parse_names("a.txt\r\nb.txt\n", 2).unwrap(),
+            names(&["a.txt", "b.txt"])
+        );
+        assert!(matches!(
+            parse_names("a.txt\n\n", 2),
+            Err(EditorError::InvalidName(2, name)) if name.is_empty()
+        ));
+        for name in &["..", "a/b", "a\0b"] {
+            assert!(matches!(
+                parse_names(format!("a.txt\n{}\n", name).as_str(), 2),
+                Err(EditorError::InvalidName(2, _))
+            ));
+        }
+        assert!(matches!(
+            parse_names("a.txt\n", 2),
+            Err(EditorError::LineCountMismatch(2, 1))
+        ));
+    }
+
+    #[test]
+    fn test_format_name_diff() {
+        assert_eq!(
+            format_name_diff(&names(&["a", "b", "c"]), &names(&["a", "x", "y"])),
+            "@@ row 2 @@\n- b\n+ x\n@@ row 3 @@\n- c\n+ y"
+        );
+        assert_eq!(format_name_diff(&names(&["a"]), &names(&["a"])), "");
+    }
+}
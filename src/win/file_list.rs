@@ -1,9 +1,11 @@
 use crate::basic_bulk_renamer::RenameMapPair;
 use crate::error::Error;
-use crate::utils::{list_store_data_iter, value2string};
+use crate::utils::{list_store_data_iter, split_file_at_dot, value2string};
 use crate::win::provider::Renamer;
+use glib::Value;
 use gtk::prelude::*;
-use gtk::ListStore;
+use gtk::{ListStore, TreeIter};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use strum_macros::EnumString;
 
@@ -35,10 +37,63 @@ pub(super) fn add_files_to_file_list(file_list_store: &ListStore, paths: &[PathB
     }
 }
 
+/// Returns `true` if the row at `iter` is a companion file shown under its primary file.
+fn is_companion_row(file_list_store: &ListStore, iter: &TreeIter) -> bool {
+    file_list_store
+        .value(iter, 5)
+        .get::<bool>()
+        .unwrap_or(false)
+}
+
+/// Iterate the rows of the files in the file list, excluding the companion files.
+fn file_rows_of_file_list(file_list_store: &ListStore) -> impl Iterator<Item = Vec<Value>> + '_ {
+    list_store_data_iter(file_list_store).filter(|v| !v[5].get::<bool>().unwrap_or(false))
+}
+
+/// Returns the new names in the file list.
+pub(super) fn get_new_names_from_file_list(file_list_store: &ListStore) -> Vec<String> {
+    file_rows_of_file_list(file_list_store)
+        .map(|v| value2string(&v[1]))
+        .collect()
+}
+
+/// Set the new names edited by hand. The changed names are pinned against renamers.
+pub(super) fn pin_new_names_of_file_list(file_list_store: &ListStore, new_names: &[String]) {
+    if let Some(iter) = file_list_store.iter_first() {
+        for new_name in new_names.iter() {
+            while is_companion_row(file_list_store, &iter) {
+                if !file_list_store.iter_next(&iter) {
+                    return;
+                }
+            }
+            let current_new_name = value2string(&file_list_store.value(&iter, 1));
+            if &current_new_name != new_name {
+                file_list_store.set(&iter, &[(1, new_name), (3, &true)]);
+            }
+            if !file_list_store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+}
+
+/// Unpin the new names edited by hand.
+pub(super) fn unpin_new_names_of_file_list(file_list_store: &ListStore) {
+    file_list_store.foreach(|_, _, iter| {
+        file_list_store.set_value(iter, 3, &false.to_value());
+        false
+    });
+}
+
+/// Returns `true` if some new names are pinned.
+pub(super) fn has_pinned_new_names(file_list_store: &ListStore) -> bool {
+    file_rows_of_file_list(file_list_store).any(|v| v[3].get::<bool>().unwrap_or(false))
+}
+
 pub(super) fn get_files_from_file_list(
     file_list_store: &ListStore,
 ) -> impl Iterator<Item = RenameMapPair> + '_ {
-    list_store_data_iter(file_list_store).map(|v| {
+    file_rows_of_file_list(file_list_store).map(|v| {
         let name = value2string(&v[0]);
         let new_name = value2string(&v[1]);
         let parent = value2string(&v[2]);
@@ -51,14 +106,163 @@ pub(super) fn get_files_from_file_list(
     })
 }
 
-pub(super) fn reset_renaming_of_file_list(file_list_store: &ListStore) {
+/// Default extensions of the companion files
+pub(super) const DEFAULT_COMPANION_EXTENSIONS: &'static str = "xmp, pp3, dop, thm, aae, jpg, jpeg";
+
+/// Parse comma or space separated extensions such as `"xmp, .pp3"`.
+pub(super) fn parse_companion_extensions(text: &str) -> Vec<String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|v| v.trim_start_matches('.').to_lowercase())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Returns the rename pairs of the companion files of `pairs`.
+///
+/// The companion files whose names do not change are omitted.
+pub(super) fn get_companion_pairs(
+    pairs: &[RenameMapPair],
+    extensions: &[String],
+) -> Vec<RenameMapPair> {
+    get_companions_of_pairs(pairs, extensions)
+        .into_iter()
+        .flatten()
+        .filter(|(source, target)| source != target)
+        .collect()
+}
+
+/// Returns the rename pairs of the companion files of each of `pairs`.
+///
+/// A companion file is a file in the same directory as the primary file
+/// whose name is the stem or the name of the primary file followed by one of `extensions`,
+/// e.g. `IMG_1234.xmp` and `IMG_1234.CR2.pp3` for `IMG_1234.CR2`.
+/// The companion files follow the new name of the primary file.
+/// Files already in `pairs` are not treated as companion files.
+pub(super) fn get_companions_of_pairs(
+    pairs: &[RenameMapPair],
+    extensions: &[String],
+) -> Vec<Vec<RenameMapPair>> {
+    let mut known_files = pairs
+        .iter()
+        .map(|(source, _)| source.clone())
+        .collect::<HashSet<_>>();
+    let mut companions_of_pairs = vec![Vec::new(); pairs.len()];
+
+    for (index, (source, target)) in pairs.iter().enumerate() {
+        if !source.is_file() {
+            continue;
+        }
+        let (name, new_name) = match (
+            source.file_name().and_then(|v| v.to_str()),
+            target.file_name().and_then(|v| v.to_str()),
+        ) {
+            (Some(name), Some(new_name)) => (name, new_name),
+            _ => continue,
+        };
+        let (stem, _) = split_file_at_dot(name);
+        let (new_stem, _) = split_file_at_dot(new_name);
+        let entries = match source.parent().map(|dir| dir.read_dir()) {
+            Some(Ok(entries)) => entries,
+            _ => continue,
+        };
+
+        let mut companions = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && !known_files.contains(path))
+            .filter_map(|path| {
+                let companion_name = path.file_name()?.to_str()?.to_string();
+                let (companion_stem, extension) = split_file_at_dot(&companion_name);
+                if !extensions.contains(&extension?.to_lowercase()) {
+                    None
+                } else if companion_stem == name {
+                    let new_companion_name = [new_name, &companion_name[name.len()..]].concat();
+                    Some((path.clone(), target.with_file_name(new_companion_name)))
+                } else if companion_stem == stem {
+                    let new_companion_name = [new_stem, &companion_name[stem.len()..]].concat();
+                    Some((path.clone(), target.with_file_name(new_companion_name)))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        companions.sort();
+
+        known_files.extend(companions.iter().map(|(path, _)| path.clone()));
+        companions_of_pairs[index] = companions;
+    }
+
+    companions_of_pairs
+}
+
+/// Show the companion files of `extensions` under their primary files with their new names.
+///
+/// The companion files shown before are replaced. Nothing is shown if `extensions` is empty.
+pub(super) fn set_companions_to_file_list(file_list_store: &ListStore, extensions: &[String]) {
     if let Some(iter) = file_list_store.iter_first() {
-        let name = file_list_store.value(&iter, 0);
-        let new_name = name.clone();
-        file_list_store.set_value(&iter, 1, &new_name);
-    } else {
-        // nothing to do if the model is empty
+        loop {
+            let has_next = if is_companion_row(file_list_store, &iter) {
+                file_list_store.remove(&iter)
+            } else {
+                file_list_store.iter_next(&iter)
+            };
+            if !has_next {
+                break;
+            }
+        }
+    }
+    if extensions.is_empty() {
+        return;
     }
+
+    let pairs = get_files_from_file_list(file_list_store).collect::<Vec<_>>();
+    let companions_of_pairs = get_companions_of_pairs(&pairs, extensions);
+    if let Some(mut iter) = file_list_store.iter_first() {
+        for companions in companions_of_pairs {
+            let mut last_iter = iter.clone();
+            for (source, target) in companions {
+                let name = source
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let new_name = target
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let parent = source.parent().unwrap().display().to_string();
+
+                last_iter = file_list_store.insert_after(Some(&last_iter));
+                file_list_store.set(
+                    &last_iter,
+                    &[(0, &name), (1, &new_name), (2, &parent), (5, &true)],
+                );
+            }
+            iter = last_iter;
+            if !file_list_store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+}
+
+/// Reset the new names to the current names and clear the flags.
+/// The new names edited by hand are kept.
+pub(super) fn reset_renaming_of_file_list(file_list_store: &ListStore) {
+    file_list_store.foreach(|_, _, iter| {
+        let pinned = file_list_store
+            .value(iter, 3)
+            .get::<bool>()
+            .unwrap_or(false);
+        if !pinned {
+            let name = file_list_store.value(iter, 0);
+            file_list_store.set_value(iter, 1, &name);
+        }
+        file_list_store.set_value(iter, 4, &false.to_value());
+        false
+    });
 }
 
 pub(super) fn apply_renamer_to_file_list(
@@ -66,16 +270,27 @@ pub(super) fn apply_renamer_to_file_list(
     target: RenamerTarget,
     renamer: Box<&dyn Renamer>,
 ) -> Result<(), Error> {
-    let data = list_store_data_iter(&file_list_store)
+    let data = file_rows_of_file_list(&file_list_store)
         .map(|row| (value2string(&row[0]), value2string(&row[2])))
         .collect::<Vec<_>>();
+    let pinned = file_rows_of_file_list(&file_list_store)
+        .map(|row| row[3].get::<bool>().unwrap_or(false))
+        .collect::<Vec<_>>();
 
     renamer
-        .apply_replacement(data.as_slice(), target)
-        .and_then(|replacements| {
+        .apply_replacement_with_flags(data.as_slice(), target)
+        .and_then(|(replacements, flags)| {
             if let Some(iter) = file_list_store.iter_first() {
-                for (new_file_name, _) in replacements {
-                    file_list_store.set(&iter, &[(1, &new_file_name)]);
+                for (((new_file_name, _), pinned), flagged) in replacements.zip(pinned).zip(flags) {
+                    // The companion files are updated with `set_companions_to_file_list`.
+                    while is_companion_row(&file_list_store, &iter) {
+                        file_list_store.iter_next(&iter);
+                    }
+                    // The new names edited by hand are kept.
+                    if !pinned {
+                        file_list_store.set(&iter, &[(1, &new_file_name)]);
+                    }
+                    file_list_store.set(&iter, &[(4, &(flagged && !pinned))]);
                     file_list_store.iter_next(&iter);
                 }
                 Ok(())
@@ -102,7 +317,14 @@ mod test {
     use std::vec::IntoIter;
 
     fn list_store() -> ListStore {
-        ListStore::new(&[Type::STRING, Type::STRING, Type::STRING])
+        ListStore::new(&[
+            Type::STRING,
+            Type::STRING,
+            Type::STRING,
+            Type::BOOL,
+            Type::BOOL,
+            Type::BOOL,
+        ])
     }
 
     struct TestRenamer {
@@ -138,6 +360,15 @@ mod test {
                 .into_iter())
         }
 
+        fn apply_replacement_with_flags(
+            &self,
+            files: &[(String, String)],
+            target: RenamerTarget,
+        ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+            self.apply_replacement(files, target)
+                .map(|replacements| (replacements, vec![true; files.len()]))
+        }
+
         fn attach_change(&self, _observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
             unimplemented!()
         }
@@ -238,6 +469,112 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_parse_companion_extensions() {
+        assert_eq!(
+            parse_companion_extensions(" xmp,.PP3  jpg,,"),
+            vec!["xmp".to_string(), "pp3".to_string(), "jpg".to_string()]
+        );
+        assert_eq!(parse_companion_extensions(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_get_companion_pairs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        for name in &[
+            "IMG_1234.CR2",
+            "IMG_1234.xmp",
+            "IMG_1234.JPG",
+            "IMG_1234.CR2.pp3",
+            "IMG_1234.txt",
+            "IMG_12345.xmp",
+            "IMG_5678.CR2",
+            "IMG_5678.xmp",
+        ] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        let extensions = parse_companion_extensions(DEFAULT_COMPANION_EXTENSIONS);
+
+        assert_eq!(
+            get_companion_pairs(
+                &[
+                    (dir.join("IMG_1234.CR2"), dir.join("2023-05-01.CR2")),
+                    (dir.join("IMG_5678.xmp"), dir.join("other.xmp")),
+                ],
+                &extensions,
+            ),
+            vec![
+                (dir.join("IMG_1234.CR2.pp3"), dir.join("2023-05-01.CR2.pp3")),
+                (dir.join("IMG_1234.JPG"), dir.join("2023-05-01.JPG")),
+                (dir.join("IMG_1234.xmp"), dir.join("2023-05-01.xmp")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_companions_to_file_list() {
+        test_synced(move || {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let dir = temp_dir.path();
+            for name in &["IMG_1.CR2", "IMG_1.xmp", "IMG_2.CR2"] {
+                std::fs::write(dir.join(name), name).unwrap();
+            }
+            let extensions = parse_companion_extensions(DEFAULT_COMPANION_EXTENSIONS);
+            let file_list_store = list_store();
+            add_files_to_file_list(
+                &file_list_store,
+                &[dir.join("IMG_1.CR2"), dir.join("IMG_2.CR2")],
+            );
+            pin_new_names_of_file_list(
+                &file_list_store,
+                &["a.CR2".to_string(), "IMG_2.CR2".to_string()],
+            );
+
+            set_companions_to_file_list(&file_list_store, &extensions);
+            assert_eq!(
+                list_store_data_iter(&file_list_store)
+                    .map(|v| (
+                        value2string(&v[0]),
+                        value2string(&v[1]),
+                        v[5].get::<bool>().unwrap()
+                    ))
+                    .collect::<Vec<_>>(),
+                vec![
+                    ("IMG_1.CR2".to_string(), "a.CR2".to_string(), false),
+                    ("IMG_1.xmp".to_string(), "a.xmp".to_string(), true),
+                    ("IMG_2.CR2".to_string(), "IMG_2.CR2".to_string(), false),
+                ]
+            );
+            assert_eq!(
+                get_new_names_from_file_list(&file_list_store),
+                vec!["a.CR2".to_string(), "IMG_2.CR2".to_string()]
+            );
+            assert_eq!(get_files_from_file_list(&file_list_store).count(), 2);
+
+            // The companion files are replaced rather than added again.
+            pin_new_names_of_file_list(
+                &file_list_store,
+                &["b.CR2".to_string(), "IMG_2.CR2".to_string()],
+            );
+            set_companions_to_file_list(&file_list_store, &extensions);
+            assert_eq!(
+                get_new_names_from_file_list(&file_list_store),
+                vec!["b.CR2".to_string(), "IMG_2.CR2".to_string()]
+            );
+            assert_eq!(
+                file_list_store
+                    .value(&file_list_store.iter_nth_child(None, 1).unwrap(), 1)
+                    .get(),
+                Ok(Some(String::from("b.xmp")))
+            );
+            assert_eq!(file_list_store.iter_n_children(None), 3);
+
+            set_companions_to_file_list(&file_list_store, &[]);
+            assert_eq!(file_list_store.iter_n_children(None), 2);
+        });
+    }
+
     #[test]
     fn test_reset_renaming_of_file_list() {
         test_synced(move || {
@@ -268,6 +605,28 @@ mod test {
                 file_list_store.value(&iter, 2).get(),
                 Ok(Some(String::from("/")))
             );
+
+            let iter = file_list_store.append();
+            file_list_store.set(
+                &iter,
+                &[
+                    (0, &"test3".to_string()),
+                    (1, &"test4".to_string()),
+                    (2, &"/".to_string()),
+                ],
+            );
+            pin_new_names_of_file_list(
+                &file_list_store,
+                &["manual".to_string(), "test4".to_string()],
+            );
+            file_list_store.set(&iter, &[(1, &"test5".to_string())]);
+
+            reset_renaming_of_file_list(&file_list_store);
+
+            assert_eq!(
+                get_new_names_from_file_list(&file_list_store),
+                vec!["manual".to_string(), "test3".to_string()]
+            );
         });
     }
 
@@ -309,6 +668,28 @@ mod test {
                 file_list_store.value(&iter, 2).get(),
                 Ok(Some(String::from("/")))
             );
+
+            assert_eq!(file_list_store.value(&iter, 4).get(), Ok(true));
+
+            assert!(!has_pinned_new_names(&file_list_store));
+            pin_new_names_of_file_list(&file_list_store, &["manual".to_string()]);
+            assert!(has_pinned_new_names(&file_list_store));
+            apply_renamer_to_file_list(&file_list_store, RenamerTarget::All, test_renamer.clone())
+                .unwrap();
+            assert_eq!(
+                get_new_names_from_file_list(&file_list_store),
+                vec!["manual".to_string()]
+            );
+            assert_eq!(file_list_store.value(&iter, 4).get(), Ok(false));
+
+            unpin_new_names_of_file_list(&file_list_store);
+            assert!(!has_pinned_new_names(&file_list_store));
+            apply_renamer_to_file_list(&file_list_store, RenamerTarget::All, test_renamer.clone())
+                .unwrap();
+            assert_eq!(
+                get_new_names_from_file_list(&file_list_store),
+                vec!["ABC-test".to_string()]
+            );
         });
     }
 }
@@ -2,8 +2,11 @@ use gtk::prelude::IsA;
 use gtk::{Application, ApplicationWindow};
 use std::path::PathBuf;
 
+mod editor;
 mod file_list;
+mod progress_dialog;
 mod provider;
+mod report_dialog;
 mod resource;
 mod window;
 
@@ -0,0 +1,153 @@
+use crate::basic_bulk_renamer::{RenamePhase, RenameProgress};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, Dialog, Inhibit, Label, ProgressBar, ResponseType, Window};
+use std::cell::Cell;
+use std::rc::Rc;
+
+const ID_PROGRESS_DIALOG: &'static str = "progress-dialog";
+const ID_PROGRESS_PHASE_LABEL: &'static str = "progress-phase-label";
+const ID_PROGRESS_BAR: &'static str = "progress-bar";
+const ID_PROGRESS_FILE_LABEL: &'static str = "progress-file-label";
+
+/// Dialog to show the progress of renaming
+pub(super) struct ProgressDialog {
+    builder: Builder,
+    finished: Rc<Cell<bool>>,
+}
+
+impl ProgressDialog {
+    pub fn new<P: IsA<Window>>(parent: Option<&P>) -> Self {
+        let builder = Builder::from_resource(&resource_path("progress_dialog.glade"));
+        let finished = Rc::new(Cell::new(false));
+        let progress_dialog = Self { builder, finished };
+
+        let dialog = progress_dialog.dialog();
+        dialog.set_transient_for(parent);
+
+        let finished = progress_dialog.finished.clone();
+        dialog.connect_delete_event(move |dialog, _| {
+            if finished.get() {
+                Inhibit(false)
+            } else {
+                // Keep the dialog until renaming stops; the escape key works as cancel.
+                dialog.response(ResponseType::Cancel);
+                Inhibit(true)
+            }
+        });
+
+        progress_dialog
+    }
+
+    /// Set the callback called when the user requests cancellation.
+    pub fn connect_cancel<F: Fn() + 'static>(&self, f: F) {
+        let phase_label = self.object::<Label>(ID_PROGRESS_PHASE_LABEL);
+        self.dialog().connect_response(move |dialog, response| {
+            if response == ResponseType::Cancel {
+                dialog.set_response_sensitive(ResponseType::Cancel, false);
+                phase_label.set_text("Cancelling");
+                f();
+            }
+        });
+    }
+
+    /// Show the progress. `rollback` is `true` while restoring the files after cancellation.
+    pub fn set_progress(&self, progress: &RenameProgress, rollback: bool) {
+        let phase_label = self.object::<Label>(ID_PROGRESS_PHASE_LABEL);
+        let progress_bar = self.object::<ProgressBar>(ID_PROGRESS_BAR);
+        let file_label = self.object::<Label>(ID_PROGRESS_FILE_LABEL);
+
+        phase_label.set_text(if rollback {
+            "Rolling back"
+        } else {
+            match progress.phase {
+                RenamePhase::Prepare => "Preparing",
+                RenamePhase::Rename => "Renaming",
+            }
+        });
+        progress_bar.set_fraction(progress_fraction(progress));
+        progress_bar.set_text(Some(
+            format!("{} / {}", progress.done, progress.total).as_str(),
+        ));
+        file_label.set_text(progress.current.display().to_string().as_str());
+    }
+
+    pub fn show(&self) {
+        self.dialog().show();
+    }
+
+    pub fn close(&self) {
+        self.finished.set(true);
+        self.dialog().close();
+    }
+
+    fn dialog(&self) -> Dialog {
+        self.object(ID_PROGRESS_DIALOG)
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+/// Returns the fraction of the progress through both phases.
+fn progress_fraction(progress: &RenameProgress) -> f64 {
+    if progress.total == 0 {
+        return 0.0;
+    }
+    let phase_index = match progress.phase {
+        RenamePhase::Prepare => 0,
+        RenamePhase::Rename => 1,
+    };
+    (phase_index * progress.total + progress.done) as f64 / (2 * progress.total) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use std::path::PathBuf;
+
+    fn progress(phase: RenamePhase, done: usize, total: usize) -> RenameProgress {
+        RenameProgress {
+            phase,
+            done,
+            total,
+            current: PathBuf::from("/tmp/1.txt"),
+        }
+    }
+
+    #[test]
+    fn test_progress_fraction() {
+        assert_eq!(
+            progress_fraction(&progress(RenamePhase::Prepare, 0, 0)),
+            0.0
+        );
+        assert_eq!(
+            progress_fraction(&progress(RenamePhase::Prepare, 0, 4)),
+            0.0
+        );
+        assert_eq!(
+            progress_fraction(&progress(RenamePhase::Prepare, 2, 4)),
+            0.25
+        );
+        assert_eq!(progress_fraction(&progress(RenamePhase::Rename, 0, 4)), 0.5);
+        assert_eq!(progress_fraction(&progress(RenamePhase::Rename, 4, 4)), 1.0);
+    }
+
+    #[test]
+    fn test_progress_dialog() {
+        test_synced(move || {
+            let progress_dialog = ProgressDialog::new::<Window>(None);
+            let phase_label = progress_dialog.object::<Label>(ID_PROGRESS_PHASE_LABEL);
+            let file_label = progress_dialog.object::<Label>(ID_PROGRESS_FILE_LABEL);
+
+            progress_dialog.set_progress(&progress(RenamePhase::Rename, 1, 2), false);
+            assert_eq!(phase_label.text().as_str(), "Renaming");
+            assert_eq!(file_label.text().as_str(), "/tmp/1.txt");
+
+            progress_dialog.set_progress(&progress(RenamePhase::Rename, 1, 2), true);
+            assert_eq!(phase_label.text().as_str(), "Rolling back");
+        });
+    }
+}
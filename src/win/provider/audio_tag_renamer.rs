@@ -0,0 +1,492 @@
+use crate::error::Error;
+use crate::utils::{format_count, read_audio_tags, split_file_at_dot, AudioTagField, AudioTags};
+use crate::utils::{set_unknown_placeholders_icon, PlaceholderTemplate, TemplateItem};
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, Container, Entry, Label};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::vec::IntoIter;
+
+const ID_AUDIO_TAG_RENAMER_PANEL: &'static str = "audio-tag-renamer-panel";
+const ID_AUDIO_TAG_TEMPLATE_ENTRY: &'static str = "audio-tag-template-entry";
+const ID_MISSING_TAG_ENTRY: &'static str = "missing-tag-entry";
+const ID_AUDIO_TAG_STATUS_LABEL: &'static str = "audio-tag-status-label";
+
+/// Maximum number of the names listed in the status
+const MAX_LISTED_NAMES: usize = 10;
+
+/// `{tracknumber:02}`: the tag, of which number is padded to the width
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct AudioTagPlaceholder {
+    field: AudioTagField,
+    width: usize,
+    zero_padded: bool,
+}
+
+/// Template of the name with the tags such as `{tracknumber:02} - {artist} - {title}`
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct AudioTagTemplate(PlaceholderTemplate<AudioTagPlaceholder>);
+
+impl AudioTagTemplate {
+    fn parse(template: &str) -> Self {
+        Self(PlaceholderTemplate::parse(
+            template,
+            Self::parse_placeholder,
+        ))
+    }
+
+    /// Parse the inside of `{…}` such as `artist` or `tracknumber:02`.
+    fn parse_placeholder(text: &str) -> Option<AudioTagPlaceholder> {
+        let (name, format) = match text.split_once(':') {
+            Some((name, format)) => (name, format),
+            None => (text, ""),
+        };
+        let field = AudioTagField::from_str(name).ok()?;
+        let width = if format.is_empty() {
+            0
+        } else if format.chars().all(|c| c.is_ascii_digit()) {
+            format.parse().ok()?
+        } else {
+            return None;
+        };
+
+        Some(AudioTagPlaceholder {
+            field,
+            width,
+            zero_padded: format.starts_with('0'),
+        })
+    }
+
+    /// Expand the tags. Returns the text with the placeholder text for the missing tags and the
+    /// missing tags.
+    fn expand(&self, tags: &AudioTags, placeholder: &str) -> (String, Vec<AudioTagField>) {
+        let mut missing_fields = Vec::new();
+        let text = self
+            .0
+            .items
+            .iter()
+            .map(|item| match item {
+                TemplateItem::Text(text) => text.clone(),
+                TemplateItem::Placeholder(AudioTagPlaceholder {
+                    field,
+                    width,
+                    zero_padded,
+                }) => match tags.get(*field) {
+                    // The numbers are reformatted only if the width is given.
+                    Some(value) => match value.parse::<u64>() {
+                        Ok(number) if *width > 0 && *zero_padded => {
+                            format!("{:0width$}", number, width = width)
+                        }
+                        Ok(number) if *width > 0 => format!("{:width$}", number, width = width),
+                        // "/" is not allowed in the file name
+                        _ => value.replace(['/', '\0'], "_"),
+                    },
+                    None => {
+                        if !missing_fields.contains(field) {
+                            missing_fields.push(*field);
+                        }
+                        placeholder.to_string()
+                    }
+                },
+            })
+            .collect();
+        (text, missing_fields)
+    }
+}
+
+struct AudioTagRule {
+    template: AudioTagTemplate,
+    /// Text for the missing tags
+    placeholder: String,
+}
+
+pub struct AudioTagRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl AudioTagRenamer {
+    pub fn new() -> Self {
+        let builder = Builder::from_resource(&resource_path("provider/audio_tag_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::AudioTag;
+        let audio_tag_template_entry = self.object::<Entry>(ID_AUDIO_TAG_TEMPLATE_ENTRY);
+        let missing_tag_entry = self.object::<Entry>(ID_MISSING_TAG_ENTRY);
+
+        let change_subject = self.change_subject.clone();
+        audio_tag_template_entry.connect_changed(move |audio_tag_template_entry| {
+            let template = AudioTagTemplate::parse(audio_tag_template_entry.text().as_str());
+            set_unknown_placeholders_icon(
+                audio_tag_template_entry,
+                &template.0.unknown_placeholders,
+            );
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        missing_tag_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    fn get_replacement_rule(&self) -> AudioTagRule {
+        let audio_tag_template_entry = self.object::<Entry>(ID_AUDIO_TAG_TEMPLATE_ENTRY);
+        let missing_tag_entry = self.object::<Entry>(ID_MISSING_TAG_ENTRY);
+
+        AudioTagRule {
+            template: AudioTagTemplate::parse(audio_tag_template_entry.text().as_str()),
+            placeholder: missing_tag_entry.text().to_string(),
+        }
+    }
+
+    /// Rename the files with the tags. Returns the new names and the missing tags of each file.
+    /// The files without tags such as the cover images are kept.
+    fn apply_replace_with(
+        rule: &AudioTagRule,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> (IntoIter<(String, String)>, Vec<Vec<AudioTagField>>) {
+        let mut missing_tags = Vec::new();
+        let renamed_files = files
+            .iter()
+            .map(|(file_name, dir_name)| {
+                let path = PathBuf::from(dir_name).join(file_name);
+                let tags = File::open(path)
+                    .and_then(|file| read_audio_tags(&mut BufReader::new(&file)))
+                    .ok()
+                    .flatten();
+
+                if let Some(tags) = tags {
+                    let (text, missing_fields) =
+                        rule.template.expand(&tags, rule.placeholder.as_str());
+                    missing_tags.push(missing_fields);
+                    let new_file_name = match target {
+                        RenamerTarget::Name => match split_file_at_dot(file_name.as_str()) {
+                            (_, Some(suffix)) => [text.as_str(), suffix].join("."),
+                            (_, None) => text,
+                        },
+                        RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
+                            (stem, Some(_)) => [stem, text.as_str()].join("."),
+                            (stem, None) => stem.to_string(),
+                        },
+                        RenamerTarget::All => text,
+                    };
+                    (new_file_name, dir_name.clone())
+                } else {
+                    missing_tags.push(Vec::new());
+                    (file_name.to_string(), dir_name.clone())
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+        (renamed_files, missing_tags)
+    }
+
+    fn set_status(&self, text: &str) {
+        let audio_tag_status_label = self.object::<Label>(ID_AUDIO_TAG_STATUS_LABEL);
+        audio_tag_status_label.set_text(text);
+        audio_tag_status_label.set_tooltip_text(Some(text));
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for AudioTagRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_AUDIO_TAG_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        self.apply_replacement_with_flags(files, target)
+            .map(|(renamed_files, _)| renamed_files)
+    }
+
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        let rule = self.get_replacement_rule();
+        let (renamed_files, missing_tags) = Self::apply_replace_with(&rule, files, target);
+        let changed = files
+            .iter()
+            .zip(renamed_files.as_slice())
+            .filter(|((file_name, _), (new_file_name, _))| file_name != new_file_name)
+            .count();
+        let missing_tags_of_files = files
+            .iter()
+            .zip(missing_tags.iter())
+            .filter(|(_, fields)| !fields.is_empty())
+            .map(|((file_name, _), fields)| (file_name.as_str(), fields.as_slice()))
+            .collect::<Vec<_>>();
+        self.set_status(format_status(changed, &missing_tags_of_files).as_str());
+        // The files with the missing tags are flagged in the file list.
+        let flags = missing_tags
+            .iter()
+            .map(|fields| !fields.is_empty())
+            .collect();
+        Ok((renamed_files, flags))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+fn format_status(changed: usize, missing_tags: &[(&str, &[AudioTagField])]) -> String {
+    let status = format!("{} to change", format_count(changed, "name"));
+    if missing_tags.is_empty() {
+        status
+    } else {
+        let mut listed = missing_tags
+            .iter()
+            .take(MAX_LISTED_NAMES)
+            .map(|(name, fields)| {
+                let fields = fields
+                    .iter()
+                    .map(|&field| field.into())
+                    .collect::<Vec<&'static str>>();
+                format!("{} ({})", name, fields.join(", "))
+            })
+            .collect::<Vec<_>>();
+        if missing_tags.len() > MAX_LISTED_NAMES {
+            listed.push("…".to_string());
+        }
+        format!(
+            "{}, \u{26a0} missing tags in {}: {}",
+            status,
+            format_count(missing_tags.len(), "name"),
+            listed.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::{EntryIconPosition, Window};
+    use std::path::Path;
+
+    fn write_flac(path: &Path, comments: &[&str]) {
+        let mut comment = Vec::new();
+        comment.extend_from_slice(&0u32.to_le_bytes());
+        comment.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for v in comments {
+            comment.extend_from_slice(&(v.len() as u32).to_le_bytes());
+            comment.extend_from_slice(v.as_bytes());
+        }
+        let mut data = b"fLaC".to_vec();
+        data.extend_from_slice(&(0x84_000000u32 | comment.len() as u32).to_be_bytes());
+        data.extend(comment);
+        std::fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_audio_tag_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let audio_tag_renamer = AudioTagRenamer::new();
+            let audio_tag_template_entry =
+                audio_tag_renamer.object::<Entry>(ID_AUDIO_TAG_TEMPLATE_ENTRY);
+            let missing_tag_entry = audio_tag_renamer.object::<Entry>(ID_MISSING_TAG_ENTRY);
+
+            audio_tag_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&audio_tag_renamer.get_panel())
+                .build()
+                .show_all();
+
+            counter_observer.reset();
+            audio_tag_template_entry.set_text("{track} - {title}");
+            assert_eq!(counter_observer.count(), 1);
+            assert!(audio_tag_template_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_some());
+
+            counter_observer.reset();
+            audio_tag_template_entry.set_text("{tracknumber:02} - {artist} - {title}");
+            missing_tag_entry.set_text("none");
+            assert_eq!(counter_observer.count(), 2);
+            assert!(audio_tag_template_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_none());
+
+            let temp_dir = tempfile::tempdir().unwrap();
+            write_flac(
+                temp_dir.path().join("track01.flac").as_path(),
+                &["TRACKNUMBER=1", "ARTIST=Band", "TITLE=Intro"],
+            );
+            write_flac(
+                temp_dir.path().join("track02.flac").as_path(),
+                &["TRACKNUMBER=2/12"],
+            );
+            std::fs::write(temp_dir.path().join("cover.jpg"), "cover").unwrap();
+            let dir_name = temp_dir.path().to_str().unwrap().to_string();
+            let files = [
+                ("track01.flac".to_string(), dir_name.clone()),
+                ("track02.flac".to_string(), dir_name.clone()),
+                ("cover.jpg".to_string(), dir_name.clone()),
+            ];
+            let renamed_files = audio_tag_renamer
+                .apply_replacement(&files, RenamerTarget::Name)
+                .unwrap()
+                .collect::<Vec<_>>();
+            assert_eq!(
+                renamed_files,
+                vec![
+                    ("01 - Band - Intro.flac".to_string(), dir_name.clone()),
+                    ("02 - none - none.flac".to_string(), dir_name.clone()),
+                    ("cover.jpg".to_string(), dir_name.clone()),
+                ]
+            );
+            assert_eq!(
+                audio_tag_renamer
+                    .object::<Label>(ID_AUDIO_TAG_STATUS_LABEL)
+                    .text(),
+                "2 names to change, \u{26a0} missing tags in 1 name: track02.flac (artist, title)"
+            );
+            let (_, flags) = audio_tag_renamer
+                .apply_replacement_with_flags(&files, RenamerTarget::Name)
+                .unwrap();
+            assert_eq!(flags, vec![false, true, false]);
+        });
+    }
+
+    #[test]
+    fn test_audio_tag_template_parse() {
+        assert_eq!(
+            AudioTagTemplate::parse("{tracknumber:02} - {{{title}}} {track} {album:x}"),
+            AudioTagTemplate(PlaceholderTemplate {
+                items: vec![
+                    TemplateItem::Placeholder(AudioTagPlaceholder {
+                        field: AudioTagField::TrackNumber,
+                        width: 2,
+                        zero_padded: true,
+                    }),
+                    TemplateItem::Text(" - {".to_string()),
+                    TemplateItem::Placeholder(AudioTagPlaceholder {
+                        field: AudioTagField::Title,
+                        width: 0,
+                        zero_padded: false,
+                    }),
+                    TemplateItem::Text("} {track} {album:x}".to_string()),
+                ],
+                unknown_placeholders: vec!["track".to_string(), "album:x".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_audio_tag_renamer_apply_replace_with() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_flac(
+            temp_dir.path().join("01.flac").as_path(),
+            &[
+                "TRACKNUMBER=1",
+                "DISCNUMBER=2",
+                "ARTIST=AC/DC",
+                "TITLE=Song",
+            ],
+        );
+        let dir_name = temp_dir.path().to_str().unwrap().to_string();
+        let files = [("01.flac".to_string(), dir_name.clone())];
+        let rule = AudioTagRule {
+            template: AudioTagTemplate::parse("{discnumber}-{tracknumber:3} {artist} {title}"),
+            placeholder: "Unknown".to_string(),
+        };
+
+        let (renamed_files, missing_tags) =
+            AudioTagRenamer::apply_replace_with(&rule, &files, RenamerTarget::Name);
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![("2-  1 AC_DC Song.flac".to_string(), dir_name.clone())]
+        );
+        assert_eq!(missing_tags, vec![Vec::<AudioTagField>::new()]);
+
+        let (renamed_files, _) =
+            AudioTagRenamer::apply_replace_with(&rule, &files, RenamerTarget::Suffix);
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![("01.2-  1 AC_DC Song".to_string(), dir_name.clone())]
+        );
+
+        let rule = AudioTagRule {
+            template: AudioTagTemplate::parse("{album} - {title}"),
+            placeholder: "Unknown".to_string(),
+        };
+        let (renamed_files, missing_tags) =
+            AudioTagRenamer::apply_replace_with(&rule, &files, RenamerTarget::All);
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![("Unknown - Song".to_string(), dir_name.clone())]
+        );
+        assert_eq!(missing_tags, vec![vec![AudioTagField::Album]]);
+
+        // The numbers are kept as they are without the width.
+        write_flac(
+            temp_dir.path().join("02.flac").as_path(),
+            &["TRACKNUMBER=02", "TITLE=007"],
+        );
+        let files = [("02.flac".to_string(), dir_name.clone())];
+        let rule = AudioTagRule {
+            template: AudioTagTemplate::parse("{tracknumber} {title} {tracknumber:3}"),
+            placeholder: "Unknown".to_string(),
+        };
+        let (renamed_files, _) =
+            AudioTagRenamer::apply_replace_with(&rule, &files, RenamerTarget::Name);
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![("02 007   2.flac".to_string(), dir_name.clone())]
+        );
+    }
+
+    #[test]
+    fn test_format_status() {
+        assert_eq!(format_status(3, &[]), "3 names to change");
+        assert_eq!(format_status(1, &[]), "1 name to change");
+        assert_eq!(
+            format_status(
+                2,
+                &[
+                    ("01.mp3", &[AudioTagField::Artist][..]),
+                    (
+                        "02.mp3",
+                        &[AudioTagField::TrackNumber, AudioTagField::AlbumArtist][..]
+                    ),
+                ]
+            ),
+            "2 names to change, \u{26a0} missing tags in 2 names: 01.mp3 (artist), 02.mp3 (tracknumber, albumartist)"
+        );
+    }
+}
@@ -1,20 +1,56 @@
 use crate::error::Error;
-use crate::utils::split_file_at_dot;
+use crate::utils::{format_count, split_file_at_dot};
 use crate::utils::{Observer, SubjectImpl};
 use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
 use crate::win::resource::resource_path;
 use gtk::prelude::*;
-use gtk::{Builder, ComboBox, Container};
+use gtk::{Builder, CheckButton, ComboBox, ComboBoxText, Container, Entry, Label};
 use heck::*;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::str::FromStr;
 use std::vec::IntoIter;
 use strum_macros::EnumString;
+use unicode_normalization::char::is_combining_mark;
 
 const ID_CHANGE_CASE_RENAMER_PANEL: &'static str = "change-case-renamer-panel";
 const ID_CHANGE_CASE_COMBO_BOX: &'static str = "change-case-combo-box";
+const ID_SMALL_WORDS_LANGUAGE_COMBO_BOX: &'static str = "small-words-language-combo-box";
+const ID_SMALL_WORDS_ENTRY: &'static str = "small-words-entry";
+const ID_PROTECTED_WORDS_ENTRY: &'static str = "protected-words-entry";
+const ID_PRESERVE_NON_WORD_CHECK_BUTTON: &'static str = "preserve-non-word-check-button";
+const ID_CHANGE_CASE_STATUS_LABEL: &'static str = "change-case-status-label";
 
-#[derive(Clone, Copy, Eq, PartialEq, EnumString)]
+/// Words kept in lowercase by the title case for each language
+const SMALL_WORDS: &[(&str, &str)] = &[
+    (
+        "English",
+        "a, an, and, as, at, but, by, for, from, in, into, nor, of, on, or, per, the, to, via, vs, with",
+    ),
+    (
+        "French",
+        "à, au, aux, de, des, du, en, et, la, le, les, ou, par, pour, sur, un, une",
+    ),
+    (
+        "German",
+        "am, an, auf, aus, das, dem, den, der, des, die, ein, eine, einer, eines, für, im, in, mit, oder, und, vom, von, zu, zum, zur",
+    ),
+    (
+        "Spanish",
+        "a, al, con, de, del, e, el, en, la, las, los, o, para, por, u, un, una, y",
+    ),
+    (
+        "Italian",
+        "a, al, con, da, dal, dei, del, della, di, e, il, in, la, le, lo, o, per, su, un, una",
+    ),
+    (
+        "Dutch",
+        "aan, de, een, en, het, in, met, of, op, te, van, voor",
+    ),
+    ("None", ""),
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
 enum ChangeCaseKind {
     Uppercase,
     Lowercase,
@@ -25,14 +61,21 @@ enum ChangeCaseKind {
     ShoutySnakeCase,
     MixedCase,
     TitleCase,
+    /// Title case keeping the separators and the punctuation
+    SmartTitleCase,
+    SentenceCase,
+    ToggleCase,
 }
 
 impl ChangeCaseKind {
-    pub fn apply<T: ToString>(&self, text: T) -> String {
+    pub fn apply<T: ToString>(&self, text: T, words: &CaseWords) -> String {
         let text = text.to_string();
         match self {
-            ChangeCaseKind::Uppercase => text.to_uppercase(),
-            ChangeCaseKind::Lowercase => text.to_lowercase(),
+            ChangeCaseKind::Uppercase
+            | ChangeCaseKind::Lowercase
+            | ChangeCaseKind::SmartTitleCase
+            | ChangeCaseKind::SentenceCase
+            | ChangeCaseKind::ToggleCase => self.apply_to_words(text.as_str(), words),
             ChangeCaseKind::FirstLetterUppercase => text.to_first_letter_uppercase(),
             ChangeCaseKind::CamelCase => text.to_upper_camel_case(),
             ChangeCaseKind::SnakeCase => text.to_snake_case(),
@@ -42,6 +85,258 @@ impl ChangeCaseKind {
             ChangeCaseKind::TitleCase => text.to_title_case(),
         }
     }
+
+    /// Returns `true` if the kind splits the text into the words and joins them again.
+    fn is_tokenizing(&self) -> bool {
+        matches!(
+            self,
+            ChangeCaseKind::CamelCase
+                | ChangeCaseKind::SnakeCase
+                | ChangeCaseKind::KebabCase
+                | ChangeCaseKind::ShoutySnakeCase
+                | ChangeCaseKind::MixedCase
+                | ChangeCaseKind::TitleCase
+        )
+    }
+
+    /// Change the case of each word, keeping the protected words and the separators.
+    fn apply_to_words(&self, text: &str, words: &CaseWords) -> String {
+        let segments = split_words(text);
+        let word_count = segments.iter().filter(|(_, is_word)| *is_word).count();
+        let mut new_text = String::with_capacity(text.len());
+        let mut word_index = 0;
+        let mut starts_phrase = true;
+
+        for (segment, is_word) in segments {
+            if !is_word {
+                new_text.push_str(
+                    match self {
+                        ChangeCaseKind::Uppercase => segment.to_uppercase(),
+                        ChangeCaseKind::Lowercase => segment.to_lowercase(),
+                        ChangeCaseKind::ToggleCase => segment.to_toggle_case(),
+                        _ => segment.to_string(),
+                    }
+                    .as_str(),
+                );
+                starts_phrase |= match self {
+                    ChangeCaseKind::SmartTitleCase => is_title_break(segment),
+                    ChangeCaseKind::SentenceCase => is_sentence_break(segment),
+                    _ => false,
+                };
+                continue;
+            }
+
+            word_index += 1;
+            let new_word = match (words.protected(segment), self) {
+                (Some(protected_word), _) => protected_word,
+                (None, ChangeCaseKind::Uppercase) => segment.to_uppercase(),
+                (None, ChangeCaseKind::Lowercase) => segment.to_lowercase(),
+                (None, ChangeCaseKind::ToggleCase) => segment.to_toggle_case(),
+                (None, ChangeCaseKind::SentenceCase) if starts_phrase => {
+                    segment.to_first_letter_uppercase()
+                }
+                (None, ChangeCaseKind::SentenceCase) => segment.to_lowercase(),
+                (None, _)
+                    if !starts_phrase && word_index != word_count && words.is_small(segment) =>
+                {
+                    segment.to_lowercase()
+                }
+                (None, _) if is_mixed_case(segment) => segment.to_string(),
+                (None, _) => segment.to_first_letter_uppercase(),
+            };
+            new_text.push_str(new_word.as_str());
+            starts_phrase = false;
+        }
+        new_text
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ChangeCaseRule {
+    kind: ChangeCaseKind,
+    words: CaseWords,
+    /// Change the case and the joins of the words only inside the alphanumeric runs
+    preserve_non_word: bool,
+}
+
+impl ChangeCaseRule {
+    fn apply(&self, text: &str) -> String {
+        if self.preserve_non_word && self.kind.is_tokenizing() {
+            split_alphanumeric_runs(text)
+                .into_iter()
+                .map(|(segment, is_run)| {
+                    if is_run {
+                        self.kind.apply(segment, &self.words)
+                    } else {
+                        segment.to_string()
+                    }
+                })
+                .collect()
+        } else {
+            self.kind.apply(text, &self.words)
+        }
+    }
+}
+
+impl From<ChangeCaseKind> for ChangeCaseRule {
+    fn from(kind: ChangeCaseKind) -> Self {
+        Self {
+            kind,
+            words: CaseWords::default(),
+            preserve_non_word: false,
+        }
+    }
+}
+
+/// Words handled specially by the case conversion
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct CaseWords {
+    /// Words kept in lowercase by the title case, in lowercase
+    small_words: Vec<String>,
+    /// Words kept as they are written
+    protected_words: Vec<String>,
+}
+
+impl CaseWords {
+    fn new(small_words: &str, protected_words: &str) -> Self {
+        Self {
+            small_words: parse_words(small_words).map(|v| v.to_lowercase()).collect(),
+            protected_words: parse_words(protected_words)
+                .map(|v| v.to_string())
+                .collect(),
+        }
+    }
+
+    fn is_small(&self, word: &str) -> bool {
+        self.small_words.contains(&word.to_lowercase())
+    }
+
+    /// Returns the protected word matching `word` ignoring case. The suffix after the apostrophe
+    /// such as "NASA's" is kept.
+    fn protected(&self, word: &str) -> Option<String> {
+        let find = |word: &str| {
+            let word = word.to_lowercase();
+            self.protected_words
+                .iter()
+                .find(|v| v.to_lowercase() == word)
+        };
+        if let Some(protected_word) = find(word) {
+            Some(protected_word.to_string())
+        } else {
+            let (stem, suffix) = word.split_at(word.find(&['\'', '’'][..])?);
+            find(stem).map(|protected_word| [protected_word.as_str(), suffix].concat())
+        }
+    }
+}
+
+/// Parse comma or space separated words.
+fn parse_words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|v| !v.is_empty())
+}
+
+/// Split the text into the words and the others. A word is a run of the alphanumeric
+/// characters which may contain apostrophes such as "don't".
+fn split_words(text: &str) -> Vec<(&str, bool)> {
+    let chars = text.char_indices().map(|(_, c)| c).collect::<Vec<_>>();
+    let is_alphanumeric = |c: char| c.is_alphanumeric() || is_combining_mark(c);
+    let is_word_char = |i: usize| match chars[i] {
+        '\'' | '’' => {
+            i > 0
+                && is_alphanumeric(chars[i - 1])
+                && matches!(chars.get(i + 1), Some(c) if is_alphanumeric(*c))
+        }
+        c => is_alphanumeric(c),
+    };
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (i, (pos, _)) in text.char_indices().enumerate().skip(1) {
+        if is_word_char(i) != is_word_char(i - 1) {
+            segments.push((&text[start..pos], is_word_char(i - 1)));
+            start = pos;
+        }
+    }
+    if !chars.is_empty() {
+        segments.push((&text[start..], is_word_char(chars.len() - 1)));
+    }
+    segments
+}
+
+/// Split the text into the runs of the alphanumeric characters joined by the spaces, `_` or
+/// `-`, and the others.
+fn split_alphanumeric_runs(text: &str) -> Vec<(&str, bool)> {
+    let is_alphanumeric = |c: char| c.is_alphanumeric() || is_combining_mark(c);
+    let is_joiner = |c: char| c.is_whitespace() || c == '_' || c == '-';
+
+    let mut segments = Vec::<(usize, usize, bool)>::new();
+    for (pos, c) in text.char_indices() {
+        let end = pos + c.len_utf8();
+        match segments.last_mut() {
+            Some((_, last_end, is_last_alphanumeric))
+                if *is_last_alphanumeric == is_alphanumeric(c) =>
+            {
+                *last_end = end
+            }
+            _ => segments.push((pos, end, is_alphanumeric(c))),
+        }
+    }
+
+    let mut runs = Vec::<(usize, usize, bool)>::new();
+    for (i, (start, end, is_alphanumeric)) in segments.iter().enumerate() {
+        let is_run = *is_alphanumeric
+            || (i > 0 && i + 1 < segments.len() && text[*start..*end].chars().all(is_joiner));
+        match runs.last_mut() {
+            Some((_, last_end, true)) if is_run => *last_end = *end,
+            _ => runs.push((*start, *end, is_run)),
+        }
+    }
+    runs.into_iter()
+        .map(|(start, end, is_run)| (&text[start..end], is_run))
+        .collect()
+}
+
+/// Returns the characters of `text` which are not in `new_text`, except the alphanumeric
+/// characters and the joiners of the words.
+fn find_removed_characters(text: &str, new_text: &str) -> Vec<char> {
+    let mut counts = BTreeMap::<char, isize>::new();
+    for c in text.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    for c in new_text.chars() {
+        *counts.entry(c).or_default() -= 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(c, count)| {
+            *count > 0 && !c.is_alphanumeric() && !c.is_whitespace() && !matches!(c, '_' | '-')
+        })
+        .map(|(c, _)| c)
+        .collect()
+}
+
+/// Returns `true` if the word after the separator starts a subtitle, e.g. after ": " or " - ".
+fn is_title_break(separator: &str) -> bool {
+    separator.contains(&[':', '.', '!', '?', '(', '[', '–', '—'][..])
+        || (separator.contains('-') && separator.contains(char::is_whitespace))
+}
+
+/// Returns `true` if the word after the separator starts a sentence, e.g. after ". ".
+fn is_sentence_break(separator: &str) -> bool {
+    separator.contains(&['.', '!', '?', '…'][..]) && separator.ends_with(char::is_whitespace)
+}
+
+/// Returns `true` if the word has an uppercase letter after a lowercase one such as "iPhone".
+fn is_mixed_case(word: &str) -> bool {
+    let mut lowercase_found = false;
+    for c in word.chars() {
+        if c.is_lowercase() {
+            lowercase_found = true;
+        } else if c.is_uppercase() && lowercase_found {
+            return true;
+        }
+    }
+    false
 }
 
 pub struct ChangeCaseRenamer {
@@ -59,6 +354,7 @@ impl ChangeCaseRenamer {
         };
 
         renamer.init_callback();
+        Self::update_sensitivity(&renamer.builder);
 
         renamer
     }
@@ -66,25 +362,106 @@ impl ChangeCaseRenamer {
     fn init_callback(&self) {
         let renamer_type = RenamerType::ChangeCase;
         let change_case_combo_box = self.object::<ComboBox>(ID_CHANGE_CASE_COMBO_BOX);
+        let small_words_language_combo_box =
+            self.object::<ComboBoxText>(ID_SMALL_WORDS_LANGUAGE_COMBO_BOX);
+        let small_words_entry = self.object::<Entry>(ID_SMALL_WORDS_ENTRY);
+        let protected_words_entry = self.object::<Entry>(ID_PROTECTED_WORDS_ENTRY);
+        let preserve_non_word_check_button =
+            self.object::<CheckButton>(ID_PRESERVE_NON_WORD_CHECK_BUTTON);
 
+        let builder = self.builder.clone();
         let change_subject = self.change_subject.clone();
         change_case_combo_box.connect_changed(move |_| {
+            Self::update_sensitivity(&builder);
+
             change_subject
                 .notify((renamer_type, ()))
                 .unwrap_or_default();
         });
+
+        // The change is notified by the entry.
+        small_words_language_combo_box.connect_changed(glib::clone!(
+            @weak small_words_entry => move |small_words_language_combo_box| {
+            let language = small_words_language_combo_box.active_id();
+            if let Some((_, small_words)) = SMALL_WORDS
+                .iter()
+                .find(|(id, _)| language.as_deref() == Some(*id))
+            {
+                small_words_entry.set_text(small_words);
+            }
+        }));
+
+        let change_subject = self.change_subject.clone();
+        small_words_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        protected_words_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        preserve_non_word_check_button.connect_toggled(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    /// Enable only the options used by the selected case.
+    fn update_sensitivity(builder: &Builder) {
+        let object = |name: &str| builder.object::<gtk::Widget>(name).unwrap();
+        let kind = builder
+            .object::<ComboBox>(ID_CHANGE_CASE_COMBO_BOX)
+            .unwrap()
+            .active_id()
+            .and_then(|id| ChangeCaseKind::from_str(id.as_str()).ok());
+
+        let is_title_case = kind == Some(ChangeCaseKind::SmartTitleCase);
+        object(ID_SMALL_WORDS_LANGUAGE_COMBO_BOX).set_sensitive(is_title_case);
+        object(ID_SMALL_WORDS_ENTRY).set_sensitive(is_title_case);
+        object(ID_PROTECTED_WORDS_ENTRY).set_sensitive(matches!(
+            kind,
+            Some(ChangeCaseKind::Uppercase)
+                | Some(ChangeCaseKind::Lowercase)
+                | Some(ChangeCaseKind::SmartTitleCase)
+                | Some(ChangeCaseKind::SentenceCase)
+                | Some(ChangeCaseKind::ToggleCase)
+        ));
+        object(ID_PRESERVE_NON_WORD_CHECK_BUTTON)
+            .set_sensitive(matches!(kind, Some(kind) if kind.is_tokenizing()));
     }
 
-    fn get_replacement_rule(&self) -> Option<ChangeCaseKind> {
+    fn get_replacement_rule(&self) -> Option<ChangeCaseRule> {
         let change_case_combo_box = self.object::<ComboBox>(ID_CHANGE_CASE_COMBO_BOX);
 
-        change_case_combo_box
+        let kind = change_case_combo_box
             .active_id()
-            .and_then(|id| ChangeCaseKind::from_str(id.as_str()).ok())
+            .and_then(|id| ChangeCaseKind::from_str(id.as_str()).ok())?;
+        let words = CaseWords::new(
+            self.object::<Entry>(ID_SMALL_WORDS_ENTRY).text().as_str(),
+            self.object::<Entry>(ID_PROTECTED_WORDS_ENTRY)
+                .text()
+                .as_str(),
+        );
+        let preserve_non_word = self
+            .object::<CheckButton>(ID_PRESERVE_NON_WORD_CHECK_BUTTON)
+            .is_active();
+
+        Some(ChangeCaseRule {
+            kind,
+            words,
+            preserve_non_word,
+        })
     }
 
     fn apply_replace_with(
-        change_case_kind: ChangeCaseKind,
+        rule: &ChangeCaseRule,
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> IntoIter<(String, String)> {
@@ -96,16 +473,16 @@ impl ChangeCaseRenamer {
                 let new_stem = match target {
                     RenamerTarget::Name | RenamerTarget::All => {
                         if stem.starts_with(".") {
-                            [".".to_string(), change_case_kind.apply(&stem[1..])].concat()
+                            [".".to_string(), rule.apply(&stem[1..])].concat()
                         } else {
-                            change_case_kind.apply(stem)
+                            rule.apply(stem)
                         }
                     }
                     RenamerTarget::Suffix => stem.to_string(),
                 };
                 let new_extension = extension.map(|suffix| match target {
                     RenamerTarget::Name => suffix.to_string(),
-                    RenamerTarget::Suffix | RenamerTarget::All => change_case_kind.apply(suffix),
+                    RenamerTarget::Suffix | RenamerTarget::All => rule.apply(suffix),
                 });
 
                 let new_file_name = if let Some(new_suffix) = new_extension {
@@ -119,6 +496,12 @@ impl ChangeCaseRenamer {
             .into_iter()
     }
 
+    fn set_status(&self, text: &str) {
+        let change_case_status_label = self.object::<Label>(ID_CHANGE_CASE_STATUS_LABEL);
+        change_case_status_label.set_text(text);
+        change_case_status_label.set_tooltip_text(Some(text));
+    }
+
     fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
         self.builder.object(name).unwrap()
     }
@@ -134,8 +517,20 @@ impl Renamer for ChangeCaseRenamer {
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> Result<IntoIter<(String, String)>, Error> {
-        let change_case_kind = self.get_replacement_rule().unwrap();
-        Ok(Self::apply_replace_with(change_case_kind, files, target))
+        self.apply_replacement_with_flags(files, target)
+            .map(|(renamed_files, _)| renamed_files)
+    }
+
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        let rule = self.get_replacement_rule().unwrap();
+        let renamed_files = Self::apply_replace_with(&rule, files, target);
+        self.set_status(format_status(files, renamed_files.as_slice()).as_str());
+        let flags = find_names_losing_characters(files, renamed_files.as_slice());
+        Ok((renamed_files, flags))
     }
 
     fn attach_change(&self, observer: Rc<dyn Observer<(RenamerType, ()), Error>>) {
@@ -143,8 +538,52 @@ impl Renamer for ChangeCaseRenamer {
     }
 }
 
+/// Returns `true` for each file of which some characters are removed by the case conversion.
+fn find_names_losing_characters(
+    files: &[(String, String)],
+    renamed_files: &[(String, String)],
+) -> Vec<bool> {
+    files
+        .iter()
+        .zip(renamed_files.iter())
+        .map(|((file_name, _), (new_file_name, _))| {
+            !find_removed_characters(file_name, new_file_name).is_empty()
+        })
+        .collect()
+}
+
+/// Returns the warning of the characters removed by the case conversion, or empty.
+fn format_status(files: &[(String, String)], renamed_files: &[(String, String)]) -> String {
+    let mut removed = Vec::new();
+    let mut count = 0;
+    for ((file_name, _), (new_file_name, _)) in files.iter().zip(renamed_files.iter()) {
+        let removed_characters = find_removed_characters(file_name, new_file_name);
+        if !removed_characters.is_empty() {
+            count += 1;
+            removed.extend(removed_characters);
+        }
+    }
+    removed.sort_unstable();
+    removed.dedup();
+
+    if removed.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\u{26a0} characters removed from {}: {}",
+            format_count(count, "name"),
+            removed
+                .into_iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+}
+
 trait CaseConversion: ToOwned {
     fn to_first_letter_uppercase(&self) -> Self::Owned;
+    fn to_toggle_case(&self) -> Self::Owned;
 }
 
 impl CaseConversion for str {
@@ -172,6 +611,20 @@ impl CaseConversion for str {
         }
         string
     }
+
+    fn to_toggle_case(&self) -> String {
+        let mut string = String::with_capacity(self.len());
+        for c in self.chars() {
+            if c.is_uppercase() {
+                string.extend(c.to_lowercase());
+            } else if c.is_lowercase() {
+                string.extend(c.to_uppercase());
+            } else {
+                string.push(c);
+            }
+        }
+        string
+    }
 }
 
 #[cfg(test)]
@@ -188,6 +641,13 @@ mod test {
             let change_case_renamer = ChangeCaseRenamer::new();
             let change_case_combo_box =
                 change_case_renamer.object::<ComboBox>(ID_CHANGE_CASE_COMBO_BOX);
+            let small_words_language_combo_box =
+                change_case_renamer.object::<ComboBoxText>(ID_SMALL_WORDS_LANGUAGE_COMBO_BOX);
+            let small_words_entry = change_case_renamer.object::<Entry>(ID_SMALL_WORDS_ENTRY);
+            let protected_words_entry =
+                change_case_renamer.object::<Entry>(ID_PROTECTED_WORDS_ENTRY);
+            let preserve_non_word_check_button =
+                change_case_renamer.object::<CheckButton>(ID_PRESERVE_NON_WORD_CHECK_BUTTON);
 
             change_case_renamer.attach_change(counter_observer.clone());
 
@@ -200,6 +660,49 @@ mod test {
             change_case_combo_box.clone().set_active(Some(1));
             gtk_test::wait(1);
             assert_eq!(counter_observer.count(), 1);
+            assert!(!small_words_entry.is_sensitive());
+            assert!(protected_words_entry.is_sensitive());
+
+            counter_observer.reset();
+            change_case_combo_box.set_active_id(Some("SmartTitleCase"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(small_words_entry.is_sensitive());
+
+            counter_observer.reset();
+            small_words_language_combo_box.set_active_id(Some("None"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(small_words_entry.text(), "");
+
+            counter_observer.reset();
+            protected_words_entry.set_text("iPhone, NASA");
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(
+                change_case_renamer.get_replacement_rule().unwrap().words,
+                CaseWords::new("", "iPhone NASA")
+            );
+            assert!(!preserve_non_word_check_button.is_sensitive());
+
+            counter_observer.reset();
+            change_case_combo_box.set_active_id(Some("SnakeCase"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(preserve_non_word_check_button.is_sensitive());
+
+            counter_observer.reset();
+            preserve_non_word_check_button.set_active(true);
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(
+                change_case_renamer.get_replacement_rule(),
+                Some(ChangeCaseRule {
+                    kind: ChangeCaseKind::SnakeCase,
+                    words: CaseWords::new("", "iPhone NASA"),
+                    preserve_non_word: true,
+                })
+            );
         });
     }
 
@@ -207,7 +710,7 @@ mod test {
     fn test_change_case_renamer_apply_replacement_with() {
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::Uppercase,
+                &ChangeCaseRule::from(ChangeCaseKind::Uppercase),
                 &[("Orig.txt".to_string(), "/tmp".to_string())],
                 RenamerTarget::All
             )
@@ -216,7 +719,7 @@ mod test {
         );
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::Lowercase,
+                &ChangeCaseRule::from(ChangeCaseKind::Lowercase),
                 &[("Orig.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Suffix
             )
@@ -226,7 +729,7 @@ mod test {
 
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::FirstLetterUppercase,
+                &ChangeCaseRule::from(ChangeCaseKind::FirstLetterUppercase),
                 &[("Original File Name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -236,7 +739,7 @@ mod test {
 
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::CamelCase,
+                &ChangeCaseRule::from(ChangeCaseKind::CamelCase),
                 &[("Original file name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -245,7 +748,7 @@ mod test {
         );
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::SnakeCase,
+                &ChangeCaseRule::from(ChangeCaseKind::SnakeCase),
                 &[("Original file name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -254,7 +757,7 @@ mod test {
         );
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::KebabCase,
+                &ChangeCaseRule::from(ChangeCaseKind::KebabCase),
                 &[("Original file name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -263,7 +766,7 @@ mod test {
         );
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::ShoutySnakeCase,
+                &ChangeCaseRule::from(ChangeCaseKind::ShoutySnakeCase),
                 &[("Original file name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -272,7 +775,7 @@ mod test {
         );
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::MixedCase,
+                &ChangeCaseRule::from(ChangeCaseKind::MixedCase),
                 &[("Original file name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -281,7 +784,7 @@ mod test {
         );
         assert_eq!(
             ChangeCaseRenamer::apply_replace_with(
-                ChangeCaseKind::TitleCase,
+                &ChangeCaseRule::from(ChangeCaseKind::TitleCase),
                 &[("Original file name.TXT".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -290,6 +793,143 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_change_case_kind_apply_to_words() {
+        let english = CaseWords::new(SMALL_WORDS[0].1, "iPhone, NASA");
+
+        assert_eq!(
+            ChangeCaseKind::SmartTitleCase.apply("the lord of the rings - part 1", &english),
+            "The Lord of the Rings - Part 1"
+        );
+        assert_eq!(
+            ChangeCaseKind::SmartTitleCase.apply("HOW TO USE AN IPHONE: a guide to", &english),
+            "How to Use an iPhone: A Guide To"
+        );
+        assert_eq!(
+            ChangeCaseKind::SmartTitleCase.apply("nasa's mcDonald_don't (the end)", &english),
+            "NASA's mcDonald_Don't (The End)"
+        );
+        assert_eq!(
+            ChangeCaseKind::SmartTitleCase
+                .apply("la vie en rose", &CaseWords::new(SMALL_WORDS[1].1, "")),
+            "La Vie en Rose"
+        );
+        assert_eq!(
+            ChangeCaseKind::SentenceCase.apply("HELLO WORLD. nasa IS here! ok", &english),
+            "Hello world. NASA is here! Ok"
+        );
+        assert_eq!(
+            ChangeCaseKind::ToggleCase.apply("Hello iPhone (Ä)", &english),
+            "hELLO iPhone (ä)"
+        );
+        assert_eq!(
+            ChangeCaseKind::Uppercase.apply("my nasa iphone", &english),
+            "MY NASA iPhone"
+        );
+        assert_eq!(
+            ChangeCaseKind::Lowercase.apply("My NASA-Photo", &english),
+            "my NASA-photo"
+        );
+        assert_eq!(
+            ChangeCaseKind::Lowercase.apply("My NASA-Photo", &CaseWords::default()),
+            "my nasa-photo"
+        );
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("don't-stop 'me' cafe\u{301}!"),
+            vec![
+                ("don't", true),
+                ("-", false),
+                ("stop", true),
+                (" '", false),
+                ("me", true),
+                ("' ", false),
+                ("cafe\u{301}", true),
+                ("!", false),
+            ]
+        );
+        assert_eq!(split_words(""), vec![]);
+    }
+
+    #[test]
+    fn test_change_case_rule_preserve_non_word() {
+        let rule = |kind| ChangeCaseRule {
+            kind,
+            words: CaseWords::default(),
+            preserve_non_word: true,
+        };
+        let text = "My Movie (2019) & Friends' cut_v2.final";
+
+        assert_eq!(
+            rule(ChangeCaseKind::SnakeCase).apply(text),
+            "my_movie (2019) & friends' cut_v2.final"
+        );
+        assert_eq!(
+            rule(ChangeCaseKind::CamelCase).apply(text),
+            "MyMovie (2019) & Friends' CutV2.Final"
+        );
+        assert_eq!(
+            rule(ChangeCaseKind::KebabCase).apply("A.B (C D)"),
+            "a.b (c-d)"
+        );
+        assert_eq!(
+            rule(ChangeCaseKind::Uppercase).apply(text),
+            "MY MOVIE (2019) & FRIENDS' CUT_V2.FINAL"
+        );
+        assert_eq!(
+            ChangeCaseRule::from(ChangeCaseKind::SnakeCase).apply(text),
+            "my_movie_2019_friends_cut_v2_final"
+        );
+    }
+
+    #[test]
+    fn test_split_alphanumeric_runs() {
+        assert_eq!(
+            split_alphanumeric_runs(" a b_c (d) -e- "),
+            vec![
+                (" ", false),
+                ("a b_c", true),
+                (" (", false),
+                ("d", true),
+                (") -", false),
+                ("e", true),
+                ("- ", false),
+            ]
+        );
+        assert_eq!(split_alphanumeric_runs(""), vec![]);
+    }
+
+    #[test]
+    fn test_format_status() {
+        let files = [
+            ("A (1).txt".to_string(), "/tmp".to_string()),
+            ("b-c.txt".to_string(), "/tmp".to_string()),
+            ("d & e.txt".to_string(), "/tmp".to_string()),
+        ];
+        let renamed_files = [
+            ("a_1.txt".to_string(), "/tmp".to_string()),
+            ("b_c.txt".to_string(), "/tmp".to_string()),
+            ("d_e.txt".to_string(), "/tmp".to_string()),
+        ];
+
+        assert_eq!(
+            format_status(&files, &renamed_files),
+            "\u{26a0} characters removed from 2 names: & ( )"
+        );
+        assert_eq!(
+            format_status(&files[..1], &renamed_files[..1]),
+            "\u{26a0} characters removed from 1 name: ( )"
+        );
+        assert_eq!(format_status(&files, &files), "");
+        assert_eq!(
+            find_names_losing_characters(&files, &renamed_files),
+            vec![true, false, true]
+        );
+    }
+
     #[test]
     fn test_char_conversion_to_first_letter_uppercase() {
         assert_eq!("".to_first_letter_uppercase(), "");
@@ -302,4 +942,10 @@ mod test {
             "+First letter uppercase"
         );
     }
+
+    #[test]
+    fn test_char_conversion_to_toggle_case() {
+        assert_eq!("".to_toggle_case(), "");
+        assert_eq!("Hello World 1".to_toggle_case(), "hELLO wORLD 1");
+    }
 }
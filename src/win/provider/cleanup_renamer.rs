@@ -0,0 +1,389 @@
+use crate::error::Error;
+use crate::utils::split_file_at_dot;
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, CheckButton, ComboBoxText, Container};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use strum_macros::EnumString;
+
+const ID_CLEANUP_RENAMER_PANEL: &'static str = "cleanup-renamer-panel";
+const ID_TRIM_CHECK_BUTTON: &'static str = "trim-check-button";
+const ID_COLLAPSE_SPACES_CHECK_BUTTON: &'static str = "collapse-spaces-check-button";
+const ID_REMOVE_INVISIBLE_CHECK_BUTTON: &'static str = "remove-invisible-check-button";
+const ID_TRIM_EXTENSION_DOT_CHECK_BUTTON: &'static str = "trim-extension-dot-check-button";
+const ID_STRIP_TRAILING_DOTS_CHECK_BUTTON: &'static str = "strip-trailing-dots-check-button";
+const ID_CONVERT_FROM_COMBO_BOX: &'static str = "convert-from-combo-box";
+const ID_CONVERT_TO_COMBO_BOX: &'static str = "convert-to-combo-box";
+
+/// Separator of the words
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum Separator {
+    Space,
+    Underscore,
+    Hyphen,
+    Dot,
+    /// Any of the separators above; only for the source of the conversion
+    Any,
+}
+
+impl Separator {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Separator::Any => " _-.".contains(c),
+            _ => Some(c) == self.as_char(),
+        }
+    }
+
+    fn as_char(&self) -> Option<char> {
+        match self {
+            Separator::Space => Some(' '),
+            Separator::Underscore => Some('_'),
+            Separator::Hyphen => Some('-'),
+            Separator::Dot => Some('.'),
+            Separator::Any => None,
+        }
+    }
+}
+
+/// Options of the cleanup
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct CleanupRule {
+    /// Trim the leading and trailing whitespace
+    trim: bool,
+    /// Collapse the runs of whitespace into a space
+    collapse_spaces: bool,
+    /// Remove zero-width and control characters
+    remove_invisible: bool,
+    /// Remove the spaces around the dot of the extension
+    trim_extension_dot: bool,
+    /// Strip the trailing dots
+    strip_trailing_dots: bool,
+    /// Convert the separators to another
+    convert: Option<(Separator, char)>,
+}
+
+impl CleanupRule {
+    pub fn apply(&self, text: &str) -> String {
+        let mut new_text = if self.remove_invisible {
+            text.chars().filter(|c| !is_invisible(*c)).collect()
+        } else {
+            text.to_string()
+        };
+        if self.collapse_spaces {
+            let mut last_is_whitespace = false;
+            new_text = new_text
+                .chars()
+                .filter_map(|c| {
+                    let is_whitespace = c.is_whitespace();
+                    let skip = is_whitespace && last_is_whitespace;
+                    last_is_whitespace = is_whitespace;
+                    match (skip, is_whitespace) {
+                        (true, _) => None,
+                        (false, true) => Some(' '),
+                        (false, false) => Some(c),
+                    }
+                })
+                .collect();
+        }
+        if self.trim {
+            new_text = new_text.trim().to_string();
+        }
+        if self.strip_trailing_dots {
+            new_text = new_text.trim_end_matches('.').to_string();
+        }
+        if let Some((from, to)) = self.convert {
+            new_text = new_text
+                .chars()
+                .map(|c| if from.matches(c) { to } else { c })
+                .collect();
+        }
+        new_text
+    }
+
+    /// Remove the spaces around the dot of the extension of `file_name`.
+    fn apply_to_extension_dot(&self, file_name: String) -> String {
+        if !self.trim_extension_dot {
+            return file_name;
+        }
+        match split_file_at_dot(file_name.as_str()) {
+            (stem, Some(suffix)) => [stem.trim_end(), suffix.trim_start()].join("."),
+            (_, None) => file_name,
+        }
+    }
+}
+
+/// Returns `true` if `c` is a control character or an invisible format character such as the
+/// zero-width space.
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{ad}'
+                | '\u{200b}'..='\u{200f}'
+                | '\u{202a}'..='\u{202e}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{feff}'
+        )
+}
+
+pub struct CleanupRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl CleanupRenamer {
+    pub fn new() -> Self {
+        let builder = Builder::from_resource(&resource_path("provider/cleanup_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::Cleanup;
+
+        for id in &[
+            ID_TRIM_CHECK_BUTTON,
+            ID_COLLAPSE_SPACES_CHECK_BUTTON,
+            ID_REMOVE_INVISIBLE_CHECK_BUTTON,
+            ID_TRIM_EXTENSION_DOT_CHECK_BUTTON,
+            ID_STRIP_TRAILING_DOTS_CHECK_BUTTON,
+        ] {
+            let change_subject = self.change_subject.clone();
+            self.object::<CheckButton>(id).connect_toggled(move |_| {
+                change_subject
+                    .notify((renamer_type, ()))
+                    .unwrap_or_default();
+            });
+        }
+
+        let convert_from_combo_box = self.object::<ComboBoxText>(ID_CONVERT_FROM_COMBO_BOX);
+        let convert_to_combo_box = self.object::<ComboBoxText>(ID_CONVERT_TO_COMBO_BOX);
+
+        let change_subject = self.change_subject.clone();
+        convert_from_combo_box.connect_changed(glib::clone!(
+            @weak convert_to_combo_box => move |convert_from_combo_box| {
+            convert_to_combo_box.set_sensitive(convert_from_combo_box.active_id().as_deref() != Some("None"));
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        }));
+
+        let change_subject = self.change_subject.clone();
+        convert_to_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    fn get_replacement_rule(&self) -> CleanupRule {
+        let is_active = |id| self.object::<CheckButton>(id).is_active();
+        let separator_of = |id| {
+            self.object::<ComboBoxText>(id)
+                .active_id()
+                .and_then(|id| Separator::from_str(id.as_str()).ok())
+        };
+        let convert = separator_of(ID_CONVERT_FROM_COMBO_BOX).and_then(|from| {
+            let to = separator_of(ID_CONVERT_TO_COMBO_BOX)?.as_char()?;
+            Some((from, to))
+        });
+
+        CleanupRule {
+            trim: is_active(ID_TRIM_CHECK_BUTTON),
+            collapse_spaces: is_active(ID_COLLAPSE_SPACES_CHECK_BUTTON),
+            remove_invisible: is_active(ID_REMOVE_INVISIBLE_CHECK_BUTTON),
+            trim_extension_dot: is_active(ID_TRIM_EXTENSION_DOT_CHECK_BUTTON),
+            strip_trailing_dots: is_active(ID_STRIP_TRAILING_DOTS_CHECK_BUTTON),
+            convert,
+        }
+    }
+
+    fn apply_replace_with(
+        rule: &CleanupRule,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> IntoIter<(String, String)> {
+        files
+            .iter()
+            .map(|(file_name, dir_name)| {
+                let new_file_name = match target {
+                    RenamerTarget::Name => {
+                        let (stem, extension) = split_file_at_dot(file_name.as_str());
+                        let new_stem = rule.apply(stem);
+                        if let Some(suffix) = extension {
+                            [new_stem.as_str(), suffix].join(".").to_string()
+                        } else {
+                            new_stem
+                        }
+                    }
+                    RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
+                        (stem, Some(suffix)) => {
+                            let new_suffix = rule.apply(suffix);
+                            [stem, new_suffix.as_str()].join(".").to_string()
+                        }
+                        (stem, None) => stem.to_string(),
+                    },
+                    RenamerTarget::All => rule.apply(file_name.as_str()),
+                };
+                (rule.apply_to_extension_dot(new_file_name), dir_name.clone())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for CleanupRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_CLEANUP_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        let rule = self.get_replacement_rule();
+        Ok(Self::apply_replace_with(&rule, files, target))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::Window;
+
+    #[test]
+    fn test_cleanup_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let cleanup_renamer = CleanupRenamer::new();
+            let trim_check_button = cleanup_renamer.object::<CheckButton>(ID_TRIM_CHECK_BUTTON);
+            let convert_from_combo_box =
+                cleanup_renamer.object::<ComboBoxText>(ID_CONVERT_FROM_COMBO_BOX);
+            let convert_to_combo_box =
+                cleanup_renamer.object::<ComboBoxText>(ID_CONVERT_TO_COMBO_BOX);
+
+            cleanup_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&cleanup_renamer.get_panel())
+                .build()
+                .show_all();
+
+            assert_eq!(cleanup_renamer.get_replacement_rule().convert, None);
+            assert!(!convert_to_combo_box.is_sensitive());
+
+            counter_observer.reset();
+            gtk_test::click(&trim_check_button);
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(
+                cleanup_renamer.get_replacement_rule().trim,
+                trim_check_button.is_active()
+            );
+
+            counter_observer.reset();
+            convert_from_combo_box.set_active_id(Some("Space"));
+            convert_to_combo_box.set_active_id(Some("Hyphen"));
+            assert_eq!(counter_observer.count(), 2);
+            assert!(convert_to_combo_box.is_sensitive());
+            assert_eq!(
+                cleanup_renamer.get_replacement_rule().convert,
+                Some((Separator::Space, '-'))
+            );
+        });
+    }
+
+    #[test]
+    fn test_cleanup_rule() {
+        let rule = CleanupRule {
+            trim: true,
+            collapse_spaces: true,
+            remove_invisible: true,
+            ..CleanupRule::default()
+        };
+        assert_eq!(rule.apply("  a \t  b\u{200b}c  "), "a bc");
+        assert_eq!(CleanupRule::default().apply("  a  b  "), "  a  b  ");
+        let rule = CleanupRule {
+            collapse_spaces: true,
+            ..CleanupRule::default()
+        };
+        assert_eq!(rule.apply("  a \u{3000} b  "), " a b ");
+
+        let rule = CleanupRule {
+            trim: true,
+            collapse_spaces: true,
+            convert: Some((Separator::Space, '_')),
+            ..CleanupRule::default()
+        };
+        assert_eq!(rule.apply(" my  file - v2 "), "my_file_-_v2");
+
+        let rule = CleanupRule {
+            convert: Some((Separator::Any, '-')),
+            strip_trailing_dots: true,
+            ..CleanupRule::default()
+        };
+        assert_eq!(rule.apply("a b_c.d-e..."), "a-b-c-d-e");
+
+        let rule = CleanupRule {
+            strip_trailing_dots: true,
+            ..CleanupRule::default()
+        };
+        assert_eq!(rule.apply("end..."), "end");
+    }
+
+    #[test]
+    fn test_cleanup_renamer_apply_replacement_with() {
+        let rule = CleanupRule {
+            trim: true,
+            collapse_spaces: true,
+            trim_extension_dot: true,
+            ..CleanupRule::default()
+        };
+        let files = [
+            ("  a  b .txt".to_string(), "/tmp".to_string()),
+            ("c . txt ".to_string(), "/tmp".to_string()),
+            ("d".to_string(), "/tmp".to_string()),
+        ];
+
+        assert_eq!(
+            CleanupRenamer::apply_replace_with(&rule, &files, RenamerTarget::Name)
+                .collect::<Vec<_>>(),
+            vec![
+                ("a b.txt".to_string(), "/tmp".to_string()),
+                ("c.txt ".to_string(), "/tmp".to_string()),
+                ("d".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            CleanupRenamer::apply_replace_with(&rule, &files, RenamerTarget::All)
+                .collect::<Vec<_>>(),
+            vec![
+                ("a b.txt".to_string(), "/tmp".to_string()),
+                ("c.txt".to_string(), "/tmp".to_string()),
+                ("d".to_string(), "/tmp".to_string()),
+            ]
+        );
+    }
+}
@@ -1,17 +1,19 @@
 use crate::error::Error;
 use crate::utils::{
-    split_file_at_dot, BulkTextReplacement, InsertPosition, TextCharPosition,
+    expand_microsecond, offset_time_zone, parse_time_shift, read_mvhd_creation_time, read_png_time,
+    split_file_at_dot, BulkTextReplacement, DatePattern, InsertPosition, TextCharPosition,
     TextInsertOrOverwrite, UnixTime,
 };
 use crate::utils::{Observer, SubjectImpl};
 use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
 use crate::win::resource::resource_path;
+use glib::{BoolError, TimeZone};
 use gtk::prelude::*;
-use gtk::{Builder, ComboBoxText, Container, Entry, SpinButton};
+use gtk::{Builder, ComboBoxText, Container, Entry, EntryIconPosition, SpinButton};
 use std::convert::TryFrom;
-use std::fs::File;
+use std::fs::{File, Metadata};
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
 use std::time::SystemTime;
@@ -23,13 +25,199 @@ const ID_INSERT_TIME_COMBO_BOX: &'static str = "insert-time-combo-box";
 const ID_FORMAT_ENTRY: &'static str = "format-entry";
 const ID_AT_POSITION_SPINNER_BUTTON: &'static str = "at-position-spin-button";
 const ID_AT_POSITION_COMBO_BOX: &'static str = "at-position-combo-box";
+const ID_EXIF_DATE_COMBO_BOX: &'static str = "exif-date-combo-box";
+const ID_FILE_NAME_DATE_ENTRY: &'static str = "file-name-date-entry";
+const ID_TIME_ZONE_COMBO_BOX: &'static str = "time-zone-combo-box";
+const ID_TIME_ZONE_ENTRY: &'static str = "time-zone-entry";
+const ID_TIME_SHIFT_COMBO_BOX: &'static str = "time-shift-combo-box";
+const ID_TIME_SHIFT_ENTRY: &'static str = "time-shift-entry";
 
 #[derive(Clone, Copy, Eq, PartialEq, EnumString)]
 enum InsertTimeKind {
     Current,
     Accessed,
     Modified,
+    /// Birth time of the file
+    Created,
+    /// Time of the last status change (ctime)
+    Changed,
+    /// EXIF date of the picture, or `tIME` of the PNG file
     PictureToken,
+    /// Creation time of the MP4/MOV movie header
+    VideoRecorded,
+    /// Either `PictureToken` or `VideoRecorded`
+    Captured,
+    /// Date written in the file name
+    FileName,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, EnumString)]
+enum ExifDateSource {
+    /// `DateTimeOriginal`, when the picture was taken
+    Original,
+    /// `DateTimeDigitized`, when the picture was stored as digital data
+    Digitized,
+    /// `DateTime`, when the file was last changed, e.g. by the photo editor
+    Modified,
+}
+
+impl ExifDateSource {
+    /// Date tag with its sub-second and offset tags
+    fn tags(&self) -> (exif::Tag, exif::Tag, exif::Tag) {
+        match self {
+            ExifDateSource::Original => (
+                exif::Tag::DateTimeOriginal,
+                exif::Tag::SubSecTimeOriginal,
+                exif::Tag::OffsetTimeOriginal,
+            ),
+            ExifDateSource::Digitized => (
+                exif::Tag::DateTimeDigitized,
+                exif::Tag::SubSecTimeDigitized,
+                exif::Tag::OffsetTimeDigitized,
+            ),
+            ExifDateSource::Modified => (
+                exif::Tag::DateTime,
+                exif::Tag::SubSecTime,
+                exif::Tag::OffsetTime,
+            ),
+        }
+    }
+
+    /// Sources to look up, falling back to the others when the date is missing
+    fn lookup_order(&self) -> [ExifDateSource; 3] {
+        match self {
+            ExifDateSource::Original => [
+                ExifDateSource::Original,
+                ExifDateSource::Digitized,
+                ExifDateSource::Modified,
+            ],
+            ExifDateSource::Digitized => [
+                ExifDateSource::Digitized,
+                ExifDateSource::Original,
+                ExifDateSource::Modified,
+            ],
+            ExifDateSource::Modified => [
+                ExifDateSource::Modified,
+                ExifDateSource::Original,
+                ExifDateSource::Digitized,
+            ],
+        }
+    }
+}
+
+/// Time for the replacement
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct FileTime {
+    time: UnixTime,
+    /// Fraction of the second
+    microsecond: u32,
+    /// Offset of the time zone recorded with the time in minutes
+    offset: Option<i16>,
+}
+
+impl From<UnixTime> for FileTime {
+    fn from(time: UnixTime) -> Self {
+        Self {
+            time,
+            microsecond: 0,
+            offset: None,
+        }
+    }
+}
+
+impl From<SystemTime> for FileTime {
+    fn from(time: SystemTime) -> Self {
+        Self {
+            time: UnixTime::from(time),
+            microsecond: time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|v| v.subsec_micros())
+                .unwrap_or(0),
+            offset: None,
+        }
+    }
+}
+
+impl TryFrom<exif::DateTime> for FileTime {
+    type Error = BoolError;
+
+    fn try_from(datetime: exif::DateTime) -> Result<Self, Self::Error> {
+        let microsecond = datetime.nanosecond.unwrap_or(0) / 1000;
+        let offset = datetime.offset;
+        Ok(Self {
+            time: UnixTime::try_from(datetime)?,
+            microsecond,
+            offset,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, EnumString)]
+enum TimeZoneKind {
+    /// Time zone of this machine
+    Local,
+    Utc,
+    /// Offset recorded in the EXIF of the file, or the local time zone without it
+    Recorded,
+    /// IANA time zone such as "Asia/Tokyo"
+    Named,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, EnumString)]
+enum TimeShiftKind {
+    /// Add the shift to the time
+    ShiftBy,
+    /// The clock was ahead by the shift, so subtract it
+    ClockOffBy,
+}
+
+struct DateTimeRule {
+    kind: InsertTimeKind,
+    pattern: String,
+    position: InsertPosition,
+    time_zone: TimeZoneKind,
+    time_zone_name: String,
+    /// Seconds added to the time
+    shift: i64,
+    /// Pattern of the date in the file name for `InsertTimeKind::FileName`
+    file_name_date: Option<DatePattern>,
+    exif_date: ExifDateSource,
+}
+
+impl DateTimeRule {
+    /// Returns the time zone to format the time in, or `None` if the named time zone is unknown.
+    fn time_zone_of(&self, recorded_offset: Option<i16>) -> Option<TimeZone> {
+        match (&self.time_zone, recorded_offset) {
+            (TimeZoneKind::Local, _) | (TimeZoneKind::Recorded, None) => Some(TimeZone::local()),
+            (TimeZoneKind::Utc, _) => Some(TimeZone::utc()),
+            (TimeZoneKind::Recorded, Some(offset)) => Some(offset_time_zone(offset)),
+            (TimeZoneKind::Named, _) => named_time_zone(self.time_zone_name.as_str()),
+        }
+    }
+
+    fn format(&self, time: &FileTime) -> Option<String> {
+        time.time.shift(self.shift).format_in(
+            expand_microsecond(self.pattern.as_str(), time.microsecond).as_str(),
+            &self.time_zone_of(time.offset)?,
+        )
+    }
+}
+
+/// Returns the time zone of the IANA name such as "Europe/Paris" or the offset such as "+09:00".
+///
+/// `TimeZone::new` falls back to UTC for the unknown names, so the name is valid only if the
+/// identifier of the time zone is the name itself.
+fn named_time_zone(name: &str) -> Option<TimeZone> {
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let time_zone = TimeZone::new(Some(name));
+    if time_zone.identifier().as_str() == name {
+        Some(time_zone)
+    } else {
+        None
+    }
 }
 
 pub struct DateTimeRenamer {
@@ -47,6 +235,7 @@ impl DateTimeRenamer {
         };
 
         renamer.init_callback();
+        Self::update_sensitivity(&renamer.builder);
 
         renamer
     }
@@ -59,7 +248,9 @@ impl DateTimeRenamer {
         let at_position_combo_box = self.object::<ComboBoxText>(ID_AT_POSITION_COMBO_BOX);
 
         let change_subject = self.change_subject.clone();
+        let builder = self.builder.clone();
         insert_time_combo_box.connect_changed(move |_| {
+            Self::update_sensitivity(&builder);
             change_subject
                 .notify((renamer_type, ()))
                 .unwrap_or_default();
@@ -85,87 +276,296 @@ impl DateTimeRenamer {
                 .notify((renamer_type, ()))
                 .unwrap_or_default();
         });
+
+        let exif_date_combo_box = self.object::<ComboBoxText>(ID_EXIF_DATE_COMBO_BOX);
+        let file_name_date_entry = self.object::<Entry>(ID_FILE_NAME_DATE_ENTRY);
+        let time_zone_combo_box = self.object::<ComboBoxText>(ID_TIME_ZONE_COMBO_BOX);
+        let time_zone_entry = self.object::<Entry>(ID_TIME_ZONE_ENTRY);
+        let time_shift_combo_box = self.object::<ComboBoxText>(ID_TIME_SHIFT_COMBO_BOX);
+        let time_shift_entry = self.object::<Entry>(ID_TIME_SHIFT_ENTRY);
+
+        let change_subject = self.change_subject.clone();
+        exif_date_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        file_name_date_entry.connect_changed(move |file_name_date_entry| {
+            if let Err(e) = DatePattern::new(file_name_date_entry.text().as_str()) {
+                file_name_date_entry
+                    .set_icon_from_icon_name(EntryIconPosition::Secondary, Some("error"));
+                file_name_date_entry.set_icon_tooltip_text(
+                    EntryIconPosition::Secondary,
+                    Some(e.to_string().as_str()),
+                );
+            } else {
+                file_name_date_entry.set_icon_from_icon_name(EntryIconPosition::Secondary, None);
+                file_name_date_entry.set_icon_tooltip_text(EntryIconPosition::Secondary, None);
+            }
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        let builder = self.builder.clone();
+        time_zone_combo_box.connect_changed(move |_| {
+            Self::update_sensitivity(&builder);
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        time_zone_entry.connect_changed(move |time_zone_entry| {
+            if named_time_zone(time_zone_entry.text().as_str()).is_some() {
+                time_zone_entry.set_icon_from_icon_name(EntryIconPosition::Secondary, None);
+                time_zone_entry.set_icon_tooltip_text(EntryIconPosition::Secondary, None);
+            } else {
+                time_zone_entry.set_icon_from_icon_name(EntryIconPosition::Secondary, Some("error"));
+                time_zone_entry.set_icon_tooltip_text(
+                    EntryIconPosition::Secondary,
+                    Some("Unknown time zone, expected a name such as Europe/Paris or an offset such as +09:00"),
+                );
+            }
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        time_shift_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        time_shift_entry.connect_changed(move |time_shift_entry| {
+            if parse_time_shift(time_shift_entry.text().as_str()).is_some() {
+                time_shift_entry.set_icon_from_icon_name(EntryIconPosition::Secondary, None);
+                time_shift_entry.set_icon_tooltip_text(EntryIconPosition::Secondary, None);
+            } else {
+                time_shift_entry
+                    .set_icon_from_icon_name(EntryIconPosition::Secondary, Some("error"));
+                time_shift_entry.set_icon_tooltip_text(
+                    EntryIconPosition::Secondary,
+                    Some("Expected [+|-]hours[:minutes[:seconds]], e.g. -1:30"),
+                );
+            }
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
     }
 
-    fn get_replacement_rule(&self) -> Option<(InsertTimeKind, String, InsertPosition)> {
+    fn update_sensitivity(builder: &Builder) {
+        let time_zone_combo_box = builder
+            .object::<ComboBoxText>(ID_TIME_ZONE_COMBO_BOX)
+            .unwrap();
+        let time_zone_entry = builder.object::<Entry>(ID_TIME_ZONE_ENTRY).unwrap();
+        let insert_time_combo_box = builder
+            .object::<ComboBoxText>(ID_INSERT_TIME_COMBO_BOX)
+            .unwrap();
+        let exif_date_combo_box = builder
+            .object::<ComboBoxText>(ID_EXIF_DATE_COMBO_BOX)
+            .unwrap();
+        let file_name_date_entry = builder.object::<Entry>(ID_FILE_NAME_DATE_ENTRY).unwrap();
+
+        let time_zone = time_zone_combo_box
+            .active_id()
+            .and_then(|id| TimeZoneKind::from_str(id.as_str()).ok());
+        time_zone_entry.set_sensitive(time_zone == Some(TimeZoneKind::Named));
+
+        let insert_time_kind = insert_time_combo_box
+            .active_id()
+            .and_then(|id| InsertTimeKind::from_str(id.as_str()).ok());
+        exif_date_combo_box.set_sensitive(matches!(
+            insert_time_kind,
+            Some(InsertTimeKind::PictureToken) | Some(InsertTimeKind::Captured)
+        ));
+        file_name_date_entry.set_sensitive(insert_time_kind == Some(InsertTimeKind::FileName));
+    }
+
+    fn get_replacement_rule(&self) -> Result<DateTimeRule, Error> {
         let insert_time_combo_box = self.object::<ComboBoxText>(ID_INSERT_TIME_COMBO_BOX);
         let format_entry = self.object::<Entry>(ID_FORMAT_ENTRY);
         let at_position_spin_button = self.object::<SpinButton>(ID_AT_POSITION_SPINNER_BUTTON);
         let at_position_combo_box = self.object::<ComboBoxText>(ID_AT_POSITION_COMBO_BOX);
+        let exif_date_combo_box = self.object::<ComboBoxText>(ID_EXIF_DATE_COMBO_BOX);
+        let file_name_date_entry = self.object::<Entry>(ID_FILE_NAME_DATE_ENTRY);
+        let time_zone_combo_box = self.object::<ComboBoxText>(ID_TIME_ZONE_COMBO_BOX);
+        let time_zone_entry = self.object::<Entry>(ID_TIME_ZONE_ENTRY);
+        let time_shift_combo_box = self.object::<ComboBoxText>(ID_TIME_SHIFT_COMBO_BOX);
+        let time_shift_entry = self.object::<Entry>(ID_TIME_SHIFT_ENTRY);
 
         let insert_time_kind = insert_time_combo_box
             .active_id()
-            .and_then(|id| InsertTimeKind::from_str(id.as_str()).ok())?;
+            .and_then(|id| InsertTimeKind::from_str(id.as_str()).ok())
+            .unwrap_or(InsertTimeKind::Modified);
         let pos = usize::try_from(at_position_spin_button.value_as_int()).unwrap_or(0);
         let insert_position = InsertPosition(
             at_position_combo_box
                 .active_id()
-                .and_then(|id| TextCharPosition::from_str_usize(id.as_str(), pos))?,
+                .and_then(|id| TextCharPosition::from_str_usize(id.as_str(), pos))
+                .unwrap_or(TextCharPosition::Front(pos)),
             TextInsertOrOverwrite::Insert,
         );
+        let time_zone = time_zone_combo_box
+            .active_id()
+            .and_then(|id| TimeZoneKind::from_str(id.as_str()).ok())
+            .unwrap_or(TimeZoneKind::Local);
+        let time_shift_kind = time_shift_combo_box
+            .active_id()
+            .and_then(|id| TimeShiftKind::from_str(id.as_str()).ok())
+            .unwrap_or(TimeShiftKind::ShiftBy);
+        let time_shift = time_shift_entry.text();
+        let shift = parse_time_shift(time_shift.as_str())
+            .ok_or_else(|| Error::InvalidTimeShift(time_shift.to_string()))?;
+        let exif_date = exif_date_combo_box
+            .active_id()
+            .and_then(|id| ExifDateSource::from_str(id.as_str()).ok())
+            .unwrap_or(ExifDateSource::Original);
+        let file_name_date = if insert_time_kind == InsertTimeKind::FileName {
+            Some(DatePattern::new(file_name_date_entry.text().as_str())?)
+        } else {
+            None
+        };
 
-        Some((
-            insert_time_kind,
-            format_entry.text().to_string(),
-            insert_position,
-        ))
+        Ok(DateTimeRule {
+            kind: insert_time_kind,
+            pattern: format_entry.text().to_string(),
+            position: insert_position,
+            time_zone,
+            time_zone_name: time_zone_entry.text().to_string(),
+            shift: match time_shift_kind {
+                TimeShiftKind::ShiftBy => shift,
+                TimeShiftKind::ClockOffBy => -shift,
+            },
+            file_name_date,
+            exif_date,
+        })
     }
 
-    fn get_time_for_replacement(
-        insert_time_kind: InsertTimeKind,
-        path: PathBuf,
-    ) -> Option<UnixTime> {
-        match insert_time_kind {
-            InsertTimeKind::Current => Some(UnixTime::from(SystemTime::now())),
+    fn get_time_for_replacement(rule: &DateTimeRule, path: &Path) -> Option<FileTime> {
+        match rule.kind {
+            InsertTimeKind::Current => Some(FileTime::from(SystemTime::now())),
             InsertTimeKind::Accessed => path
                 .metadata()
                 .and_then(|metadata| metadata.accessed())
-                .map(|v| UnixTime::from(v))
+                .map(|v| FileTime::from(v))
                 .ok(),
             InsertTimeKind::Modified => path
                 .metadata()
                 .and_then(|metadata| metadata.modified())
-                .map(|v| UnixTime::from(v))
+                .map(|v| FileTime::from(v))
                 .ok(),
-            InsertTimeKind::PictureToken => {
-                let exif = File::open(path).and_then(|file| {
-                    let mut reader = BufReader::new(&file);
-                    Ok(exif::Reader::new().read_from_container(&mut reader))
-                });
-
-                if let Ok(Ok(exif)) = exif {
-                    exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY)
-                        .or_else(|| exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY))
-                        .or_else(|| exif.get_field(exif::Tag::DateTimeDigitized, exif::In::PRIMARY))
-                        .and_then(|v| match v.value {
-                            exif::Value::Ascii(ref vec) if !vec.is_empty() => {
-                                exif::DateTime::from_ascii(&vec[0])
-                                    .ok()
-                                    .and_then(|v| UnixTime::try_from(v).ok())
-                            }
-                            _ => None,
-                        })
-                } else {
-                    None
-                }
+            InsertTimeKind::Created => path
+                .metadata()
+                .and_then(|metadata| metadata.created())
+                .map(|v| FileTime::from(v))
+                .ok(),
+            InsertTimeKind::Changed => path
+                .metadata()
+                .ok()
+                .and_then(|metadata| Self::get_status_changed_time(&metadata)),
+            InsertTimeKind::PictureToken => Self::get_picture_time(path, rule.exif_date),
+            InsertTimeKind::VideoRecorded => Self::get_video_time(path).map(FileTime::from),
+            InsertTimeKind::Captured => Self::get_picture_time(path, rule.exif_date)
+                .or_else(|| Self::get_video_time(path).map(FileTime::from)),
+            InsertTimeKind::FileName => {
+                let file_name = path.file_name()?.to_string_lossy();
+                rule.file_name_date
+                    .as_ref()?
+                    .find(&file_name)?
+                    .to_unix_time(&rule.time_zone_of(None)?)
+                    .map(FileTime::from)
             }
         }
     }
 
+    #[cfg(unix)]
+    fn get_status_changed_time(metadata: &Metadata) -> Option<FileTime> {
+        use std::os::unix::fs::MetadataExt;
+        Some(FileTime {
+            time: UnixTime(metadata.ctime()),
+            microsecond: u32::try_from(metadata.ctime_nsec() / 1000).unwrap_or(0),
+            offset: None,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn get_status_changed_time(_metadata: &Metadata) -> Option<FileTime> {
+        None
+    }
+
+    /// Get the EXIF date of the picture, or the `tIME` of the PNG file
+    fn get_picture_time(path: &Path, exif_date: ExifDateSource) -> Option<FileTime> {
+        let exif = File::open(path).and_then(|file| {
+            let mut reader = BufReader::new(&file);
+            Ok(exif::Reader::new().read_from_container(&mut reader))
+        });
+
+        let exif_time = if let Ok(Ok(exif)) = exif {
+            let ascii_of = |tag| {
+                exif.get_field(tag, exif::In::PRIMARY)
+                    .and_then(|v| match v.value {
+                        exif::Value::Ascii(ref vec) if !vec.is_empty() => Some(vec[0].clone()),
+                        _ => None,
+                    })
+            };
+            exif_date.lookup_order().iter().find_map(|source| {
+                let (date_time_tag, sub_sec_tag, offset_tag) = source.tags();
+                let mut date_time =
+                    exif::DateTime::from_ascii(ascii_of(date_time_tag)?.as_slice()).ok()?;
+                if let Some(sub_sec) = ascii_of(sub_sec_tag) {
+                    date_time
+                        .parse_subsec(sub_sec.as_slice())
+                        .unwrap_or_default();
+                }
+                if let Some(offset) = ascii_of(offset_tag) {
+                    date_time
+                        .parse_offset(offset.as_slice())
+                        .unwrap_or_default();
+                }
+                FileTime::try_from(date_time).ok()
+            })
+        } else {
+            None
+        };
+
+        exif_time.or_else(|| {
+            File::open(path)
+                .and_then(|file| read_png_time(&mut BufReader::new(&file)))
+                .ok()
+                .flatten()
+                .map(FileTime::from)
+        })
+    }
+
+    /// Get the creation time of the MP4/MOV file
+    fn get_video_time(path: &Path) -> Option<UnixTime> {
+        File::open(path)
+            .and_then(|file| read_mvhd_creation_time(&mut BufReader::new(&file)))
+            .ok()
+            .flatten()
+    }
+
     fn apply_replace_with(
-        insert_time_kind: InsertTimeKind,
-        pattern: String,
-        position: InsertPosition,
+        rule: &DateTimeRule,
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> IntoIter<(String, String)> {
+        let position = rule.position;
         files
             .iter()
             .map(|(file_name, dir_name)| {
                 let path = PathBuf::from(dir_name).join(file_name);
-                let time = DateTimeRenamer::get_time_for_replacement(insert_time_kind, path);
+                let time = DateTimeRenamer::get_time_for_replacement(rule, path.as_path());
 
-                if let Some(time_str) = time.and_then(|v| v.format(pattern.as_str())) {
+                if let Some(time_str) = time.and_then(|time| rule.format(&time)) {
                     let new_file_name = match target {
                         RenamerTarget::Name => {
                             let (stem, extension) = split_file_at_dot(file_name.as_str());
@@ -211,14 +611,8 @@ impl Renamer for DateTimeRenamer {
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> Result<IntoIter<(String, String)>, Error> {
-        let (insert_time_kind, pattern, position) = self.get_replacement_rule().unwrap();
-        Ok(Self::apply_replace_with(
-            insert_time_kind,
-            pattern,
-            position,
-            files,
-            target,
-        ))
+        let rule = self.get_replacement_rule()?;
+        Ok(Self::apply_replace_with(&rule, files, target))
     }
 
     fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
@@ -248,6 +642,15 @@ mod test {
                 date_time_renamer.object::<SpinButton>(ID_AT_POSITION_SPINNER_BUTTON);
             let at_position_combo_box =
                 date_time_renamer.object::<ComboBoxText>(ID_AT_POSITION_COMBO_BOX);
+            let time_zone_combo_box =
+                date_time_renamer.object::<ComboBoxText>(ID_TIME_ZONE_COMBO_BOX);
+            let time_zone_entry = date_time_renamer.object::<Entry>(ID_TIME_ZONE_ENTRY);
+            let time_shift_combo_box =
+                date_time_renamer.object::<ComboBoxText>(ID_TIME_SHIFT_COMBO_BOX);
+            let time_shift_entry = date_time_renamer.object::<Entry>(ID_TIME_SHIFT_ENTRY);
+            let exif_date_combo_box =
+                date_time_renamer.object::<ComboBoxText>(ID_EXIF_DATE_COMBO_BOX);
+            let file_name_date_entry = date_time_renamer.object::<Entry>(ID_FILE_NAME_DATE_ENTRY);
 
             date_time_renamer.attach_change(counter_observer.clone());
 
@@ -277,6 +680,94 @@ mod test {
             at_position_combo_box.clone().set_active(Some(1));
             gtk_test::wait(1);
             assert_eq!(counter_observer.count(), 1);
+
+            assert!(!exif_date_combo_box.is_sensitive());
+            insert_time_combo_box.set_active_id(Some("PictureToken"));
+            gtk_test::wait(1);
+            assert!(exif_date_combo_box.is_sensitive());
+
+            counter_observer.reset();
+            exif_date_combo_box.set_active_id(Some("Modified"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(
+                date_time_renamer.get_replacement_rule().unwrap().exif_date
+                    == ExifDateSource::Modified
+            );
+
+            assert!(!file_name_date_entry.is_sensitive());
+            counter_observer.reset();
+            insert_time_combo_box.set_active_id(Some("FileName"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(file_name_date_entry.is_sensitive());
+
+            counter_observer.reset();
+            file_name_date_entry.set_text("%Y-%Y");
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(file_name_date_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_some());
+            assert!(date_time_renamer.get_replacement_rule().is_err());
+
+            file_name_date_entry.set_text("%Y%m%d");
+            gtk_test::wait(1);
+            assert!(file_name_date_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_none());
+
+            assert!(!time_zone_entry.is_sensitive());
+            counter_observer.reset();
+            time_zone_combo_box.set_active_id(Some("Named"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(time_zone_entry.is_sensitive());
+
+            counter_observer.reset();
+            gtk_test::focus(&time_zone_entry);
+            gtk_test::enter_keys(&time_zone_entry, "UTC");
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), "UTC".len());
+            assert!(time_zone_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_none());
+            time_zone_entry.set_text("Mars/Olympus_Mons");
+            gtk_test::wait(1);
+            assert!(time_zone_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_some());
+            time_zone_entry.set_text("UTC");
+            gtk_test::wait(1);
+
+            counter_observer.reset();
+            time_shift_combo_box.set_active_id(Some("ClockOffBy"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+
+            counter_observer.reset();
+            gtk_test::focus(&time_shift_entry);
+            gtk_test::enter_keys(&time_shift_entry, "+1:x");
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), "+1:x".len());
+            assert!(time_shift_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_some());
+
+            assert!(matches!(
+                date_time_renamer.get_replacement_rule(),
+                Err(Error::InvalidTimeShift(_))
+            ));
+
+            time_shift_entry.set_text("+1:30");
+            gtk_test::wait(1);
+            assert!(time_shift_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_none());
+            let rule = date_time_renamer.get_replacement_rule().unwrap();
+            assert_eq!(rule.time_zone, TimeZoneKind::Named);
+            assert_eq!(rule.time_zone_name, "UTC");
+            assert_eq!(rule.shift, -(3600 + 30 * 60));
         });
     }
 
@@ -327,9 +818,16 @@ mod test {
         );
 
         let replacement = DateTimeRenamer::apply_replace_with(
-            InsertTimeKind::Current,
-            "%Y-%m-%d-%H-%M-%S".to_string(),
-            InsertPosition(TextCharPosition::Front(1), TextInsertOrOverwrite::Insert),
+            &DateTimeRule {
+                kind: InsertTimeKind::Current,
+                pattern: "%Y-%m-%d-%H-%M-%S".to_string(),
+                position: InsertPosition(TextCharPosition::Front(1), TextInsertOrOverwrite::Insert),
+                time_zone: TimeZoneKind::Local,
+                time_zone_name: String::new(),
+                shift: 0,
+                file_name_date: None,
+                exif_date: ExifDateSource::Original,
+            },
             &[jpg_file_pair.clone()],
             RenamerTarget::All,
         )
@@ -345,9 +843,16 @@ mod test {
         assert_eq!(jpg_file_pair.1, replacement[0].1);
 
         let replacement = DateTimeRenamer::apply_replace_with(
-            InsertTimeKind::Accessed,
-            "%Y-%m-%d-%H-%M-%S".to_string(),
-            InsertPosition(TextCharPosition::Back(4), TextInsertOrOverwrite::Insert),
+            &DateTimeRule {
+                kind: InsertTimeKind::Accessed,
+                pattern: "%Y-%m-%d-%H-%M-%S".to_string(),
+                position: InsertPosition(TextCharPosition::Back(4), TextInsertOrOverwrite::Insert),
+                time_zone: TimeZoneKind::Local,
+                time_zone_name: String::new(),
+                shift: 0,
+                file_name_date: None,
+                exif_date: ExifDateSource::Original,
+            },
             &[jpg_file_pair.clone()],
             RenamerTarget::All,
         )
@@ -363,9 +868,16 @@ mod test {
         assert_eq!(jpg_file_pair.1, replacement[0].1);
 
         let replacement = DateTimeRenamer::apply_replace_with(
-            InsertTimeKind::Modified,
-            "%Y-%m-%d-%H-%M-%S".to_string(),
-            InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+            &DateTimeRule {
+                kind: InsertTimeKind::Modified,
+                pattern: "%Y-%m-%d-%H-%M-%S".to_string(),
+                position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+                time_zone: TimeZoneKind::Local,
+                time_zone_name: String::new(),
+                shift: 0,
+                file_name_date: None,
+                exif_date: ExifDateSource::Original,
+            },
             &[jpg_file_pair.clone()],
             RenamerTarget::All,
         )
@@ -381,9 +893,16 @@ mod test {
         assert_eq!(jpg_file_pair.1, replacement[0].1);
 
         let replacement = DateTimeRenamer::apply_replace_with(
-            InsertTimeKind::PictureToken,
-            "%Y-%m-%d-%H-%M-%S".to_string(),
-            InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+            &DateTimeRule {
+                kind: InsertTimeKind::PictureToken,
+                pattern: "%Y-%m-%d-%H-%M-%S".to_string(),
+                position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+                time_zone: TimeZoneKind::Local,
+                time_zone_name: String::new(),
+                shift: 0,
+                file_name_date: None,
+                exif_date: ExifDateSource::Original,
+            },
             &[jpg_file_pair.clone()],
             RenamerTarget::All,
         )
@@ -397,5 +916,332 @@ mod test {
                 .is_match(replacement[0].0.as_str())
         );
         assert_eq!(jpg_file_pair.1, replacement[0].1);
+
+        // without the recorded offset, the picture time is read and shown in the local time zone
+        let picture_rule = |time_zone, shift| DateTimeRule {
+            kind: InsertTimeKind::PictureToken,
+            pattern: "%Y-%m-%d-%H-%M-%S_".to_string(),
+            position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+            time_zone,
+            time_zone_name: String::new(),
+            shift,
+            file_name_date: None,
+            exif_date: ExifDateSource::Original,
+        };
+
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &picture_rule(TimeZoneKind::Recorded, 0),
+            &[jpg_file_pair.clone()],
+            RenamerTarget::All,
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(replacement[0].0, "2009-02-13-23-31-30_test.jpg");
+
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &picture_rule(TimeZoneKind::Local, -(31 * 60 + 30)),
+            &[jpg_file_pair.clone()],
+            RenamerTarget::All,
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(replacement[0].0, "2009-02-13-23-00-00_test.jpg");
+
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &picture_rule(TimeZoneKind::Recorded, 3600),
+            &[jpg_file_pair.clone()],
+            RenamerTarget::Name,
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(replacement[0].0, "2009-02-14-00-31-30_test.jpg");
+    }
+
+    #[test]
+    fn test_date_time_rule_format() {
+        let rule = |time_zone, time_zone_name: &str| DateTimeRule {
+            kind: InsertTimeKind::Current,
+            pattern: "%Y-%m-%d %H:%M".to_string(),
+            position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+            time_zone,
+            time_zone_name: time_zone_name.to_string(),
+            shift: 0,
+            file_name_date: None,
+            exif_date: ExifDateSource::Original,
+        };
+        let time = UnixTime(1234567890);
+        let file_time = |time, offset| FileTime {
+            time,
+            microsecond: 0,
+            offset,
+        };
+
+        assert_eq!(
+            rule(TimeZoneKind::Utc, "").format(&file_time(time, Some(9 * 60))),
+            Some("2009-02-13 23:31".to_string())
+        );
+        assert_eq!(
+            rule(TimeZoneKind::Recorded, "").format(&file_time(time, Some(9 * 60))),
+            Some("2009-02-14 08:31".to_string())
+        );
+        assert_eq!(
+            rule(TimeZoneKind::Recorded, "").format(&file_time(time, None)),
+            time.format("%Y-%m-%d %H:%M")
+        );
+        assert_eq!(
+            rule(TimeZoneKind::Named, "+05:30").format(&file_time(time, Some(9 * 60))),
+            Some("2009-02-14 05:01".to_string())
+        );
+        assert_eq!(
+            rule(TimeZoneKind::Named, "UTC").format(&file_time(time.shift(-60), None)),
+            Some("2009-02-13 23:30".to_string())
+        );
+        assert_eq!(
+            rule(TimeZoneKind::Named, "Mars/Olympus_Mons").format(&file_time(time, None)),
+            None
+        );
+        assert_eq!(
+            rule(TimeZoneKind::Named, " ").format(&file_time(time, None)),
+            None
+        );
+
+        let mut rule = rule(TimeZoneKind::Utc, "");
+        rule.pattern = "%H%M%S_%3f".to_string();
+        assert_eq!(
+            rule.format(&FileTime {
+                time,
+                microsecond: 120000,
+                offset: None,
+            }),
+            Some("233130_120".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_replace_with_sources() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir_name = temp_dir.path().to_str().unwrap().to_string();
+        let rule = |kind, file_name_date| DateTimeRule {
+            kind,
+            pattern: "%Y-%m-%d-%H-%M-%S_".to_string(),
+            position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+            time_zone: TimeZoneKind::Utc,
+            time_zone_name: String::new(),
+            shift: 0,
+            file_name_date,
+            exif_date: ExifDateSource::Original,
+        };
+
+        // MP4 with the movie header created at 2009-02-13 23:31:30 UTC
+        #[rustfmt::skip]
+        let mp4_data: [u8; 52] = [
+            0x00, 0x00, 0x00, 0x10, 0x66, 0x74, 0x79, 0x70, 0x69, 0x73, 0x6f, 0x6d,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x24, 0x6d, 0x6f, 0x6f, 0x76,
+            0x00, 0x00, 0x00, 0x1c, 0x6d, 0x76, 0x68, 0x64, 0x00, 0x00, 0x00, 0x00,
+            0xc5, 0xbb, 0xb3, 0x52, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        // PNG with the tIME chunk of 2009-02-13 23:31:30 UTC
+        #[rustfmt::skip]
+        let png_data: [u8; 52] = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d,
+            0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01,
+            0x08, 0x00, 0x00, 0x00, 0x00, 0x3a, 0x7e, 0x9b, 0x55, 0x00, 0x00, 0x00,
+            0x07, 0x74, 0x49, 0x4d, 0x45, 0x07, 0xd9, 0x02, 0x0d, 0x17, 0x1f, 0x1e,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        for (file_name, data) in [
+            ("movie.mp4", mp4_data.to_vec()),
+            ("image.png", png_data.to_vec()),
+            ("IMG_20210304_123456.txt", Vec::new()),
+        ]
+        .iter()
+        {
+            let mut writer =
+                BufWriter::new(File::create(PathBuf::from(&dir_name).join(file_name)).unwrap());
+            writer.write(data).unwrap();
+        }
+        let files = [
+            ("movie.mp4".to_string(), dir_name.clone()),
+            ("image.png".to_string(), dir_name.clone()),
+            ("IMG_20210304_123456.txt".to_string(), dir_name.clone()),
+        ];
+
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &rule(InsertTimeKind::VideoRecorded, None),
+            &files,
+            RenamerTarget::All,
+        )
+        .map(|(file_name, _)| file_name)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            replacement,
+            vec![
+                "2009-02-13-23-31-30_movie.mp4",
+                "image.png",
+                "IMG_20210304_123456.txt"
+            ]
+        );
+
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &rule(InsertTimeKind::Captured, None),
+            &files,
+            RenamerTarget::All,
+        )
+        .map(|(file_name, _)| file_name)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            replacement,
+            vec![
+                "2009-02-13-23-31-30_movie.mp4",
+                "2009-02-13-23-31-30_image.png",
+                "IMG_20210304_123456.txt"
+            ]
+        );
+
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &rule(
+                InsertTimeKind::FileName,
+                Some(DatePattern::new("%Y%m%d_%H%M%S").unwrap()),
+            ),
+            &files,
+            RenamerTarget::All,
+        )
+        .map(|(file_name, _)| file_name)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            replacement,
+            vec![
+                "movie.mp4",
+                "image.png",
+                "2021-03-04-12-34-56_IMG_20210304_123456.txt"
+            ]
+        );
+
+        let matcher = RegexBuilder::new("^\\d{4}-\\d{2}-\\d{2}-\\d{2}-\\d{2}-\\d{2}_movie.mp4$")
+            .build()
+            .unwrap();
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &rule(InsertTimeKind::Changed, None),
+            &files[0..1],
+            RenamerTarget::All,
+        )
+        .collect::<Vec<_>>();
+        assert!(matcher.is_match(replacement[0].0.as_str()));
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &rule(InsertTimeKind::Created, None),
+            &files[0..1],
+            RenamerTarget::All,
+        )
+        .collect::<Vec<_>>();
+        // the birth time is not supported by all the file systems
+        assert!(replacement[0].0 == "movie.mp4" || matcher.is_match(replacement[0].0.as_str()));
+    }
+
+    #[test]
+    fn test_get_picture_time() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let write_tiff = |file_name: &str, values: &[(exif::Tag, &str)]| {
+            let fields = values
+                .iter()
+                .map(|&(tag, value)| exif::Field {
+                    tag,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+                })
+                .collect::<Vec<_>>();
+            let mut writer = exif::experimental::Writer::new();
+            fields.iter().for_each(|field| writer.push_field(field));
+            let path = temp_dir.path().join(file_name);
+            writer
+                .write(&mut File::create(path.as_path()).unwrap(), false)
+                .unwrap();
+            path
+        };
+        let local_time = |year, month, day, hour, minute, second| {
+            UnixTime::from(
+                glib::DateTime::from_local(year, month, day, hour, minute, second).unwrap(),
+            )
+        };
+
+        let edited = write_tiff(
+            "edited.tif",
+            &[
+                (exif::Tag::DateTime, "2020:01:02 03:04:05"),
+                (exif::Tag::DateTimeOriginal, "2009:02:13 23:31:30"),
+                (exif::Tag::SubSecTimeOriginal, "12"),
+                (exif::Tag::OffsetTimeOriginal, "+09:00"),
+                (exif::Tag::DateTimeDigitized, "2009:02:13 23:31:31"),
+            ],
+        );
+        assert_eq!(
+            DateTimeRenamer::get_picture_time(edited.as_path(), ExifDateSource::Original),
+            Some(FileTime {
+                time: UnixTime(1234567890 - 9 * 3600),
+                microsecond: 120000,
+                offset: Some(9 * 60),
+            })
+        );
+        assert_eq!(
+            DateTimeRenamer::get_picture_time(edited.as_path(), ExifDateSource::Digitized),
+            Some(FileTime::from(local_time(2009, 2, 13, 23, 31, 31.0)))
+        );
+        assert_eq!(
+            DateTimeRenamer::get_picture_time(edited.as_path(), ExifDateSource::Modified),
+            Some(FileTime::from(local_time(2020, 1, 2, 3, 4, 5.0)))
+        );
+
+        let scanned = write_tiff(
+            "scanned.tif",
+            &[
+                (exif::Tag::DateTime, "2020:01:02 03:04:05"),
+                (exif::Tag::SubSecTime, "5"),
+            ],
+        );
+        assert_eq!(
+            DateTimeRenamer::get_picture_time(scanned.as_path(), ExifDateSource::Original),
+            Some(FileTime {
+                time: local_time(2020, 1, 2, 3, 4, 5.0),
+                microsecond: 500000,
+                offset: None,
+            })
+        );
+
+        // burst shots taken in the same second
+        let files = ["burst1.tif", "burst2.tif"]
+            .iter()
+            .zip(["25", "75"].iter())
+            .map(|(file_name, sub_sec)| {
+                write_tiff(
+                    *file_name,
+                    &[
+                        (exif::Tag::DateTimeOriginal, "2009:02:13 23:31:30"),
+                        (exif::Tag::SubSecTimeOriginal, *sub_sec),
+                        (exif::Tag::OffsetTimeOriginal, "+00:00"),
+                    ],
+                );
+                (
+                    file_name.to_string(),
+                    temp_dir.path().to_str().unwrap().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let replacement = DateTimeRenamer::apply_replace_with(
+            &DateTimeRule {
+                kind: InsertTimeKind::PictureToken,
+                pattern: "%Y%m%d_%H%M%S%2f".to_string(),
+                position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+                time_zone: TimeZoneKind::Recorded,
+                time_zone_name: String::new(),
+                shift: 0,
+                file_name_date: None,
+                exif_date: ExifDateSource::Original,
+            },
+            &files,
+            RenamerTarget::Name,
+        )
+        .map(|(file_name, _)| file_name)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            replacement,
+            vec!["20090213_23313025burst1.tif", "20090213_23313075burst2.tif"]
+        );
     }
 }
@@ -0,0 +1,759 @@
+use crate::error::Error;
+use crate::utils::{format_count, split_file_at_dot};
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, CheckButton, ComboBoxText, Container, Entry, Label};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::SystemTime;
+use std::vec::IntoIter;
+use strum_macros::EnumString;
+
+const ID_EXTENSION_RENAMER_PANEL: &'static str = "extension-renamer-panel";
+const ID_EXTENSION_MODE_COMBO_BOX: &'static str = "extension-mode-combo-box";
+const ID_REPLACE_FROM_ENTRY: &'static str = "extension-replace-from-entry";
+const ID_NEW_EXTENSION_ENTRY: &'static str = "new-extension-entry";
+const ID_EXTENSION_CASE_COMBO_BOX: &'static str = "extension-case-combo-box";
+const ID_MAP_ALIASES_CHECK_BUTTON: &'static str = "map-aliases-check-button";
+const ID_EXTENSION_STATUS_LABEL: &'static str = "extension-status-label";
+
+/// Number of the leading bytes read to detect the content type
+const SNIFF_LENGTH: u64 = 4096;
+
+/// Maximum number of the names listed in the status
+const MAX_LISTED_NAMES: usize = 10;
+
+/// Content types and the extensions of them. The first extension is the one to set.
+///
+/// The specific types come first since a type matches its subclasses, e.g. `application/zip`
+/// matches `application/epub+zip`.
+const CONTENT_TYPE_EXTENSIONS: &[(&str, &[&str])] = &[
+    ("image/jpeg", &["jpg", "jpeg", "jpe", "jfif"]),
+    ("image/png", &["png"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+    (
+        "image/tiff",
+        &["tiff", "tif", "dng", "cr2", "nef", "arw", "orf", "pef"],
+    ),
+    ("image/bmp", &["bmp"]),
+    ("image/heif", &["heic", "heif"]),
+    ("image/avif", &["avif"]),
+    ("image/svg+xml", &["svg"]),
+    ("image/vnd.microsoft.icon", &["ico"]),
+    ("application/pdf", &["pdf"]),
+    ("application/epub+zip", &["epub"]),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        &["docx"],
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        &["xlsx"],
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        &["pptx"],
+    ),
+    ("application/vnd.oasis.opendocument.text", &["odt"]),
+    ("application/vnd.oasis.opendocument.spreadsheet", &["ods"]),
+    ("application/vnd.oasis.opendocument.presentation", &["odp"]),
+    ("application/x-java-archive", &["jar"]),
+    ("application/vnd.android.package-archive", &["apk"]),
+    (
+        "application/zip",
+        &[
+            "zip", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "cbz",
+        ],
+    ),
+    ("application/gzip", &["gz", "tgz"]),
+    ("application/x-xz", &["xz", "txz"]),
+    ("application/x-bzip", &["bz2", "tbz2"]),
+    ("application/x-7z-compressed", &["7z"]),
+    ("application/vnd.rar", &["rar", "cbr"]),
+    ("application/x-tar", &["tar"]),
+    ("video/mp4", &["mp4", "m4v"]),
+    ("video/quicktime", &["mov"]),
+    ("video/x-matroska", &["mkv"]),
+    ("video/webm", &["webm"]),
+    ("video/x-msvideo", &["avi"]),
+    ("audio/mpeg", &["mp3"]),
+    ("audio/flac", &["flac"]),
+    ("audio/mp4", &["m4a", "m4b"]),
+    ("audio/x-wav", &["wav"]),
+    ("audio/ogg", &["ogg", "oga", "opus"]),
+];
+
+/// Aliases of the extensions and the extensions to map them to
+const EXTENSION_ALIASES: &[(&str, &str)] = &[
+    ("jpeg", "jpg"),
+    ("jpe", "jpg"),
+    ("jfif", "jpg"),
+    ("tif", "tiff"),
+    ("htm", "html"),
+    ("mpeg", "mpg"),
+    ("yml", "yaml"),
+    ("markdown", "md"),
+    ("text", "txt"),
+];
+
+/// How to change the extension
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum ExtensionMode {
+    /// Keep the extension
+    Keep,
+    /// Set the extension to the new one
+    Set,
+    /// Replace the extension matching the one to replace
+    Replace,
+    /// Add the extension detected from the content to the files without extension
+    AddFromContent,
+    /// Set the extension detected from the content if the extension is missing or wrong
+    FixFromContent,
+}
+
+/// Case of the new extension
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum ExtensionCase {
+    Keep,
+    Lower,
+    Upper,
+}
+
+impl ExtensionCase {
+    fn apply(self, extension: String) -> String {
+        match self {
+            ExtensionCase::Keep => extension,
+            ExtensionCase::Lower => extension.to_lowercase(),
+            ExtensionCase::Upper => extension.to_uppercase(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ExtensionRule {
+    mode: ExtensionMode,
+    /// Extension to replace in `ExtensionMode::Replace`; empty for the files without extension
+    replace_from: String,
+    /// New extension; empty to remove the extension
+    new_extension: String,
+    /// Map the aliases such as `jpeg` to `jpg`
+    map_aliases: bool,
+    case: ExtensionCase,
+}
+
+impl ExtensionRule {
+    /// Returns the new extension of the file. `content_type` is the content type of the file in
+    /// `CONTENT_TYPE_EXTENSIONS`, if detected.
+    fn apply(&self, extension: Option<&str>, content_type: Option<&str>) -> Option<String> {
+        let trim_dot = |v: &str| v.trim().trim_start_matches('.').to_string();
+        let detected = content_type.and_then(extensions_of_content_type);
+        let new_extension = match self.mode {
+            ExtensionMode::Keep => extension.map(|v| v.to_string()),
+            ExtensionMode::Set => Some(trim_dot(&self.new_extension)),
+            ExtensionMode::Replace => {
+                let replace_from = trim_dot(&self.replace_from);
+                if extension
+                    .unwrap_or_default()
+                    .eq_ignore_ascii_case(replace_from.as_str())
+                {
+                    Some(trim_dot(&self.new_extension))
+                } else {
+                    extension.map(|v| v.to_string())
+                }
+            }
+            ExtensionMode::AddFromContent => match (extension, detected) {
+                (None, Some(extensions)) => Some(extensions[0].to_string()),
+                _ => extension.map(|v| v.to_string()),
+            },
+            ExtensionMode::FixFromContent => match detected {
+                Some(extensions) if !is_extension_of(extension, extensions) => {
+                    Some(extensions[0].to_string())
+                }
+                _ => extension.map(|v| v.to_string()),
+            },
+        }
+        .filter(|v| !v.is_empty());
+
+        new_extension
+            .map(|v| if self.map_aliases { map_alias(v) } else { v })
+            .map(|v| self.case.apply(v))
+    }
+}
+
+/// Returns the extensions of the content type in `CONTENT_TYPE_EXTENSIONS`.
+fn extensions_of_content_type(content_type: &str) -> Option<&'static [&'static str]> {
+    CONTENT_TYPE_EXTENSIONS
+        .iter()
+        .find(|(v, _)| *v == content_type)
+        .map(|(_, extensions)| *extensions)
+}
+
+/// Returns `true` if `extension` is one of `extensions`, ignoring case.
+fn is_extension_of(extension: Option<&str>, extensions: &[&str]) -> bool {
+    match extension {
+        Some(extension) => extensions.iter().any(|v| v.eq_ignore_ascii_case(extension)),
+        None => false,
+    }
+}
+
+/// Map the alias of the extension, keeping the extension in upper case if so.
+fn map_alias(extension: String) -> String {
+    match EXTENSION_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(extension.as_str()))
+    {
+        Some((_, v)) if extension == extension.to_uppercase() => v.to_uppercase(),
+        Some((_, v)) => v.to_string(),
+        None => extension,
+    }
+}
+
+/// Returns the content type in `CONTENT_TYPE_EXTENSIONS` detected from the leading bytes of
+/// the file, regardless of the name of the file.
+fn sniff_content_type(path: &Path) -> Option<&'static str> {
+    let mut data = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LENGTH)
+        .read_to_end(&mut data)
+        .ok()?;
+    let (content_type, _) = gio::content_type_guess(None::<&Path>, &data);
+    if gio::content_type_is_unknown(&content_type) {
+        return None;
+    }
+    CONTENT_TYPE_EXTENSIONS
+        .iter()
+        .map(|(v, _)| *v)
+        .find(|v| gio::content_type_is_a(&content_type, v))
+}
+
+/// Content type detected from the file, valid while the file is not modified
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct SniffedContentType {
+    modified: Option<SystemTime>,
+    len: u64,
+    content_type: Option<&'static str>,
+}
+
+pub struct ExtensionRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+    /// Content types detected from the files in the file list
+    content_type_cache: RefCell<HashMap<PathBuf, SniffedContentType>>,
+}
+
+impl ExtensionRenamer {
+    pub fn new() -> Self {
+        let builder = Builder::from_resource(&resource_path("provider/extension_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+            content_type_cache: RefCell::new(HashMap::new()),
+        };
+
+        renamer.init_callback();
+        Self::update_sensitivity(&renamer.builder);
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::Extension;
+        let extension_mode_combo_box = self.object::<ComboBoxText>(ID_EXTENSION_MODE_COMBO_BOX);
+        let replace_from_entry = self.object::<Entry>(ID_REPLACE_FROM_ENTRY);
+        let new_extension_entry = self.object::<Entry>(ID_NEW_EXTENSION_ENTRY);
+        let extension_case_combo_box = self.object::<ComboBoxText>(ID_EXTENSION_CASE_COMBO_BOX);
+        let map_aliases_check_button = self.object::<CheckButton>(ID_MAP_ALIASES_CHECK_BUTTON);
+
+        let builder = self.builder.clone();
+        let change_subject = self.change_subject.clone();
+        extension_mode_combo_box.connect_changed(move |_| {
+            Self::update_sensitivity(&builder);
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        replace_from_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        new_extension_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        extension_case_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        map_aliases_check_button.connect_toggled(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    /// Enable only the entries used by the selected mode.
+    fn update_sensitivity(builder: &Builder) {
+        let mode = builder
+            .object::<ComboBoxText>(ID_EXTENSION_MODE_COMBO_BOX)
+            .unwrap()
+            .active_id()
+            .and_then(|id| ExtensionMode::from_str(id.as_str()).ok());
+
+        builder
+            .object::<Entry>(ID_REPLACE_FROM_ENTRY)
+            .unwrap()
+            .set_sensitive(mode == Some(ExtensionMode::Replace));
+        builder
+            .object::<Entry>(ID_NEW_EXTENSION_ENTRY)
+            .unwrap()
+            .set_sensitive(matches!(
+                mode,
+                Some(ExtensionMode::Set) | Some(ExtensionMode::Replace)
+            ));
+    }
+
+    fn get_replacement_rule(&self) -> Option<ExtensionRule> {
+        let mode = self
+            .object::<ComboBoxText>(ID_EXTENSION_MODE_COMBO_BOX)
+            .active_id()
+            .and_then(|id| ExtensionMode::from_str(id.as_str()).ok())?;
+        let case = self
+            .object::<ComboBoxText>(ID_EXTENSION_CASE_COMBO_BOX)
+            .active_id()
+            .and_then(|id| ExtensionCase::from_str(id.as_str()).ok())?;
+
+        Some(ExtensionRule {
+            mode,
+            replace_from: self
+                .object::<Entry>(ID_REPLACE_FROM_ENTRY)
+                .text()
+                .to_string(),
+            new_extension: self
+                .object::<Entry>(ID_NEW_EXTENSION_ENTRY)
+                .text()
+                .to_string(),
+            map_aliases: self
+                .object::<CheckButton>(ID_MAP_ALIASES_CHECK_BUTTON)
+                .is_active(),
+            case,
+        })
+    }
+
+    /// Returns the content types of the files. The files are read only if they are new to the
+    /// cache or modified since read; the files no longer in `files` are removed from the cache.
+    fn content_types_of(&self, files: &[(String, String)]) -> Vec<Option<&'static str>> {
+        let paths = files
+            .iter()
+            .map(|(file_name, dir_name)| Path::new(dir_name).join(file_name))
+            .collect::<Vec<_>>();
+        let mut cache = self.content_type_cache.borrow_mut();
+        let current_paths = paths.iter().collect::<HashSet<_>>();
+        cache.retain(|path, _| current_paths.contains(&path));
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let metadata = path.metadata().ok();
+                let modified = metadata.as_ref().and_then(|v| v.modified().ok());
+                let len = metadata.as_ref().map(|v| v.len()).unwrap_or_default();
+                match cache.get(&path) {
+                    Some(sniffed) if sniffed.modified == modified && sniffed.len == len => {
+                        sniffed.content_type
+                    }
+                    _ => {
+                        let content_type = sniff_content_type(&path);
+                        cache.insert(
+                            path,
+                            SniffedContentType {
+                                modified,
+                                len,
+                                content_type,
+                            },
+                        );
+                        content_type
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Change the extensions of the files. `content_types` are the content types detected from
+    /// the files. The extension is changed regardless of the target.
+    fn apply_replace_with(
+        rule: &ExtensionRule,
+        files: &[(String, String)],
+        content_types: &[Option<&str>],
+        _target: RenamerTarget,
+    ) -> IntoIter<(String, String)> {
+        files
+            .iter()
+            .zip(content_types.iter())
+            .map(|((file_name, dir_name), content_type)| {
+                let (stem, extension) = split_file_at_dot(file_name.as_str());
+                let new_file_name = match rule.apply(extension, *content_type) {
+                    Some(new_extension) => [stem, new_extension.as_str()].join("."),
+                    None => stem.to_string(),
+                };
+                (new_file_name, dir_name.clone())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn set_status(&self, text: &str) {
+        let extension_status_label = self.object::<Label>(ID_EXTENSION_STATUS_LABEL);
+        extension_status_label.set_text(text);
+        extension_status_label.set_tooltip_text(Some(text));
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for ExtensionRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_EXTENSION_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        self.apply_replacement_with_flags(files, target)
+            .map(|(renamed_files, _)| renamed_files)
+    }
+
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        let rule = self.get_replacement_rule().unwrap();
+        let content_types = self.content_types_of(files);
+        let renamed_files = Self::apply_replace_with(&rule, files, &content_types, target);
+        let changed = files
+            .iter()
+            .zip(renamed_files.as_slice())
+            .filter(|((file_name, _), (new_file_name, _))| file_name != new_file_name)
+            .count();
+        let mismatches = find_content_mismatches(files, &content_types);
+        self.set_status(format_status(changed, &mismatches).as_str());
+        // The files whose extension disagrees with the content are flagged in the file list.
+        let flags = files
+            .iter()
+            .zip(content_types.iter())
+            .map(|((file_name, _), content_type)| {
+                content_mismatch(file_name.as_str(), *content_type).is_some()
+            })
+            .collect();
+        Ok((renamed_files, flags))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+/// Returns the names of the files whose extension disagrees with the detected content type,
+/// with the content type.
+fn find_content_mismatches<'a>(
+    files: &[(String, String)],
+    content_types: &[Option<&'a str>],
+) -> Vec<(String, &'a str)> {
+    files
+        .iter()
+        .zip(content_types.iter())
+        .filter_map(|((file_name, _), content_type)| {
+            content_mismatch(file_name.as_str(), *content_type)
+                .map(|content_type| (file_name.clone(), content_type))
+        })
+        .collect()
+}
+
+/// Returns the content type if the extension of `file_name` disagrees with it.
+/// The files without extension are not mismatches.
+fn content_mismatch<'a>(file_name: &str, content_type: Option<&'a str>) -> Option<&'a str> {
+    let content_type = content_type?;
+    let extension = split_file_at_dot(file_name).1?;
+    let extensions = extensions_of_content_type(content_type)?;
+    if is_extension_of(Some(extension), extensions) {
+        None
+    } else {
+        Some(content_type)
+    }
+}
+
+fn format_status(changed: usize, mismatches: &[(String, &str)]) -> String {
+    let status = format!("{} to change", format_count(changed, "extension"));
+    if mismatches.is_empty() {
+        status
+    } else {
+        let mut listed = mismatches
+            .iter()
+            .take(MAX_LISTED_NAMES)
+            .map(|(name, content_type)| format!("{} ({})", name, content_type))
+            .collect::<Vec<_>>();
+        if mismatches.len() > MAX_LISTED_NAMES {
+            listed.push("…".to_string());
+        }
+        format!(
+            "{}, \u{26a0} extension disagrees with the content in {}: {}",
+            status,
+            format_count(mismatches.len(), "name"),
+            listed.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::Window;
+
+    fn rule(mode: ExtensionMode, replace_from: &str, new_extension: &str) -> ExtensionRule {
+        ExtensionRule {
+            mode,
+            replace_from: replace_from.to_string(),
+            new_extension: new_extension.to_string(),
+            map_aliases: false,
+            case: ExtensionCase::Keep,
+        }
+    }
+
+    #[test]
+    fn test_extension_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let extension_renamer = ExtensionRenamer::new();
+            let extension_mode_combo_box =
+                extension_renamer.object::<ComboBoxText>(ID_EXTENSION_MODE_COMBO_BOX);
+            let replace_from_entry = extension_renamer.object::<Entry>(ID_REPLACE_FROM_ENTRY);
+            let new_extension_entry = extension_renamer.object::<Entry>(ID_NEW_EXTENSION_ENTRY);
+            let extension_case_combo_box =
+                extension_renamer.object::<ComboBoxText>(ID_EXTENSION_CASE_COMBO_BOX);
+            let map_aliases_check_button =
+                extension_renamer.object::<CheckButton>(ID_MAP_ALIASES_CHECK_BUTTON);
+
+            extension_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&extension_renamer.get_panel())
+                .build()
+                .show_all();
+
+            assert!(!replace_from_entry.is_sensitive());
+            assert!(!new_extension_entry.is_sensitive());
+
+            counter_observer.reset();
+            extension_mode_combo_box.set_active_id(Some("Replace"));
+            assert_eq!(counter_observer.count(), 1);
+            assert!(replace_from_entry.is_sensitive());
+            assert!(new_extension_entry.is_sensitive());
+
+            counter_observer.reset();
+            replace_from_entry.set_text("jpeg");
+            new_extension_entry.set_text("jpg");
+            assert_eq!(counter_observer.count(), 2);
+
+            counter_observer.reset();
+            extension_case_combo_box.set_active_id(Some("Upper"));
+            map_aliases_check_button.set_active(true);
+            assert_eq!(counter_observer.count(), 2);
+
+            assert_eq!(
+                extension_renamer.get_replacement_rule(),
+                Some(ExtensionRule {
+                    mode: ExtensionMode::Replace,
+                    replace_from: "jpeg".to_string(),
+                    new_extension: "jpg".to_string(),
+                    map_aliases: true,
+                    case: ExtensionCase::Upper,
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn test_extension_rule() {
+        let set = rule(ExtensionMode::Set, "", ".md");
+        assert_eq!(set.apply(Some("txt"), None), Some("md".to_string()));
+        assert_eq!(set.apply(None, None), Some("md".to_string()));
+        assert_eq!(
+            rule(ExtensionMode::Set, "", "").apply(Some("txt"), None),
+            None
+        );
+
+        let replace = rule(ExtensionMode::Replace, "JPEG", "jpg");
+        assert_eq!(replace.apply(Some("jpeg"), None), Some("jpg".to_string()));
+        assert_eq!(replace.apply(Some("png"), None), Some("png".to_string()));
+        assert_eq!(replace.apply(None, None), None);
+        let replace = rule(ExtensionMode::Replace, "", "txt");
+        assert_eq!(replace.apply(None, None), Some("txt".to_string()));
+        assert_eq!(replace.apply(Some("md"), None), Some("md".to_string()));
+
+        let add = rule(ExtensionMode::AddFromContent, "", "");
+        assert_eq!(add.apply(None, Some("image/png")), Some("png".to_string()));
+        assert_eq!(
+            add.apply(Some("jpg"), Some("image/png")),
+            Some("jpg".to_string())
+        );
+        assert_eq!(add.apply(None, None), None);
+
+        let fix = rule(ExtensionMode::FixFromContent, "", "");
+        assert_eq!(fix.apply(None, Some("image/jpeg")), Some("jpg".to_string()));
+        assert_eq!(
+            fix.apply(Some("png"), Some("image/jpeg")),
+            Some("jpg".to_string())
+        );
+        assert_eq!(
+            fix.apply(Some("JPEG"), Some("image/jpeg")),
+            Some("JPEG".to_string())
+        );
+        assert_eq!(
+            fix.apply(Some("docx"), Some("application/zip")),
+            Some("docx".to_string())
+        );
+        assert_eq!(fix.apply(Some("csv"), None), Some("csv".to_string()));
+
+        let keep = ExtensionRule {
+            map_aliases: true,
+            case: ExtensionCase::Lower,
+            ..rule(ExtensionMode::Keep, "", "")
+        };
+        assert_eq!(keep.apply(Some("JPEG"), None), Some("jpg".to_string()));
+        assert_eq!(keep.apply(Some("Tif"), None), Some("tiff".to_string()));
+        assert_eq!(keep.apply(Some("PNG"), None), Some("png".to_string()));
+        assert_eq!(keep.apply(None, None), None);
+
+        assert_eq!(map_alias("JPEG".to_string()), "JPG");
+        assert_eq!(map_alias("Jpeg".to_string()), "jpg");
+    }
+
+    #[test]
+    fn test_extension_renamer_apply_replacement_with() {
+        let files = [
+            ("photo.jpeg".to_string(), "/tmp".to_string()),
+            ("download".to_string(), "/tmp".to_string()),
+            ("image.txt".to_string(), "/tmp".to_string()),
+            ("notes.txt".to_string(), "/tmp".to_string()),
+        ];
+        let content_types = [
+            Some("image/jpeg"),
+            Some("application/pdf"),
+            Some("image/png"),
+            None,
+        ];
+
+        assert_eq!(
+            ExtensionRenamer::apply_replace_with(
+                &rule(ExtensionMode::FixFromContent, "", ""),
+                &files,
+                &content_types,
+                RenamerTarget::Name
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("photo.jpeg".to_string(), "/tmp".to_string()),
+                ("download.pdf".to_string(), "/tmp".to_string()),
+                ("image.png".to_string(), "/tmp".to_string()),
+                ("notes.txt".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            ExtensionRenamer::apply_replace_with(
+                &rule(ExtensionMode::Set, "", ""),
+                &files[..2],
+                &content_types[..2],
+                RenamerTarget::All
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("photo".to_string(), "/tmp".to_string()),
+                ("download".to_string(), "/tmp".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_content_mismatches() {
+        let files = [
+            ("photo.jpeg".to_string(), "/tmp".to_string()),
+            ("download".to_string(), "/tmp".to_string()),
+            ("image.txt".to_string(), "/tmp".to_string()),
+            ("notes.txt".to_string(), "/tmp".to_string()),
+        ];
+        let content_types = [
+            Some("image/jpeg"),
+            Some("application/pdf"),
+            Some("image/png"),
+            None,
+        ];
+
+        let mismatches = find_content_mismatches(&files, &content_types);
+        assert_eq!(mismatches, vec![("image.txt".to_string(), "image/png")]);
+        assert_eq!(
+            format_status(1, &mismatches),
+            "1 extension to change, \u{26a0} extension disagrees with the content in 1 name: image.txt (image/png)"
+        );
+        assert_eq!(format_status(0, &[]), "0 extensions to change");
+    }
+
+    #[test]
+    fn test_extension_renamer_content_type_cache() {
+        test_synced(move || {
+            let extension_renamer = ExtensionRenamer::new();
+            let temp_dir = tempfile::tempdir().unwrap();
+            let dir_name = temp_dir.path().to_str().unwrap().to_string();
+            std::fs::write(temp_dir.path().join("image.txt"), b"\x89PNG\r\n\x1a\n").unwrap();
+            std::fs::write(temp_dir.path().join("notes.txt"), "notes").unwrap();
+            let files = [
+                ("image.txt".to_string(), dir_name.clone()),
+                ("notes.txt".to_string(), dir_name.clone()),
+            ];
+
+            assert_eq!(
+                extension_renamer.content_types_of(&files),
+                vec![Some("image/png"), None]
+            );
+            assert_eq!(extension_renamer.content_type_cache.borrow().len(), 2);
+
+            std::fs::write(temp_dir.path().join("image.txt"), "%PDF-1.4\n").unwrap();
+            assert_eq!(
+                extension_renamer.content_types_of(&files[..1]),
+                vec![Some("application/pdf")]
+            );
+            assert_eq!(extension_renamer.content_type_cache.borrow().len(), 1);
+
+            let (_, flags) = extension_renamer
+                .apply_replacement_with_flags(&files, RenamerTarget::Name)
+                .unwrap();
+            assert_eq!(flags, vec![true, false]);
+        });
+    }
+}
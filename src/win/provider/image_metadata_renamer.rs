@@ -0,0 +1,585 @@
+use crate::error::Error;
+use crate::utils::{set_unknown_placeholders_icon, PlaceholderTemplate, TemplateItem};
+use crate::utils::{
+    split_file_at_dot, BulkTextReplacement, InsertPosition, TextCharPosition, TextInsertOrOverwrite,
+};
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, ComboBoxText, Container, Entry, SpinButton};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use strum_macros::EnumString;
+
+const ID_IMAGE_METADATA_RENAMER_PANEL: &'static str = "image-metadata-renamer-panel";
+const ID_METADATA_TEMPLATE_ENTRY: &'static str = "metadata-template-entry";
+const ID_FALLBACK_ENTRY: &'static str = "fallback-entry";
+const ID_AT_POSITION_SPINNER_BUTTON: &'static str = "at-position-spin-button";
+const ID_AT_POSITION_COMBO_BOX: &'static str = "at-position-combo-box";
+
+/// Field of the image metadata written as `{name}` in the template
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum MetadataField {
+    Make,
+    Model,
+    Lens,
+    /// Focal length in millimeters
+    FocalLength,
+    Iso,
+    /// Exposure time in seconds, e.g. `1-250` for 1/250 s
+    Exposure,
+    /// F-number
+    Aperture,
+    /// EXIF orientation from 1 to 8
+    Orientation,
+    Width,
+    Height,
+    /// Latitude in degrees, negative in the southern hemisphere
+    Latitude,
+    /// Longitude in degrees, negative in the western hemisphere
+    Longitude,
+    UniqueId,
+}
+
+/// Text with the placeholders of the image metadata such as `{model}_{lens}`
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct MetadataTemplate(PlaceholderTemplate<MetadataField>);
+
+impl MetadataTemplate {
+    fn parse(template: &str) -> Self {
+        Self(PlaceholderTemplate::parse(template, |name| {
+            MetadataField::from_str(name).ok()
+        }))
+    }
+
+    /// Expand the placeholders with the fallback text for the missing tags.
+    fn expand(&self, metadata: &ImageMetadata, fallback: &str) -> String {
+        self.0
+            .items
+            .iter()
+            .map(|item| match item {
+                TemplateItem::Text(text) => text.clone(),
+                TemplateItem::Placeholder(field) => metadata
+                    .value_of(*field)
+                    .unwrap_or_else(|| fallback.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// EXIF of the image
+struct ImageMetadata(exif::Exif);
+
+impl ImageMetadata {
+    fn read(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        exif::Reader::new()
+            .read_from_container(&mut BufReader::new(&file))
+            .ok()
+            .map(Self)
+    }
+
+    /// Returns the value of the field usable in the file name.
+    fn value_of(&self, field: MetadataField) -> Option<String> {
+        match field {
+            MetadataField::Make => self.ascii_of(exif::Tag::Make),
+            MetadataField::Model => self.ascii_of(exif::Tag::Model),
+            MetadataField::Lens => self.ascii_of(exif::Tag::LensModel),
+            MetadataField::FocalLength => self
+                .rational_of(exif::Tag::FocalLength)
+                .map(|(num, denom)| format_decimal(num / denom)),
+            MetadataField::Iso => self
+                .uint_of(exif::Tag::PhotographicSensitivity)
+                .map(|v| v.to_string()),
+            MetadataField::Exposure => {
+                self.rational_of(exif::Tag::ExposureTime)
+                    .map(|(num, denom)| {
+                        if num < denom {
+                            // "/" is not allowed in the file name
+                            format!("1-{}", format_decimal(denom / num))
+                        } else {
+                            format_decimal(num / denom)
+                        }
+                    })
+            }
+            MetadataField::Aperture => self
+                .rational_of(exif::Tag::FNumber)
+                .map(|(num, denom)| format_decimal(num / denom)),
+            MetadataField::Orientation => {
+                self.uint_of(exif::Tag::Orientation).map(|v| v.to_string())
+            }
+            MetadataField::Width => self
+                .uint_of(exif::Tag::PixelXDimension)
+                .or_else(|| self.uint_of(exif::Tag::ImageWidth))
+                .map(|v| v.to_string()),
+            MetadataField::Height => self
+                .uint_of(exif::Tag::PixelYDimension)
+                .or_else(|| self.uint_of(exif::Tag::ImageLength))
+                .map(|v| v.to_string()),
+            MetadataField::Latitude => {
+                self.coordinate_of(exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, "S")
+            }
+            MetadataField::Longitude => {
+                self.coordinate_of(exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, "W")
+            }
+            MetadataField::UniqueId => self.ascii_of(exif::Tag::ImageUniqueID),
+        }
+    }
+
+    fn ascii_of(&self, tag: exif::Tag) -> Option<String> {
+        let text = match self.0.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(ref vec) if !vec.is_empty() => String::from_utf8_lossy(&vec[0])
+                .trim_end_matches('\0')
+                .trim()
+                .replace(['/', '\0'], "_"),
+            _ => return None,
+        };
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    fn uint_of(&self, tag: exif::Tag) -> Option<u32> {
+        self.0.get_field(tag, exif::In::PRIMARY)?.value.get_uint(0)
+    }
+
+    /// Returns the numerator and the denominator of the positive rational value.
+    fn rational_of(&self, tag: exif::Tag) -> Option<(f64, f64)> {
+        match self.0.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Rational(ref vec) if !vec.is_empty() => {
+                let value = vec[0];
+                if value.num > 0 && value.denom > 0 {
+                    Some((f64::from(value.num), f64::from(value.denom)))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the GPS coordinate in decimal degrees.
+    fn coordinate_of(
+        &self,
+        tag: exif::Tag,
+        ref_tag: exif::Tag,
+        negative_ref: &str,
+    ) -> Option<String> {
+        let degrees = match self.0.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Rational(ref vec) if vec.len() >= 3 => {
+                vec[0].to_f64() + vec[1].to_f64() / 60.0 + vec[2].to_f64() / 3600.0
+            }
+            _ => return None,
+        };
+        if !degrees.is_finite() {
+            return None;
+        }
+        let sign = if self.ascii_of(ref_tag).as_deref() == Some(negative_ref) {
+            -1.0
+        } else {
+            1.0
+        };
+        Some(format!("{:.6}", sign * degrees))
+    }
+}
+
+/// Format the number with one decimal place at most, e.g. "50" or "2.8".
+fn format_decimal(value: f64) -> String {
+    let text = format!("{:.1}", value);
+    text.strip_suffix(".0").unwrap_or(text.as_str()).to_string()
+}
+
+struct ImageMetadataRule {
+    template: MetadataTemplate,
+    /// Text inserted for the missing tags
+    fallback: String,
+    position: InsertPosition,
+}
+
+pub struct ImageMetadataRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl ImageMetadataRenamer {
+    pub fn new() -> Self {
+        let builder =
+            Builder::from_resource(&resource_path("provider/image_metadata_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::ImageMetadata;
+        let metadata_template_entry = self.object::<Entry>(ID_METADATA_TEMPLATE_ENTRY);
+        let fallback_entry = self.object::<Entry>(ID_FALLBACK_ENTRY);
+        let at_position_spin_button = self.object::<SpinButton>(ID_AT_POSITION_SPINNER_BUTTON);
+        let at_position_combo_box = self.object::<ComboBoxText>(ID_AT_POSITION_COMBO_BOX);
+
+        let change_subject = self.change_subject.clone();
+        metadata_template_entry.connect_changed(move |metadata_template_entry| {
+            let template = MetadataTemplate::parse(metadata_template_entry.text().as_str());
+            set_unknown_placeholders_icon(
+                metadata_template_entry,
+                &template.0.unknown_placeholders,
+            );
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        fallback_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        at_position_spin_button.connect_value_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        at_position_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    fn get_replacement_rule(&self) -> ImageMetadataRule {
+        let metadata_template_entry = self.object::<Entry>(ID_METADATA_TEMPLATE_ENTRY);
+        let fallback_entry = self.object::<Entry>(ID_FALLBACK_ENTRY);
+        let at_position_spin_button = self.object::<SpinButton>(ID_AT_POSITION_SPINNER_BUTTON);
+        let at_position_combo_box = self.object::<ComboBoxText>(ID_AT_POSITION_COMBO_BOX);
+
+        let pos = usize::try_from(at_position_spin_button.value_as_int()).unwrap_or(0);
+        let insert_position = InsertPosition(
+            at_position_combo_box
+                .active_id()
+                .and_then(|id| TextCharPosition::from_str_usize(id.as_str(), pos))
+                .unwrap_or(TextCharPosition::Front(pos)),
+            TextInsertOrOverwrite::Insert,
+        );
+
+        ImageMetadataRule {
+            template: MetadataTemplate::parse(metadata_template_entry.text().as_str()),
+            fallback: fallback_entry.text().to_string(),
+            position: insert_position,
+        }
+    }
+
+    fn apply_replace_with(
+        rule: &ImageMetadataRule,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> IntoIter<(String, String)> {
+        let position = rule.position;
+        files
+            .iter()
+            .map(|(file_name, dir_name)| {
+                let path = PathBuf::from(dir_name).join(file_name);
+
+                // the files without EXIF such as documents are kept
+                if let Some(metadata) = ImageMetadata::read(path.as_path()) {
+                    let text = rule.template.expand(&metadata, rule.fallback.as_str());
+                    let new_file_name = match target {
+                        RenamerTarget::Name => {
+                            let (stem, extension) = split_file_at_dot(file_name.as_str());
+                            let new_stem = position.apply_to(stem, text.as_str());
+                            if let Some(suffix) = extension {
+                                [new_stem.as_str(), suffix].join(".").to_string()
+                            } else {
+                                new_stem
+                            }
+                        }
+                        RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
+                            (stem, Some(suffix)) => {
+                                let new_suffix = position.apply_to(suffix, text.as_str());
+                                [stem, new_suffix.as_str()].join(".").to_string()
+                            }
+                            (stem, None) => stem.to_string(),
+                        },
+                        RenamerTarget::All => position.apply_to(file_name.as_str(), text.as_str()),
+                    };
+                    (new_file_name, dir_name.clone())
+                } else {
+                    (file_name.to_string(), dir_name.clone())
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for ImageMetadataRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_IMAGE_METADATA_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        let rule = self.get_replacement_rule();
+        Ok(Self::apply_replace_with(&rule, files, target))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::{EntryIconPosition, Window};
+
+    fn ascii_field(tag: exif::Tag, value: &str) -> exif::Field {
+        exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    fn write_exif(fields: &[exif::Field]) -> Vec<u8> {
+        let mut writer = exif::experimental::Writer::new();
+        fields.iter().for_each(|field| writer.push_field(field));
+        let mut buf = std::io::Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        buf.into_inner()
+    }
+
+    fn camera_fields() -> Vec<exif::Field> {
+        let rational = |values: &[(u32, u32)]| {
+            exif::Value::Rational(values.iter().map(|&v| exif::Rational::from(v)).collect())
+        };
+        let field = |tag, value| exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value,
+        };
+        vec![
+            ascii_field(exif::Tag::Make, "Canon"),
+            ascii_field(exif::Tag::Model, "Canon EOS R5"),
+            ascii_field(exif::Tag::LensModel, "EF24-70mm f/2.8L II USM"),
+            field(exif::Tag::FocalLength, rational(&[(50, 1)])),
+            field(
+                exif::Tag::PhotographicSensitivity,
+                exif::Value::Short(vec![400]),
+            ),
+            field(exif::Tag::ExposureTime, rational(&[(10, 2500)])),
+            field(exif::Tag::FNumber, rational(&[(28, 10)])),
+            field(exif::Tag::Orientation, exif::Value::Short(vec![6])),
+            field(exif::Tag::PixelXDimension, exif::Value::Long(vec![8192])),
+            field(exif::Tag::PixelYDimension, exif::Value::Long(vec![5464])),
+            ascii_field(exif::Tag::GPSLatitudeRef, "S"),
+            field(
+                exif::Tag::GPSLatitude,
+                rational(&[(33, 1), (51, 1), (2460, 100)]),
+            ),
+            ascii_field(exif::Tag::GPSLongitudeRef, "E"),
+            field(
+                exif::Tag::GPSLongitude,
+                rational(&[(151, 1), (12, 1), (3600, 100)]),
+            ),
+            ascii_field(exif::Tag::ImageUniqueID, "0123456789abcdef0123456789abcdef"),
+        ]
+    }
+
+    #[test]
+    fn test_image_metadata_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let image_metadata_renamer = ImageMetadataRenamer::new();
+            let metadata_template_entry =
+                image_metadata_renamer.object::<Entry>(ID_METADATA_TEMPLATE_ENTRY);
+            let fallback_entry = image_metadata_renamer.object::<Entry>(ID_FALLBACK_ENTRY);
+            let at_position_spin_button =
+                image_metadata_renamer.object::<SpinButton>(ID_AT_POSITION_SPINNER_BUTTON);
+            let at_position_combo_box =
+                image_metadata_renamer.object::<ComboBoxText>(ID_AT_POSITION_COMBO_BOX);
+
+            image_metadata_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&image_metadata_renamer.get_panel())
+                .build()
+                .show_all();
+
+            counter_observer.reset();
+            metadata_template_entry.set_text("{body}_");
+            assert_eq!(counter_observer.count(), 1);
+            assert!(metadata_template_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_some());
+
+            counter_observer.reset();
+            metadata_template_entry.set_text(" {model} {iso}");
+            fallback_entry.set_text("none");
+            at_position_spin_button.set_value(1.0);
+            at_position_spin_button.set_value(0.0);
+            at_position_combo_box.set_active_id(Some("Back"));
+            assert_eq!(counter_observer.count(), 5);
+            assert!(metadata_template_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_none());
+
+            let temp_dir = tempfile::tempdir().unwrap();
+            std::fs::write(
+                temp_dir.path().join("IMG_0001.tif"),
+                write_exif(&[ascii_field(exif::Tag::Model, "Canon EOS R5")]),
+            )
+            .unwrap();
+            std::fs::write(temp_dir.path().join("notes.txt"), "notes").unwrap();
+            let dir_name = temp_dir.path().to_str().unwrap().to_string();
+            let files = [
+                ("IMG_0001.tif".to_string(), dir_name.clone()),
+                ("notes.txt".to_string(), dir_name.clone()),
+            ];
+            let renamed_files = image_metadata_renamer
+                .apply_replacement(&files, RenamerTarget::Name)
+                .unwrap()
+                .collect::<Vec<_>>();
+            assert_eq!(
+                renamed_files,
+                vec![
+                    (
+                        "IMG_0001 Canon EOS R5 none.tif".to_string(),
+                        dir_name.clone()
+                    ),
+                    ("notes.txt".to_string(), dir_name.clone()),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_metadata_template_parse() {
+        assert_eq!(
+            MetadataTemplate::parse("{model}_{focal_length}mm {body} {iso}"),
+            MetadataTemplate(PlaceholderTemplate {
+                items: vec![
+                    TemplateItem::Placeholder(MetadataField::Model),
+                    TemplateItem::Text("_".to_string()),
+                    TemplateItem::Placeholder(MetadataField::FocalLength),
+                    TemplateItem::Text("mm {body} ".to_string()),
+                    TemplateItem::Placeholder(MetadataField::Iso),
+                ],
+                unknown_placeholders: vec!["body".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_image_metadata_value_of() {
+        let exif = exif::Reader::new()
+            .read_raw(write_exif(&camera_fields()))
+            .unwrap();
+        let metadata = ImageMetadata(exif);
+        let template = MetadataTemplate::parse(
+            "{make}|{model}|{lens}|{focal_length}|{iso}|{exposure}|{aperture}|{orientation}|{width}x{height}|{latitude},{longitude}|{unique_id}",
+        );
+        assert_eq!(
+            template.expand(&metadata, "unknown"),
+            "Canon|Canon EOS R5|EF24-70mm f_2.8L II USM|50|400|1-250|2.8|6|8192x5464|-33.856833,151.210000|0123456789abcdef0123456789abcdef"
+        );
+
+        let exif = exif::Reader::new()
+            .read_raw(write_exif(&[
+                ascii_field(exif::Tag::Model, "PENTAX 645Z  \0"),
+                exif::Field {
+                    tag: exif::Tag::ExposureTime,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Rational(vec![exif::Rational::from((5, 2))]),
+                },
+                exif::Field {
+                    tag: exif::Tag::FNumber,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Rational(vec![exif::Rational::from((0, 0))]),
+                },
+            ]))
+            .unwrap();
+        let metadata = ImageMetadata(exif);
+        assert_eq!(
+            template.expand(&metadata, "unknown"),
+            "unknown|PENTAX 645Z|unknown|unknown|unknown|2.5|unknown|unknown|unknownxunknown|unknown,unknown|unknown"
+        );
+    }
+
+    #[test]
+    fn test_image_metadata_renamer_apply_replace_with() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join("IMG_0001.tar.tif"),
+            write_exif(&camera_fields()),
+        )
+        .unwrap();
+        let dir_name = temp_dir.path().to_str().unwrap().to_string();
+        let files = [("IMG_0001.tar.tif".to_string(), dir_name.clone())];
+        let rule = ImageMetadataRule {
+            template: MetadataTemplate::parse("{model}_{lens}_{gps}_"),
+            fallback: "unknown".to_string(),
+            position: InsertPosition(TextCharPosition::Front(0), TextInsertOrOverwrite::Insert),
+        };
+
+        assert_eq!(
+            ImageMetadataRenamer::apply_replace_with(&rule, &files, RenamerTarget::Name)
+                .collect::<Vec<_>>(),
+            vec![(
+                "Canon EOS R5_EF24-70mm f_2.8L II USM_{gps}_IMG_0001.tar.tif".to_string(),
+                dir_name.clone()
+            )]
+        );
+        assert_eq!(
+            ImageMetadataRenamer::apply_replace_with(&rule, &files, RenamerTarget::Suffix)
+                .collect::<Vec<_>>(),
+            vec![(
+                "IMG_0001.tar.Canon EOS R5_EF24-70mm f_2.8L II USM_{gps}_tif".to_string(),
+                dir_name.clone()
+            )]
+        );
+
+        let rule = ImageMetadataRule {
+            template: MetadataTemplate::parse(" ({width}x{height}, ISO {iso})"),
+            fallback: "unknown".to_string(),
+            position: InsertPosition(TextCharPosition::Back(0), TextInsertOrOverwrite::Insert),
+        };
+        assert_eq!(
+            ImageMetadataRenamer::apply_replace_with(&rule, &files, RenamerTarget::All)
+                .collect::<Vec<_>>(),
+            vec![(
+                "IMG_0001.tar.tif (8192x5464, ISO 400)".to_string(),
+                dir_name.clone()
+            )]
+        );
+    }
+}
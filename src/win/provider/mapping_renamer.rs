@@ -0,0 +1,585 @@
+use crate::error::Error;
+use crate::utils::{format_count, split_file_at_dot};
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, CheckButton, ComboBoxText, Container, FileChooserButton, Label, SpinButton};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use strum_macros::EnumString;
+
+const ID_MAPPING_RENAMER_PANEL: &'static str = "mapping-renamer-panel";
+const ID_MAPPING_FILE_CHOOSER_BUTTON: &'static str = "mapping-file-chooser-button";
+const ID_MAPPING_FORMAT_COMBO_BOX: &'static str = "mapping-format-combo-box";
+const ID_MATCH_BY_COMBO_BOX: &'static str = "match-by-combo-box";
+const ID_KEY_COLUMN_SPIN_BUTTON: &'static str = "key-column-spin-button";
+const ID_NEW_NAME_COLUMN_SPIN_BUTTON: &'static str = "new-name-column-spin-button";
+const ID_HEADER_ROW_CHECK_BUTTON: &'static str = "header-row-check-button";
+const ID_MAPPING_STATUS_LABEL: &'static str = "mapping-status-label";
+
+/// Maximum number of the unmatched rows listed in the status
+const MAX_LISTED_UNMATCHED_ROWS: usize = 10;
+
+#[derive(Clone, Copy, Eq, PartialEq, EnumString)]
+enum MappingFormat {
+    /// Comma separated values
+    Csv,
+    /// Tab separated values
+    Tsv,
+    /// New names, one per line in the order of the file list
+    Lines,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, EnumString)]
+enum MappingKey {
+    /// Full path of the file
+    Path,
+    /// Name of the file
+    Name,
+    /// Order of the file in the list
+    Order,
+}
+
+/// Mapping read from the mapping file
+struct Mapping {
+    /// Row number (1-based) and fields of each row
+    rows: Vec<(usize, Vec<String>)>,
+    key: MappingKey,
+    /// Column index (0-based) of the key
+    key_column: usize,
+    /// Column index (0-based) of the new name
+    new_name_column: usize,
+}
+
+pub struct MappingRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl MappingRenamer {
+    pub fn new() -> Self {
+        let builder = Builder::from_resource(&resource_path("provider/mapping_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::Mapping;
+        let mapping_file_chooser_button =
+            self.object::<FileChooserButton>(ID_MAPPING_FILE_CHOOSER_BUTTON);
+        let mapping_format_combo_box = self.object::<ComboBoxText>(ID_MAPPING_FORMAT_COMBO_BOX);
+        let match_by_combo_box = self.object::<ComboBoxText>(ID_MATCH_BY_COMBO_BOX);
+        let key_column_spin_button = self.object::<SpinButton>(ID_KEY_COLUMN_SPIN_BUTTON);
+        let new_name_column_spin_button = self.object::<SpinButton>(ID_NEW_NAME_COLUMN_SPIN_BUTTON);
+        let header_row_check_button = self.object::<CheckButton>(ID_HEADER_ROW_CHECK_BUTTON);
+
+        let change_subject = self.change_subject.clone();
+        mapping_file_chooser_button.connect_file_set(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        mapping_format_combo_box.connect_changed(glib::clone!(
+            @weak match_by_combo_box,
+            @weak key_column_spin_button,
+            @weak new_name_column_spin_button,
+            @weak header_row_check_button => move |mapping_format_combo_box| {
+            // The plain list of new names has neither keys nor columns.
+            let is_table = mapping_format_combo_box.active_id().as_deref() != Some("Lines");
+            match_by_combo_box.set_sensitive(is_table);
+            key_column_spin_button.set_sensitive(is_table);
+            new_name_column_spin_button.set_sensitive(is_table);
+            header_row_check_button.set_sensitive(is_table);
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        }));
+
+        let change_subject = self.change_subject.clone();
+        match_by_combo_box.connect_changed(glib::clone!(
+            @weak key_column_spin_button => move |match_by_combo_box| {
+            key_column_spin_button
+                .set_sensitive(match_by_combo_box.active_id().as_deref() != Some("Order"));
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        }));
+
+        let change_subject = self.change_subject.clone();
+        key_column_spin_button.connect_value_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        new_name_column_spin_button.connect_value_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        header_row_check_button.connect_toggled(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    /// Read the mapping file. Returns `None` if no file is chosen.
+    fn get_replacement_rule(&self) -> Result<Option<Mapping>, Error> {
+        let mapping_file_chooser_button =
+            self.object::<FileChooserButton>(ID_MAPPING_FILE_CHOOSER_BUTTON);
+        let mapping_format_combo_box = self.object::<ComboBoxText>(ID_MAPPING_FORMAT_COMBO_BOX);
+        let match_by_combo_box = self.object::<ComboBoxText>(ID_MATCH_BY_COMBO_BOX);
+        let key_column_spin_button = self.object::<SpinButton>(ID_KEY_COLUMN_SPIN_BUTTON);
+        let new_name_column_spin_button = self.object::<SpinButton>(ID_NEW_NAME_COLUMN_SPIN_BUTTON);
+        let header_row_check_button = self.object::<CheckButton>(ID_HEADER_ROW_CHECK_BUTTON);
+
+        let path = match mapping_file_chooser_button.filename() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let format = mapping_format_combo_box
+            .active_id()
+            .and_then(|id| MappingFormat::from_str(id.as_str()).ok())
+            .unwrap_or(MappingFormat::Csv);
+        let text = fs::read_to_string(path)?;
+        let mut rows = parse_rows(text.as_str(), format)
+            .into_iter()
+            .enumerate()
+            .map(|(i, fields)| (i + 1, fields))
+            .collect::<Vec<_>>();
+        // The plain list of new names has no header row.
+        let has_header_row = format != MappingFormat::Lines && header_row_check_button.is_active();
+        if has_header_row && !rows.is_empty() {
+            rows.remove(0);
+        }
+
+        let mapping = if format == MappingFormat::Lines {
+            Mapping {
+                rows,
+                key: MappingKey::Order,
+                key_column: 0,
+                new_name_column: 0,
+            }
+        } else {
+            let column_of = |spin_button: SpinButton| {
+                usize::try_from(spin_button.value_as_int() - 1).unwrap_or(0)
+            };
+            Mapping {
+                rows,
+                key: match_by_combo_box
+                    .active_id()
+                    .and_then(|id| MappingKey::from_str(id.as_str()).ok())
+                    .unwrap_or(MappingKey::Name),
+                key_column: column_of(key_column_spin_button),
+                new_name_column: column_of(new_name_column_spin_button),
+            }
+        };
+        Ok(Some(mapping))
+    }
+
+    /// Returns the renamed files, the row numbers of the unmatched rows and whether each file
+    /// has no matching row.
+    fn apply_replace_with(
+        mapping: &Mapping,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> (IntoIter<(String, String)>, Vec<usize>, Vec<bool>) {
+        let new_name_of = |fields: &Vec<String>| {
+            fields
+                .get(mapping.new_name_column)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+        };
+
+        // Row numbers and new names in the order of `files`
+        let mut new_names: Vec<Option<(usize, String)>> = vec![None; files.len()];
+        if mapping.key == MappingKey::Order {
+            for (i, (row, fields)) in mapping.rows.iter().enumerate().take(files.len()) {
+                new_names[i] = new_name_of(fields).map(|new_name| (*row, new_name));
+            }
+        } else {
+            let mut file_indices = HashMap::new();
+            for (i, (file_name, dir_name)) in files.iter().enumerate() {
+                let key = match mapping.key {
+                    MappingKey::Path => Path::new(dir_name).join(file_name).display().to_string(),
+                    _ => file_name.clone(),
+                };
+                file_indices.entry(key).or_insert_with(Vec::new).push(i);
+            }
+            for (row, fields) in mapping.rows.iter() {
+                let indices = fields
+                    .get(mapping.key_column)
+                    .and_then(|key| file_indices.get(key.trim()));
+                if let (Some(indices), Some(new_name)) = (indices, new_name_of(fields)) {
+                    for &i in indices.iter() {
+                        // The first row wins if some rows have the same key.
+                        if new_names[i].is_none() {
+                            new_names[i] = Some((*row, new_name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        let matched_rows = new_names
+            .iter()
+            .flatten()
+            .map(|(row, _)| *row)
+            .collect::<HashSet<_>>();
+        let unmatched_rows = mapping
+            .rows
+            .iter()
+            .map(|(row, _)| *row)
+            .filter(|row| !matched_rows.contains(row))
+            .collect::<Vec<_>>();
+        let unmatched_files = new_names.iter().map(|v| v.is_none()).collect();
+
+        let renamed_files = files
+            .iter()
+            .zip(new_names)
+            .map(|((file_name, dir_name), new_name)| {
+                let new_file_name = match new_name {
+                    Some((_, new_name)) => replace_file_name(file_name, new_name.as_str(), target),
+                    None => file_name.clone(),
+                };
+                (new_file_name, dir_name.clone())
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        (renamed_files, unmatched_rows, unmatched_files)
+    }
+
+    fn set_status(&self, text: &str) {
+        let mapping_status_label = self.object::<Label>(ID_MAPPING_STATUS_LABEL);
+        mapping_status_label.set_text(text);
+        mapping_status_label.set_tooltip_text(Some(text));
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for MappingRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_MAPPING_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        self.apply_replacement_with_flags(files, target)
+            .map(|(renamed_files, _)| renamed_files)
+    }
+
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        let mapping = match self.get_replacement_rule() {
+            Ok(Some(mapping)) => mapping,
+            Ok(None) => {
+                self.set_status("");
+                return Ok((files.to_vec().into_iter(), vec![false; files.len()]));
+            }
+            Err(e) => {
+                self.set_status(e.to_string().as_str());
+                return Err(e);
+            }
+        };
+        // The files without a matching row are flagged in the file list.
+        let (renamed_files, unmatched_rows, unmatched_files) =
+            Self::apply_replace_with(&mapping, files, target);
+        self.set_status(format_status(mapping.rows.len(), &unmatched_rows).as_str());
+        Ok((renamed_files, unmatched_files))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+/// Replace the part of `file_name` specified by `target` with `new_name`.
+fn replace_file_name(file_name: &str, new_name: &str, target: RenamerTarget) -> String {
+    match target {
+        RenamerTarget::Name => match split_file_at_dot(file_name) {
+            (_, Some(suffix)) => [new_name, suffix].join("."),
+            (_, None) => new_name.to_string(),
+        },
+        RenamerTarget::Suffix => match split_file_at_dot(file_name) {
+            (stem, Some(_)) => [stem, new_name].join("."),
+            (stem, None) => stem.to_string(),
+        },
+        RenamerTarget::All => new_name.to_string(),
+    }
+}
+
+/// Split the text of the mapping file into the rows of the fields.
+fn parse_rows(text: &str, format: MappingFormat) -> Vec<Vec<String>> {
+    let text = text.trim_start_matches('\u{feff}');
+    match format {
+        MappingFormat::Csv => parse_delimited(text, ','),
+        MappingFormat::Tsv => parse_delimited(text, '\t'),
+        MappingFormat::Lines => text.lines().map(|line| vec![line.to_string()]).collect(),
+    }
+}
+
+/// Parse the delimiter separated values. Fields can be quoted by `"` as CSV.
+fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+        } else {
+            match c {
+                '"' if field.is_empty() => in_quotes = true,
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' | '\r' => {
+                    fields.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut fields));
+                }
+                c if c == delimiter => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        rows.push(fields);
+    }
+
+    rows
+}
+
+fn format_status(row_count: usize, unmatched_rows: &[usize]) -> String {
+    if unmatched_rows.is_empty() {
+        format!("{}, all matched", format_count(row_count, "row"))
+    } else {
+        let mut listed = unmatched_rows
+            .iter()
+            .take(MAX_LISTED_UNMATCHED_ROWS)
+            .map(|row| row.to_string())
+            .collect::<Vec<_>>();
+        if unmatched_rows.len() > MAX_LISTED_UNMATCHED_ROWS {
+            listed.push("…".to_string());
+        }
+        format!(
+            "{}, {} unmatched: row {}",
+            format_count(row_count, "row"),
+            unmatched_rows.len(),
+            listed.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::Window;
+
+    fn files() -> Vec<(String, String)> {
+        vec![
+            ("a.txt".to_string(), "/tmp".to_string()),
+            ("b.txt".to_string(), "/tmp".to_string()),
+            ("c".to_string(), "/home/foo".to_string()),
+        ]
+    }
+
+    fn mapping(rows: &[&[&str]], key: MappingKey) -> Mapping {
+        Mapping {
+            rows: rows
+                .iter()
+                .enumerate()
+                .map(|(i, fields)| (i + 1, fields.iter().map(|v| v.to_string()).collect()))
+                .collect(),
+            key,
+            key_column: 0,
+            new_name_column: 1,
+        }
+    }
+
+    #[test]
+    fn test_mapping_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let mapping_renamer = MappingRenamer::new();
+            let mapping_format_combo_box =
+                mapping_renamer.object::<ComboBoxText>(ID_MAPPING_FORMAT_COMBO_BOX);
+            let match_by_combo_box = mapping_renamer.object::<ComboBoxText>(ID_MATCH_BY_COMBO_BOX);
+            let key_column_spin_button =
+                mapping_renamer.object::<SpinButton>(ID_KEY_COLUMN_SPIN_BUTTON);
+            let header_row_check_button =
+                mapping_renamer.object::<CheckButton>(ID_HEADER_ROW_CHECK_BUTTON);
+
+            mapping_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&mapping_renamer.get_panel())
+                .build()
+                .show_all();
+
+            counter_observer.reset();
+            mapping_format_combo_box.set_active_id(Some("Lines"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(!match_by_combo_box.is_sensitive());
+            assert!(!header_row_check_button.is_sensitive());
+            mapping_format_combo_box.set_active_id(Some("Csv"));
+            gtk_test::wait(1);
+            assert!(match_by_combo_box.is_sensitive());
+            assert!(header_row_check_button.is_sensitive());
+
+            counter_observer.reset();
+            match_by_combo_box.set_active_id(Some("Order"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(!key_column_spin_button.is_sensitive());
+
+            counter_observer.reset();
+            gtk_test::click(&header_row_check_button);
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+        });
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        assert_eq!(
+            parse_rows(
+                "\u{feff}a,b\r\n\"c,d\",\"e\"\"f\"\n\"g\nh\",\n",
+                MappingFormat::Csv
+            ),
+            vec![
+                vec!["a".to_string(), "b".to_string()],
+                vec!["c,d".to_string(), "e\"f".to_string()],
+                vec!["g\nh".to_string(), "".to_string()],
+            ]
+        );
+        assert_eq!(
+            parse_rows("a\tb,c\nd\te", MappingFormat::Tsv),
+            vec![
+                vec!["a".to_string(), "b,c".to_string()],
+                vec!["d".to_string(), "e".to_string()],
+            ]
+        );
+        assert_eq!(
+            parse_rows("a,b\n\"c\"\n", MappingFormat::Lines),
+            vec![vec!["a,b".to_string()], vec!["\"c\"".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_mapping_renamer_apply_replacement_with() {
+        let (renamed_files, unmatched_rows, unmatched_files) = MappingRenamer::apply_replace_with(
+            &mapping(
+                &[&["b.txt", "x.txt"], &["d.txt", "y.txt"], &["c", "z"]],
+                MappingKey::Name,
+            ),
+            &files(),
+            RenamerTarget::All,
+        );
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![
+                ("a.txt".to_string(), "/tmp".to_string()),
+                ("x.txt".to_string(), "/tmp".to_string()),
+                ("z".to_string(), "/home/foo".to_string()),
+            ]
+        );
+        assert_eq!(unmatched_rows, vec![2]);
+        assert_eq!(unmatched_files, vec![true, false, false]);
+
+        let (renamed_files, unmatched_rows, unmatched_files) = MappingRenamer::apply_replace_with(
+            &mapping(
+                &[
+                    &["/tmp/a.txt", "x"],
+                    &["/home/foo/b.txt", "y"],
+                    &["/tmp/a.txt", "z"],
+                ],
+                MappingKey::Path,
+            ),
+            &files(),
+            RenamerTarget::Name,
+        );
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![
+                ("x.txt".to_string(), "/tmp".to_string()),
+                ("b.txt".to_string(), "/tmp".to_string()),
+                ("c".to_string(), "/home/foo".to_string()),
+            ]
+        );
+        assert_eq!(unmatched_rows, vec![2, 3]);
+        assert_eq!(unmatched_files, vec![false, true, true]);
+
+        let (renamed_files, unmatched_rows, unmatched_files) = MappingRenamer::apply_replace_with(
+            &mapping(
+                &[&["", "md"], &["", ""], &["", "x"], &["", "y"]],
+                MappingKey::Order,
+            ),
+            &files(),
+            RenamerTarget::Suffix,
+        );
+        assert_eq!(
+            renamed_files.collect::<Vec<_>>(),
+            vec![
+                ("a.md".to_string(), "/tmp".to_string()),
+                ("b.txt".to_string(), "/tmp".to_string()),
+                ("c".to_string(), "/home/foo".to_string()),
+            ]
+        );
+        assert_eq!(unmatched_rows, vec![2, 4]);
+        assert_eq!(unmatched_files, vec![false, true, false]);
+    }
+
+    #[test]
+    fn test_format_status() {
+        assert_eq!(format_status(3, &[]), "3 rows, all matched");
+        assert_eq!(format_status(1, &[]), "1 row, all matched");
+        assert_eq!(format_status(3, &[1, 3]), "3 rows, 2 unmatched: row 1, 3");
+        assert_eq!(
+            format_status(20, &(1..=11).collect::<Vec<_>>()),
+            "20 rows, 11 unmatched: row 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, …"
+        );
+    }
+}
@@ -1,21 +1,37 @@
 use crate::error::Error;
 use crate::utils::Observer;
 use crate::win::file_list::RenamerTarget;
+use crate::win::provider::audio_tag_renamer::AudioTagRenamer;
 use crate::win::provider::change_case_renamer::ChangeCaseRenamer;
+use crate::win::provider::cleanup_renamer::CleanupRenamer;
 use crate::win::provider::date_time_renamer::DateTimeRenamer;
+use crate::win::provider::extension_renamer::ExtensionRenamer;
+use crate::win::provider::image_metadata_renamer::ImageMetadataRenamer;
 use crate::win::provider::insert_overwrite_renamer::InsertOverwriteRenamer;
+use crate::win::provider::mapping_renamer::MappingRenamer;
+use crate::win::provider::normalize_renamer::NormalizeRenamer;
+use crate::win::provider::reformat_date_renamer::ReformatDateRenamer;
 use crate::win::provider::remove_characters::RemoveCharactersRenamer;
 use crate::win::provider::replace_renamer::ReplaceRenamer;
+use crate::win::provider::transliterate_renamer::TransliterateRenamer;
 use gtk::Container;
 use std::rc::Rc;
 use std::vec::IntoIter;
 use strum_macros::{EnumIter, EnumString, IntoStaticStr};
 
+mod audio_tag_renamer;
 mod change_case_renamer;
+mod cleanup_renamer;
 mod date_time_renamer;
+mod extension_renamer;
+mod image_metadata_renamer;
 mod insert_overwrite_renamer;
+mod mapping_renamer;
+mod normalize_renamer;
+mod reformat_date_renamer;
 mod remove_characters;
 mod replace_renamer;
+mod transliterate_renamer;
 
 pub(crate) trait Renamer {
     /// Get panel
@@ -26,6 +42,16 @@ pub(crate) trait Renamer {
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> Result<IntoIter<(String, String)>, Error>;
+    /// Apply replacement and flag the rows to be checked in the file list,
+    /// e.g. the files of which the tags are missing. No row is flagged by default.
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        self.apply_replacement(files, target)
+            .map(|replacements| (replacements, vec![false; files.len()]))
+    }
     /// Add change listener
     fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>);
 }
@@ -40,6 +66,14 @@ pub(crate) enum RenamerType {
     DateTime,
     RemoveCharacters,
     ChangeCase,
+    Mapping,
+    Transliterate,
+    Normalize,
+    Cleanup,
+    Extension,
+    ReformatDate,
+    ImageMetadata,
+    AudioTag,
 }
 
 impl RenamerType {
@@ -50,6 +84,14 @@ impl RenamerType {
             RenamerType::DateTime => "Insert Date/Time",
             RenamerType::RemoveCharacters => "Remove Characters",
             RenamerType::ChangeCase => "Uppercase / lowercase",
+            RenamerType::Mapping => "Mapping",
+            RenamerType::Transliterate => "Transliterate",
+            RenamerType::Normalize => "Unicode Normalization",
+            RenamerType::Cleanup => "Whitespace / Separators",
+            RenamerType::Extension => "Extension",
+            RenamerType::ReformatDate => "Reformat Dates",
+            RenamerType::ImageMetadata => "Image Metadata",
+            RenamerType::AudioTag => "Audio Tags",
         }
     }
 }
@@ -60,6 +102,14 @@ pub(crate) struct Provider {
     date_time_renamer: DateTimeRenamer,
     remove_characters_renamer: RemoveCharactersRenamer,
     change_case_renamer: ChangeCaseRenamer,
+    mapping_renamer: MappingRenamer,
+    transliterate_renamer: TransliterateRenamer,
+    normalize_renamer: NormalizeRenamer,
+    cleanup_renamer: CleanupRenamer,
+    extension_renamer: ExtensionRenamer,
+    reformat_date_renamer: ReformatDateRenamer,
+    image_metadata_renamer: ImageMetadataRenamer,
+    audio_tag_renamer: AudioTagRenamer,
 }
 
 impl Provider {
@@ -70,6 +120,14 @@ impl Provider {
             date_time_renamer: DateTimeRenamer::new(),
             remove_characters_renamer: RemoveCharactersRenamer::new(),
             change_case_renamer: ChangeCaseRenamer::new(),
+            mapping_renamer: MappingRenamer::new(),
+            transliterate_renamer: TransliterateRenamer::new(),
+            normalize_renamer: NormalizeRenamer::new(),
+            cleanup_renamer: CleanupRenamer::new(),
+            extension_renamer: ExtensionRenamer::new(),
+            reformat_date_renamer: ReformatDateRenamer::new(),
+            image_metadata_renamer: ImageMetadataRenamer::new(),
+            audio_tag_renamer: AudioTagRenamer::new(),
         }
     }
 
@@ -81,6 +139,14 @@ impl Provider {
         self.remove_characters_renamer
             .attach_change(observer.clone());
         self.change_case_renamer.attach_change(observer.clone());
+        self.mapping_renamer.attach_change(observer.clone());
+        self.transliterate_renamer.attach_change(observer.clone());
+        self.normalize_renamer.attach_change(observer.clone());
+        self.cleanup_renamer.attach_change(observer.clone());
+        self.extension_renamer.attach_change(observer.clone());
+        self.reformat_date_renamer.attach_change(observer.clone());
+        self.image_metadata_renamer.attach_change(observer.clone());
+        self.audio_tag_renamer.attach_change(observer.clone());
     }
 
     pub fn renamer_of(&self, renamer_type: RenamerType) -> Box<&dyn Renamer> {
@@ -90,6 +156,14 @@ impl Provider {
             RenamerType::DateTime => &self.date_time_renamer,
             RenamerType::RemoveCharacters => &self.remove_characters_renamer,
             RenamerType::ChangeCase => &self.change_case_renamer,
+            RenamerType::Mapping => &self.mapping_renamer,
+            RenamerType::Transliterate => &self.transliterate_renamer,
+            RenamerType::Normalize => &self.normalize_renamer,
+            RenamerType::Cleanup => &self.cleanup_renamer,
+            RenamerType::Extension => &self.extension_renamer,
+            RenamerType::ReformatDate => &self.reformat_date_renamer,
+            RenamerType::ImageMetadata => &self.image_metadata_renamer,
+            RenamerType::AudioTag => &self.audio_tag_renamer,
         })
     }
 }
@@ -0,0 +1,405 @@
+use crate::error::Error;
+use crate::utils::{format_count, split_file_at_dot};
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, ComboBoxText, Container, Label};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use strum_macros::EnumString;
+use unicode_normalization::{is_nfc, is_nfd, UnicodeNormalization};
+
+const ID_NORMALIZE_RENAMER_PANEL: &'static str = "normalize-renamer-panel";
+const ID_NORMALIZATION_FORM_COMBO_BOX: &'static str = "normalization-form-combo-box";
+const ID_NORMALIZE_STATUS_LABEL: &'static str = "normalize-status-label";
+
+/// Maximum number of the names listed in the status
+const MAX_LISTED_NAMES: usize = 10;
+
+/// Unicode normalization form
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum NormalizationForm {
+    /// Canonical composition, used by most of the systems
+    Nfc,
+    /// Canonical decomposition, used by macOS
+    Nfd,
+    /// Compatibility composition
+    Nfkc,
+    /// Compatibility decomposition
+    Nfkd,
+}
+
+impl NormalizationForm {
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            NormalizationForm::Nfc => text.nfc().collect(),
+            NormalizationForm::Nfd => text.nfd().collect(),
+            NormalizationForm::Nfkc => text.nfkc().collect(),
+            NormalizationForm::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+pub struct NormalizeRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl NormalizeRenamer {
+    pub fn new() -> Self {
+        let builder = Builder::from_resource(&resource_path("provider/normalize_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::Normalize;
+        let normalization_form_combo_box =
+            self.object::<ComboBoxText>(ID_NORMALIZATION_FORM_COMBO_BOX);
+
+        let change_subject = self.change_subject.clone();
+        normalization_form_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    fn get_replacement_rule(&self) -> Option<NormalizationForm> {
+        self.object::<ComboBoxText>(ID_NORMALIZATION_FORM_COMBO_BOX)
+            .active_id()
+            .and_then(|id| NormalizationForm::from_str(id.as_str()).ok())
+    }
+
+    fn apply_replace_with(
+        form: NormalizationForm,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> IntoIter<(String, String)> {
+        files
+            .iter()
+            .map(|(file_name, dir_name)| {
+                let new_file_name = match target {
+                    RenamerTarget::Name => {
+                        let (stem, extension) = split_file_at_dot(file_name.as_str());
+                        let new_stem = form.apply(stem);
+                        if let Some(suffix) = extension {
+                            [new_stem.as_str(), suffix].join(".").to_string()
+                        } else {
+                            new_stem
+                        }
+                    }
+                    RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
+                        (stem, Some(suffix)) => {
+                            let new_suffix = form.apply(suffix);
+                            [stem, new_suffix.as_str()].join(".").to_string()
+                        }
+                        (stem, None) => stem.to_string(),
+                    },
+                    RenamerTarget::All => form.apply(file_name.as_str()),
+                };
+                (new_file_name.to_string(), dir_name.clone())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn set_status(&self, text: &str) {
+        let normalize_status_label = self.object::<Label>(ID_NORMALIZE_STATUS_LABEL);
+        normalize_status_label.set_text(text);
+        normalize_status_label.set_tooltip_text(Some(text));
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for NormalizeRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_NORMALIZE_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        self.apply_replacement_with_flags(files, target)
+            .map(|(renamed_files, _)| renamed_files)
+    }
+
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        let form = self.get_replacement_rule().unwrap();
+        let renamed_files = Self::apply_replace_with(form, files, target);
+        let changed = files
+            .iter()
+            .zip(renamed_files.as_slice())
+            .filter(|((file_name, _), (new_file_name, _))| file_name != new_file_name)
+            .count();
+        // The files colliding after the normalization are flagged in the file list.
+        let (duplicates, flags) = find_normalization_duplicates(form, files);
+        self.set_status(format_status(changed, &duplicates).as_str());
+        Ok((renamed_files, flags))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+/// Returns the names of the files which differ only in normalization from another file in the
+/// same directory, i.e. the files whose names will be the same after the normalization, and
+/// whether each of `files` is one of them.
+///
+/// The files in the directories but not in `files` are also compared, e.g. `caf\u{e9}.txt` on
+/// the disk collides with `cafe\u{301}.txt` in the list.
+fn find_normalization_duplicates(
+    form: NormalizationForm,
+    files: &[(String, String)],
+) -> (Vec<String>, Vec<bool>) {
+    let mut groups = BTreeMap::<(&str, String), Vec<String>>::new();
+    for (file_name, dir_name) in files.iter() {
+        groups
+            .entry((dir_name.as_str(), form.apply(file_name.as_str())))
+            .or_default()
+            .push(file_name.clone());
+    }
+
+    let dir_names = files
+        .iter()
+        .map(|(_, dir_name)| dir_name.as_str())
+        .collect::<BTreeSet<_>>();
+    for dir_name in dir_names.into_iter() {
+        let entries = match Path::new(dir_name).read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for file_name in entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok()) {
+            if let Some(file_names) = groups.get_mut(&(dir_name, form.apply(file_name.as_str()))) {
+                file_names.push(file_name);
+            }
+        }
+    }
+
+    for file_names in groups.values_mut() {
+        file_names.sort_unstable();
+        file_names.dedup();
+    }
+    let duplicates = groups
+        .values()
+        .filter(|file_names| file_names.len() > 1)
+        .flatten()
+        .cloned()
+        .collect();
+    let flags = files
+        .iter()
+        .map(|(file_name, dir_name)| {
+            groups[&(dir_name.as_str(), form.apply(file_name.as_str()))].len() > 1
+        })
+        .collect();
+    (duplicates, flags)
+}
+
+fn format_status(changed: usize, duplicates: &[String]) -> String {
+    let status = format!("{} to normalize", format_count(changed, "name"));
+    if duplicates.is_empty() {
+        status
+    } else {
+        let mut listed = duplicates
+            .iter()
+            .take(MAX_LISTED_NAMES)
+            .map(|name| match (is_nfc(name), is_nfd(name)) {
+                // The names look the same, so tell the form of them.
+                (true, true) => name.to_string(),
+                (true, false) => format!("{} (NFC)", name),
+                (false, true) => format!("{} (NFD)", name),
+                (false, false) => format!("{} (mixed)", name),
+            })
+            .collect::<Vec<_>>();
+        if duplicates.len() > MAX_LISTED_NAMES {
+            listed.push("…".to_string());
+        }
+        format!(
+            "{}, \u{26a0} {} differ only in normalization: {}",
+            status,
+            format_count(duplicates.len(), "name"),
+            listed.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::Window;
+
+    #[test]
+    fn test_normalize_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let normalize_renamer = NormalizeRenamer::new();
+            let normalization_form_combo_box =
+                normalize_renamer.object::<ComboBoxText>(ID_NORMALIZATION_FORM_COMBO_BOX);
+
+            normalize_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&normalize_renamer.get_panel())
+                .build()
+                .show_all();
+
+            assert_eq!(
+                normalize_renamer.get_replacement_rule(),
+                Some(NormalizationForm::Nfc)
+            );
+
+            counter_observer.reset();
+            normalization_form_combo_box.set_active_id(Some("Nfkd"));
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(
+                normalize_renamer.get_replacement_rule(),
+                Some(NormalizationForm::Nfkd)
+            );
+        });
+    }
+
+    #[test]
+    fn test_normalize_renamer_apply_replacement_with() {
+        let files = [
+            ("cafe\u{301}.txt".to_string(), "/tmp".to_string()),
+            ("caf\u{e9}.txt".to_string(), "/tmp".to_string()),
+            ("\u{fb01}le.e\u{301}".to_string(), "/tmp".to_string()),
+        ];
+
+        assert_eq!(
+            NormalizeRenamer::apply_replace_with(
+                NormalizationForm::Nfc,
+                &files,
+                RenamerTarget::All
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("caf\u{e9}.txt".to_string(), "/tmp".to_string()),
+                ("caf\u{e9}.txt".to_string(), "/tmp".to_string()),
+                ("\u{fb01}le.\u{e9}".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            NormalizeRenamer::apply_replace_with(
+                NormalizationForm::Nfd,
+                &files,
+                RenamerTarget::Name
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("cafe\u{301}.txt".to_string(), "/tmp".to_string()),
+                ("cafe\u{301}.txt".to_string(), "/tmp".to_string()),
+                ("\u{fb01}le.e\u{301}".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            NormalizeRenamer::apply_replace_with(
+                NormalizationForm::Nfkc,
+                &files,
+                RenamerTarget::Suffix
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("cafe\u{301}.txt".to_string(), "/tmp".to_string()),
+                ("caf\u{e9}.txt".to_string(), "/tmp".to_string()),
+                ("\u{fb01}le.\u{e9}".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            NormalizeRenamer::apply_replace_with(
+                NormalizationForm::Nfkd,
+                &files[2..],
+                RenamerTarget::All
+            )
+            .collect::<Vec<_>>(),
+            vec![("file.e\u{301}".to_string(), "/tmp".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_find_normalization_duplicates() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let other_temp_dir = tempfile::tempdir().unwrap();
+        let dir_name = temp_dir.path().to_str().unwrap().to_string();
+        let other_dir_name = other_temp_dir.path().to_str().unwrap().to_string();
+        let files = [
+            ("cafe\u{301}.txt".to_string(), dir_name.clone()),
+            ("caf\u{e9}.txt".to_string(), dir_name.clone()),
+            ("caf\u{e9}.txt".to_string(), other_dir_name.clone()),
+            ("\u{fb01}le".to_string(), dir_name.clone()),
+            ("file".to_string(), dir_name.clone()),
+        ];
+
+        assert_eq!(
+            find_normalization_duplicates(NormalizationForm::Nfc, &files),
+            (
+                vec!["cafe\u{301}.txt".to_string(), "caf\u{e9}.txt".to_string()],
+                vec![true, true, false, false, false]
+            )
+        );
+        assert_eq!(
+            find_normalization_duplicates(NormalizationForm::Nfkc, &files),
+            (
+                vec![
+                    "cafe\u{301}.txt".to_string(),
+                    "caf\u{e9}.txt".to_string(),
+                    "file".to_string(),
+                    "\u{fb01}le".to_string(),
+                ],
+                vec![true, true, false, true, true]
+            )
+        );
+
+        // The NFC twin on the disk is not in the list.
+        std::fs::write(other_temp_dir.path().join("cafe\u{301}.txt"), "").unwrap();
+        std::fs::write(other_temp_dir.path().join("notes.txt"), "").unwrap();
+        assert_eq!(
+            find_normalization_duplicates(NormalizationForm::Nfc, &files[2..3]),
+            (
+                vec!["cafe\u{301}.txt".to_string(), "caf\u{e9}.txt".to_string()],
+                vec![true]
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_status() {
+        assert_eq!(format_status(2, &[]), "2 names to normalize");
+        assert_eq!(
+            format_status(
+                1,
+                &[
+                    "e\u{301}".to_string(),
+                    "\u{e9}".to_string(),
+                    "e".to_string(),
+                    "\u{e9}e\u{301}".to_string()
+                ]
+            ),
+            "1 name to normalize, \u{26a0} 4 names differ only in normalization: \
+             e\u{301} (NFD), \u{e9} (NFC), e, \u{e9}e\u{301} (mixed)"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use crate::error::Error;
+use crate::utils::{format_count, split_file_at_dot, DatePattern};
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use glib::TimeZone;
+use gtk::prelude::*;
+use gtk::{Builder, Container, Entry, EntryIconPosition, Label};
+use std::rc::Rc;
+use std::vec::IntoIter;
+
+const ID_REFORMAT_DATE_RENAMER_PANEL: &'static str = "reformat-date-renamer-panel";
+const ID_DATE_PATTERN_ENTRY: &'static str = "date-pattern-entry";
+const ID_DATE_FORMAT_ENTRY: &'static str = "date-format-entry";
+const ID_REFORMAT_DATE_STATUS_LABEL: &'static str = "reformat-date-status-label";
+
+/// Maximum number of the names listed in the status
+const MAX_LISTED_NAMES: usize = 10;
+
+struct ReformatDateRule {
+    pattern: DatePattern,
+    /// Format of `DateTime::format` to rewrite the dates with
+    format: String,
+}
+
+impl ReformatDateRule {
+    fn new(pattern: &str, format: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: DatePattern::new(pattern)?,
+            format: format.to_string(),
+        })
+    }
+
+    /// Rewrite the dates found in the text. The invalid dates such as "31.02.21" are kept.
+    fn apply(&self, text: &str) -> String {
+        // the dates are read and written in UTC so that no date falls in a gap of the daylight
+        // saving time
+        let time_zone = TimeZone::utc();
+        let mut new_text = String::with_capacity(text.len());
+        let mut last = 0;
+        for date in self.pattern.find_iter(text) {
+            let formatted = date
+                .to_unix_time(&time_zone)
+                .and_then(|time| time.format_in(self.format.as_str(), &time_zone));
+            if let Some(formatted) = formatted {
+                new_text.push_str(&text[last..date.range.start]);
+                new_text.push_str(formatted.as_str());
+                last = date.range.end;
+            }
+        }
+        new_text.push_str(&text[last..]);
+        new_text
+    }
+
+    /// Returns the text of the invalid dates found in the text.
+    fn invalid_dates<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let time_zone = TimeZone::utc();
+        self.pattern
+            .find_iter(text)
+            .filter(|date| date.to_unix_time(&time_zone).is_none())
+            .map(|date| &text[date.range])
+            .collect()
+    }
+}
+
+pub struct ReformatDateRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl ReformatDateRenamer {
+    pub fn new() -> Self {
+        let builder =
+            Builder::from_resource(&resource_path("provider/reformat_date_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::ReformatDate;
+        let date_pattern_entry = self.object::<Entry>(ID_DATE_PATTERN_ENTRY);
+        let date_format_entry = self.object::<Entry>(ID_DATE_FORMAT_ENTRY);
+
+        let change_subject = self.change_subject.clone();
+        date_pattern_entry.connect_changed(move |date_pattern_entry| {
+            if let Err(e) = DatePattern::new(date_pattern_entry.text().as_str()) {
+                date_pattern_entry
+                    .set_icon_from_icon_name(EntryIconPosition::Secondary, Some("error"));
+                date_pattern_entry.set_icon_tooltip_text(
+                    EntryIconPosition::Secondary,
+                    Some(e.to_string().as_str()),
+                );
+            } else {
+                date_pattern_entry.set_icon_from_icon_name(EntryIconPosition::Secondary, None);
+                date_pattern_entry.set_icon_tooltip_text(EntryIconPosition::Secondary, None);
+            }
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        date_format_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    fn get_replacement_rule(&self) -> Result<ReformatDateRule, Error> {
+        let date_pattern_entry = self.object::<Entry>(ID_DATE_PATTERN_ENTRY);
+        let date_format_entry = self.object::<Entry>(ID_DATE_FORMAT_ENTRY);
+
+        Ok(ReformatDateRule::new(
+            date_pattern_entry.text().as_str(),
+            date_format_entry.text().as_str(),
+        )?)
+    }
+
+    fn apply_replace_with(
+        rule: &ReformatDateRule,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> IntoIter<(String, String)> {
+        files
+            .iter()
+            .map(|(file_name, dir_name)| {
+                let new_file_name = match target {
+                    RenamerTarget::Name => {
+                        let (stem, extension) = split_file_at_dot(file_name.as_str());
+                        let new_stem = rule.apply(stem);
+                        if let Some(suffix) = extension {
+                            [new_stem.as_str(), suffix].join(".").to_string()
+                        } else {
+                            new_stem
+                        }
+                    }
+                    RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
+                        (stem, Some(suffix)) => {
+                            let new_suffix = rule.apply(suffix);
+                            [stem, new_suffix.as_str()].join(".").to_string()
+                        }
+                        (stem, None) => stem.to_string(),
+                    },
+                    RenamerTarget::All => rule.apply(file_name.as_str()),
+                };
+                (new_file_name, dir_name.clone())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn set_status(&self, text: &str) {
+        let reformat_date_status_label = self.object::<Label>(ID_REFORMAT_DATE_STATUS_LABEL);
+        reformat_date_status_label.set_text(text);
+        reformat_date_status_label.set_tooltip_text(Some(text));
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for ReformatDateRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_REFORMAT_DATE_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        self.apply_replacement_with_flags(files, target)
+            .map(|(renamed_files, _)| renamed_files)
+    }
+
+    fn apply_replacement_with_flags(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<(IntoIter<(String, String)>, Vec<bool>), Error> {
+        let rule = match self.get_replacement_rule() {
+            Ok(rule) => rule,
+            Err(e) => {
+                self.set_status("");
+                return Err(e);
+            }
+        };
+        let renamed_files = Self::apply_replace_with(&rule, files, target);
+        let changed = files
+            .iter()
+            .zip(renamed_files.as_slice())
+            .filter(|((file_name, _), (new_file_name, _))| file_name != new_file_name)
+            .count();
+        let invalid_dates = find_invalid_dates(&rule, files);
+        self.set_status(format_status(changed, &invalid_dates).as_str());
+        // The files with the invalid dates are flagged in the file list.
+        let flags = files
+            .iter()
+            .map(|(file_name, _)| !rule.invalid_dates(file_name.as_str()).is_empty())
+            .collect();
+        Ok((renamed_files, flags))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+/// Returns the names of the files with the invalid dates, with the first invalid date.
+fn find_invalid_dates<'a>(
+    rule: &ReformatDateRule,
+    files: &'a [(String, String)],
+) -> Vec<(&'a str, &'a str)> {
+    files
+        .iter()
+        .filter_map(|(file_name, _)| {
+            rule.invalid_dates(file_name.as_str())
+                .first()
+                .map(|date| (file_name.as_str(), *date))
+        })
+        .collect()
+}
+
+fn format_status(changed: usize, invalid_dates: &[(&str, &str)]) -> String {
+    let status = format!("{} to change", format_count(changed, "name"));
+    if invalid_dates.is_empty() {
+        status
+    } else {
+        let mut listed = invalid_dates
+            .iter()
+            .take(MAX_LISTED_NAMES)
+            .map(|(name, date)| format!("{} ({})", name, date))
+            .collect::<Vec<_>>();
+        if invalid_dates.len() > MAX_LISTED_NAMES {
+            listed.push("…".to_string());
+        }
+        format!(
+            "{}, \u{26a0} invalid dates in {}: {}",
+            status,
+            format_count(invalid_dates.len(), "name"),
+            listed.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::Window;
+
+    #[test]
+    fn test_reformat_date_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let reformat_date_renamer = ReformatDateRenamer::new();
+            let date_pattern_entry = reformat_date_renamer.object::<Entry>(ID_DATE_PATTERN_ENTRY);
+            let date_format_entry = reformat_date_renamer.object::<Entry>(ID_DATE_FORMAT_ENTRY);
+
+            reformat_date_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&reformat_date_renamer.get_panel())
+                .build()
+                .show_all();
+
+            counter_observer.reset();
+            date_pattern_entry.set_text("%Y-%Y");
+            assert_eq!(counter_observer.count(), 1);
+            assert!(date_pattern_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_some());
+            assert!(reformat_date_renamer.get_replacement_rule().is_err());
+
+            counter_observer.reset();
+            date_pattern_entry.set_text("%d.%m.%y");
+            date_format_entry.set_text("%Y-%m-%d");
+            assert_eq!(counter_observer.count(), 2);
+            assert!(date_pattern_entry
+                .icon_name(EntryIconPosition::Secondary)
+                .is_none());
+
+            let files = [
+                ("Scan 03.04.21.pdf".to_string(), "/tmp".to_string()),
+                ("Scan 31.02.21.pdf".to_string(), "/tmp".to_string()),
+            ];
+            let renamed_files = reformat_date_renamer
+                .apply_replacement(&files, RenamerTarget::Name)
+                .unwrap()
+                .collect::<Vec<_>>();
+            assert_eq!(
+                renamed_files,
+                vec![
+                    ("Scan 2021-04-03.pdf".to_string(), "/tmp".to_string()),
+                    ("Scan 31.02.21.pdf".to_string(), "/tmp".to_string()),
+                ]
+            );
+            assert_eq!(
+                reformat_date_renamer
+                    .object::<Label>(ID_REFORMAT_DATE_STATUS_LABEL)
+                    .text(),
+                "1 name to change, \u{26a0} invalid dates in 1 name: Scan 31.02.21.pdf (31.02.21)"
+            );
+            let (_, flags) = reformat_date_renamer
+                .apply_replacement_with_flags(&files, RenamerTarget::Name)
+                .unwrap();
+            assert_eq!(flags, vec![false, true]);
+        });
+    }
+
+    #[test]
+    fn test_reformat_date_rule() {
+        let rule = ReformatDateRule::new("%d.%m.%y", "%Y-%m-%d").unwrap();
+        assert_eq!(rule.apply("Scan 03.04.21"), "Scan 2021-04-03");
+        assert_eq!(
+            rule.apply("from 01.12.20 to 31.01.21"),
+            "from 2020-12-01 to 2021-01-31"
+        );
+        assert_eq!(rule.apply("Scan 31.02.21"), "Scan 31.02.21");
+        assert_eq!(rule.invalid_dates("Scan 31.02.21"), vec!["31.02.21"]);
+        assert_eq!(rule.apply("Scan"), "Scan");
+        assert!(rule.invalid_dates("Scan 03.04.21").is_empty());
+
+        let rule = ReformatDateRule::new("IMG-%Y%m%d-WA", "%Y-%m-%d ").unwrap();
+        assert_eq!(rule.apply("IMG-20210304-WA0001"), "2021-03-04 0001");
+
+        let rule = ReformatDateRule::new("%Y%m%d_%H%M%S", "%Y-%m-%d %H.%M.%S").unwrap();
+        assert_eq!(rule.apply("VID_20210328_023000"), "VID_2021-03-28 02.30.00");
+    }
+
+    #[test]
+    fn test_reformat_date_renamer_apply_replace_with() {
+        let rule = ReformatDateRule::new("%Y%m%d", "%Y-%m-%d").unwrap();
+        let files = [
+            ("20210304.20210305".to_string(), "/tmp".to_string()),
+            ("notes".to_string(), "/tmp".to_string()),
+        ];
+
+        assert_eq!(
+            ReformatDateRenamer::apply_replace_with(&rule, &files, RenamerTarget::Name)
+                .collect::<Vec<_>>(),
+            vec![
+                ("2021-03-04.20210305".to_string(), "/tmp".to_string()),
+                ("notes".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            ReformatDateRenamer::apply_replace_with(&rule, &files, RenamerTarget::Suffix)
+                .collect::<Vec<_>>(),
+            vec![
+                ("20210304.2021-03-05".to_string(), "/tmp".to_string()),
+                ("notes".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            ReformatDateRenamer::apply_replace_with(&rule, &files, RenamerTarget::All)
+                .collect::<Vec<_>>(),
+            vec![
+                ("2021-03-04.2021-03-05".to_string(), "/tmp".to_string()),
+                ("notes".to_string(), "/tmp".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_invalid_dates() {
+        let rule = ReformatDateRule::new("%d.%m.%Y", "%Y-%m-%d").unwrap();
+        let files = [
+            ("Scan 03.04.2021.pdf".to_string(), "/tmp".to_string()),
+            ("Scan 31.02.2021.pdf".to_string(), "/tmp".to_string()),
+            (
+                "Scan 00.13.2021 31.04.2021.pdf".to_string(),
+                "/tmp".to_string(),
+            ),
+        ];
+
+        let invalid_dates = find_invalid_dates(&rule, &files);
+        assert_eq!(
+            invalid_dates,
+            vec![
+                ("Scan 31.02.2021.pdf", "31.02.2021"),
+                ("Scan 00.13.2021 31.04.2021.pdf", "00.13.2021"),
+            ]
+        );
+        assert_eq!(
+            format_status(1, &invalid_dates),
+            "1 name to change, \u{26a0} invalid dates in 2 names: Scan 31.02.2021.pdf (31.02.2021), Scan 00.13.2021 31.04.2021.pdf (00.13.2021)"
+        );
+        assert_eq!(format_status(0, &[]), "0 names to change");
+    }
+}
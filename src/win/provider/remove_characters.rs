@@ -4,16 +4,141 @@ use crate::utils::{Observer, SubjectImpl};
 use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
 use crate::win::resource::resource_path;
 use gtk::prelude::*;
-use gtk::{Builder, ComboBoxText, Container, SpinButton};
+use gtk::{Builder, ComboBoxText, Container, Entry, SpinButton};
+use regex::{Error as RegexError, Regex};
 use std::convert::TryFrom;
 use std::rc::Rc;
+use std::str::FromStr;
 use std::vec::IntoIter;
+use strum_macros::EnumString;
 
 const ID_REMOVE_CHARACTERS_RENAMER_PANEL: &'static str = "remove-characters-panel";
+const ID_REMOVE_MODE_COMBO_BOX: &'static str = "remove-mode-combo-box";
 const ID_REMOVE_FROM_SPINNER_BUTTON: &'static str = "remove-from-spin-button";
 const ID_REMOVE_FROM_COMBO_BOX: &'static str = "remove-from-combo-box";
 const ID_REMOVE_TO_SPINNER_BUTTON: &'static str = "remove-to-spin-button";
 const ID_REMOVE_TO_COMBO_BOX: &'static str = "remove-to-combo-box";
+const ID_REMOVE_CUSTOM_ENTRY: &'static str = "remove-custom-entry";
+const ID_REMOVE_DELIMITERS_COMBO_BOX: &'static str = "remove-delimiters-combo-box";
+
+/// What to remove from the names
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum RemoveMode {
+    /// Characters between the positions
+    Range,
+    /// Decimal digits
+    Digits,
+    /// Punctuation such as `!` or `、`
+    Punctuation,
+    /// Symbols such as `+`, `$` or `©`
+    Symbols,
+    /// Emoji including the modifiers and the joiners
+    Emoji,
+    /// Non-ASCII characters
+    NonAscii,
+    /// Characters in the set entered by the user
+    Custom,
+    /// Text between the delimiters
+    Delimited,
+}
+
+impl RemoveMode {
+    /// Returns the pattern matching a character of the class.
+    fn character_pattern(self, custom: &str) -> Option<String> {
+        match self {
+            RemoveMode::Range | RemoveMode::Delimited => None,
+            RemoveMode::Digits => Some(r"\p{Nd}".to_string()),
+            RemoveMode::Punctuation => Some(r"\p{P}".to_string()),
+            RemoveMode::Symbols => Some(r"\p{S}".to_string()),
+            RemoveMode::Emoji => Some(
+                r"[\p{Extended_Pictographic}\p{Emoji_Modifier}\p{Regional_Indicator}\u{200D}\u{20E3}\u{FE0F}]"
+                    .to_string(),
+            ),
+            RemoveMode::NonAscii => Some(r"[^\x00-\x7F]".to_string()),
+            RemoveMode::Custom => Some(
+                custom
+                    .chars()
+                    .map(|c| regex::escape(c.encode_utf8(&mut [0; 4])))
+                    .collect::<Vec<_>>()
+                    .join("|"),
+            ),
+        }
+    }
+}
+
+/// Pair of the delimiters enclosing the text to remove
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum Delimiters {
+    /// `(…)`
+    Parentheses,
+    /// `[…]`
+    SquareBrackets,
+    /// `{…}`
+    CurlyBraces,
+    /// Any of `(…)`, `[…]` and `{…}`
+    AnyBrackets,
+}
+
+impl Delimiters {
+    fn pattern(self) -> &'static str {
+        match self {
+            Delimiters::Parentheses => r"\([^()]*\)",
+            Delimiters::SquareBrackets => r"\[[^\[\]]*\]",
+            Delimiters::CurlyBraces => r"\{[^{}]*\}",
+            Delimiters::AnyBrackets => r"\([^()]*\)|\[[^\[\]]*\]|\{[^{}]*\}",
+        }
+    }
+}
+
+enum RemoveRule {
+    /// Remove the characters between the positions.
+    Range(RemoveRangePosition),
+    /// Remove every character matching the pattern.
+    Characters(Regex),
+    /// Remove the text matching the pattern and the whitespace left around it.
+    Delimited(Regex),
+}
+
+impl RemoveRule {
+    fn new(
+        mode: RemoveMode,
+        position: RemoveRangePosition,
+        custom: &str,
+        delimiters: Delimiters,
+    ) -> Result<Self, RegexError> {
+        Ok(match mode {
+            RemoveMode::Range => RemoveRule::Range(position),
+            RemoveMode::Delimited => RemoveRule::Delimited(Regex::new(delimiters.pattern())?),
+            _ => RemoveRule::Characters(Regex::new(
+                mode.character_pattern(custom).unwrap_or_default().as_str(),
+            )?),
+        })
+    }
+
+    fn apply(&self, text: &str) -> String {
+        match self {
+            RemoveRule::Range(position) => position.apply_to(text, ""),
+            RemoveRule::Characters(regex) => regex.replace_all(text, "").to_string(),
+            RemoveRule::Delimited(regex) => {
+                let mut new_text = String::new();
+                let mut last = 0;
+                for m in regex.find_iter(text) {
+                    new_text.push_str(&text[last..m.start()]);
+                    last = m.end();
+                    // Do not leave the whitespace on both sides of the removed text.
+                    if new_text.is_empty() || new_text.ends_with(char::is_whitespace) {
+                        last = text.len() - text[last..].trim_start().len();
+                    }
+                    if last == text.len() {
+                        new_text.truncate(new_text.trim_end().len());
+                    }
+                }
+                new_text.push_str(&text[last..]);
+                new_text
+            }
+        }
+    }
+}
 
 pub struct RemoveCharactersRenamer {
     builder: Builder,
@@ -30,16 +155,31 @@ impl RemoveCharactersRenamer {
         };
 
         renamer.init_callback();
+        Self::update_sensitivity(&renamer.builder);
 
         renamer
     }
 
     fn init_callback(&self) {
         let renamer_type = RenamerType::RemoveCharacters;
+        let remove_mode_combo_box = self.object::<ComboBoxText>(ID_REMOVE_MODE_COMBO_BOX);
         let remove_from_spin_button = self.object::<SpinButton>(ID_REMOVE_FROM_SPINNER_BUTTON);
         let remove_from_combo_box = self.object::<ComboBoxText>(ID_REMOVE_FROM_COMBO_BOX);
         let remove_to_spin_button = self.object::<SpinButton>(ID_REMOVE_TO_SPINNER_BUTTON);
         let remove_to_combo_box = self.object::<ComboBoxText>(ID_REMOVE_TO_COMBO_BOX);
+        let remove_custom_entry = self.object::<Entry>(ID_REMOVE_CUSTOM_ENTRY);
+        let remove_delimiters_combo_box =
+            self.object::<ComboBoxText>(ID_REMOVE_DELIMITERS_COMBO_BOX);
+
+        let builder = self.builder.clone();
+        let change_subject = self.change_subject.clone();
+        remove_mode_combo_box.connect_changed(move |_| {
+            Self::update_sensitivity(&builder);
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
 
         let change_subject = self.change_subject.clone();
         remove_from_spin_button.connect_value_changed(move |_| {
@@ -68,9 +208,44 @@ impl RemoveCharactersRenamer {
                 .notify((renamer_type, ()))
                 .unwrap_or_default();
         });
+
+        let change_subject = self.change_subject.clone();
+        remove_custom_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        remove_delimiters_combo_box.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    /// Enable only the options of the selected mode.
+    fn update_sensitivity(builder: &Builder) {
+        let object = |name: &str| builder.object::<gtk::Widget>(name).unwrap();
+        let mode = builder
+            .object::<ComboBoxText>(ID_REMOVE_MODE_COMBO_BOX)
+            .unwrap()
+            .active_id()
+            .and_then(|id| RemoveMode::from_str(id.as_str()).ok());
+
+        for &id in &[
+            ID_REMOVE_FROM_SPINNER_BUTTON,
+            ID_REMOVE_FROM_COMBO_BOX,
+            ID_REMOVE_TO_SPINNER_BUTTON,
+            ID_REMOVE_TO_COMBO_BOX,
+        ] {
+            object(id).set_sensitive(mode == Some(RemoveMode::Range));
+        }
+        object(ID_REMOVE_CUSTOM_ENTRY).set_sensitive(mode == Some(RemoveMode::Custom));
+        object(ID_REMOVE_DELIMITERS_COMBO_BOX).set_sensitive(mode == Some(RemoveMode::Delimited));
     }
 
-    fn get_replacement_rule(&self) -> Option<RemoveRangePosition> {
+    fn get_remove_range(&self) -> Option<RemoveRangePosition> {
         let remove_from_spin_button = self.object::<SpinButton>(ID_REMOVE_FROM_SPINNER_BUTTON);
         let remove_from_combo_box = self.object::<ComboBoxText>(ID_REMOVE_FROM_COMBO_BOX);
         let remove_to_spin_button = self.object::<SpinButton>(ID_REMOVE_TO_SPINNER_BUTTON);
@@ -92,19 +267,39 @@ impl RemoveCharactersRenamer {
         ))
     }
 
+    fn get_replacement_rule(&self) -> Result<RemoveRule, Error> {
+        let mode = self
+            .object::<ComboBoxText>(ID_REMOVE_MODE_COMBO_BOX)
+            .active_id()
+            .and_then(|id| RemoveMode::from_str(id.as_str()).ok())
+            .unwrap_or(RemoveMode::Range);
+        let custom = self.object::<Entry>(ID_REMOVE_CUSTOM_ENTRY).text();
+        let delimiters = self
+            .object::<ComboBoxText>(ID_REMOVE_DELIMITERS_COMBO_BOX)
+            .active_id()
+            .and_then(|id| Delimiters::from_str(id.as_str()).ok())
+            .unwrap_or(Delimiters::AnyBrackets);
+
+        Ok(RemoveRule::new(
+            mode,
+            self.get_remove_range().unwrap(),
+            custom.as_str(),
+            delimiters,
+        )?)
+    }
+
     fn apply_replace_with(
-        position: RemoveRangePosition,
+        rule: &RemoveRule,
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> IntoIter<(String, String)> {
         files
             .iter()
             .map(|(file_name, dir_name)| {
-                let text = "";
                 let new_file_name = match target {
                     RenamerTarget::Name => {
                         let (stem, extension) = split_file_at_dot(file_name.as_str());
-                        let new_stem = position.apply_to(stem, text);
+                        let new_stem = rule.apply(stem);
                         if let Some(suffix) = extension {
                             [new_stem.as_str(), suffix].join(".").to_string()
                         } else {
@@ -113,12 +308,12 @@ impl RemoveCharactersRenamer {
                     }
                     RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
                         (stem, Some(suffix)) => {
-                            let new_suffix = position.apply_to(suffix, text);
+                            let new_suffix = rule.apply(suffix);
                             [stem, new_suffix.as_str()].join(".").to_string()
                         }
                         (stem, None) => stem.to_string(),
                     },
-                    RenamerTarget::All => position.apply_to(file_name.as_str(), text),
+                    RenamerTarget::All => rule.apply(file_name.as_str()),
                 };
                 (new_file_name.to_string(), dir_name.clone())
             })
@@ -141,8 +336,8 @@ impl Renamer for RemoveCharactersRenamer {
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> Result<IntoIter<(String, String)>, Error> {
-        let position = self.get_replacement_rule().unwrap();
-        Ok(Self::apply_replace_with(position, files, target))
+        let rule = self.get_replacement_rule()?;
+        Ok(Self::apply_replace_with(&rule, files, target))
     }
 
     fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
@@ -170,6 +365,12 @@ mod test {
                 remove_characters_renamer.object::<SpinButton>(ID_REMOVE_TO_SPINNER_BUTTON);
             let remove_to_combo_button =
                 remove_characters_renamer.object::<ComboBoxText>(ID_REMOVE_TO_COMBO_BOX);
+            let remove_mode_combo_box =
+                remove_characters_renamer.object::<ComboBoxText>(ID_REMOVE_MODE_COMBO_BOX);
+            let remove_custom_entry =
+                remove_characters_renamer.object::<Entry>(ID_REMOVE_CUSTOM_ENTRY);
+            let remove_delimiters_combo_box =
+                remove_characters_renamer.object::<ComboBoxText>(ID_REMOVE_DELIMITERS_COMBO_BOX);
 
             remove_characters_renamer.attach_change(counter_observer.clone());
 
@@ -199,6 +400,34 @@ mod test {
             remove_to_combo_button.clone().set_active(Some(1));
             gtk_test::wait(1);
             assert_eq!(counter_observer.count(), 1);
+
+            counter_observer.reset();
+            remove_mode_combo_box.set_active_id(Some("Custom"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert!(!remove_from_spinner_button.is_sensitive());
+            assert!(remove_custom_entry.is_sensitive());
+            assert!(!remove_delimiters_combo_box.is_sensitive());
+
+            counter_observer.reset();
+            remove_custom_entry.set_text("_-");
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(
+                remove_characters_renamer
+                    .get_replacement_rule()
+                    .unwrap()
+                    .apply("a_b-c"),
+                "abc"
+            );
+
+            counter_observer.reset();
+            remove_mode_combo_box.set_active_id(Some("Delimited"));
+            remove_delimiters_combo_box.set_active_id(Some("Parentheses"));
+            gtk_test::wait(1);
+            assert_eq!(counter_observer.count(), 2);
+            assert!(!remove_custom_entry.is_sensitive());
+            assert!(remove_delimiters_combo_box.is_sensitive());
         });
     }
 
@@ -206,7 +435,10 @@ mod test {
     fn test_insert_overwrite_renamer_apply_replacement_with() {
         assert_eq!(
             RemoveCharactersRenamer::apply_replace_with(
-                RemoveRangePosition(TextCharPosition::Front(0), TextCharPosition::Front(0)),
+                &RemoveRule::Range(RemoveRangePosition(
+                    TextCharPosition::Front(0),
+                    TextCharPosition::Front(0)
+                )),
                 &[("orig.txt".to_string(), "/tmp".to_string())],
                 RenamerTarget::All
             )
@@ -216,7 +448,10 @@ mod test {
 
         assert_eq!(
             RemoveCharactersRenamer::apply_replace_with(
-                RemoveRangePosition(TextCharPosition::Front(1), TextCharPosition::Back(1)),
+                &RemoveRule::Range(RemoveRangePosition(
+                    TextCharPosition::Front(1),
+                    TextCharPosition::Back(1)
+                )),
                 &[("orig.txt".to_string(), "/tmp".to_string())],
                 RenamerTarget::All
             )
@@ -225,7 +460,10 @@ mod test {
         );
         assert_eq!(
             RemoveCharactersRenamer::apply_replace_with(
-                RemoveRangePosition(TextCharPosition::Back(3), TextCharPosition::Front(3)),
+                &RemoveRule::Range(RemoveRangePosition(
+                    TextCharPosition::Back(3),
+                    TextCharPosition::Front(3)
+                )),
                 &[("orig.txt".to_string(), "/tmp".to_string())],
                 RenamerTarget::Name
             )
@@ -234,7 +472,10 @@ mod test {
         );
         assert_eq!(
             RemoveCharactersRenamer::apply_replace_with(
-                RemoveRangePosition(TextCharPosition::Front(1), TextCharPosition::Front(2)),
+                &RemoveRule::Range(RemoveRangePosition(
+                    TextCharPosition::Front(1),
+                    TextCharPosition::Front(2)
+                )),
                 &[("orig.txt".to_string(), "/tmp".to_string())],
                 RenamerTarget::Suffix
             )
@@ -242,4 +483,121 @@ mod test {
             vec![("orig.tt".to_string(), "/tmp".to_string()),]
         );
     }
+
+    #[test]
+    fn test_remove_rule() {
+        let range = RemoveRangePosition(TextCharPosition::Front(0), TextCharPosition::Front(0));
+        let apply = |mode, custom, delimiters, text| {
+            RemoveRule::new(mode, range, custom, delimiters)
+                .unwrap()
+                .apply(text)
+        };
+
+        assert_eq!(
+            apply(
+                RemoveMode::Digits,
+                "",
+                Delimiters::AnyBrackets,
+                "IMG_0012 ２"
+            ),
+            "IMG_ "
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Punctuation,
+                "",
+                Delimiters::AnyBrackets,
+                "Hello, world! (1)"
+            ),
+            "Hello world 1"
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Symbols,
+                "",
+                Delimiters::AnyBrackets,
+                "a+b=c © $1"
+            ),
+            "abc  1"
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Emoji,
+                "",
+                Delimiters::AnyBrackets,
+                "party🎉 👍🏽 👨\u{200d}👩 🇯🇵 ❤\u{fe0f} #1"
+            ),
+            "party     #1"
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::NonAscii,
+                "",
+                Delimiters::AnyBrackets,
+                "Café 日本"
+            ),
+            "Caf "
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Custom,
+                "[]-^\\",
+                Delimiters::AnyBrackets,
+                "a[b]-c^d\\e"
+            ),
+            "abcde"
+        );
+        assert_eq!(
+            apply(RemoveMode::Custom, "", Delimiters::AnyBrackets, "abc"),
+            "abc"
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Delimited,
+                "",
+                Delimiters::AnyBrackets,
+                "Movie Name (2019) [1080p]"
+            ),
+            "Movie Name"
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Delimited,
+                "",
+                Delimiters::SquareBrackets,
+                "[Group] Title (Live) [HD] - Part{1}"
+            ),
+            "Title (Live) - Part{1}"
+        );
+        assert_eq!(
+            apply(
+                RemoveMode::Delimited,
+                "",
+                Delimiters::Parentheses,
+                "a(b)c (d (e) f)"
+            ),
+            "ac (d f)"
+        );
+    }
+
+    #[test]
+    fn test_remove_characters_renamer_apply_replacement_with_delimited() {
+        let rule = RemoveRule::new(
+            RemoveMode::Delimited,
+            RemoveRangePosition(TextCharPosition::Front(0), TextCharPosition::Front(0)),
+            "",
+            Delimiters::AnyBrackets,
+        )
+        .unwrap();
+
+        assert_eq!(
+            RemoveCharactersRenamer::apply_replace_with(
+                &rule,
+                &[("Movie (2019) [1080p].mkv".to_string(), "/tmp".to_string())],
+                RenamerTarget::Name
+            )
+            .collect::<Vec<_>>(),
+            vec![("Movie.mkv".to_string(), "/tmp".to_string())]
+        );
+    }
 }
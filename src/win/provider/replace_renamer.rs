@@ -1,11 +1,16 @@
 use crate::error::Error;
-use crate::utils::split_file_at_dot;
+use crate::utils::{list_store_data_iter, split_file_at_dot, value2string, ReplaceTemplate};
 use crate::utils::{Observer, SubjectImpl};
 use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
 use crate::win::resource::resource_path;
 use gtk::prelude::*;
-use gtk::{Builder, CheckButton, Container, Entry, EntryIconPosition};
-use regex::{Regex, RegexBuilder};
+use gtk::{
+    Builder, Button, ButtonsType, CellRendererText, CellRendererToggle, CheckButton, ComboBoxText,
+    Container, Entry, EntryIconPosition, FileChooserAction, FileChooserDialog, ListStore,
+    MessageDialog, MessageType, ResponseType, SpinButton, TreeView, Window,
+};
+use regex::{Captures, Regex, RegexBuilder};
+use std::fs;
 use std::rc::Rc;
 use std::vec::IntoIter;
 
@@ -14,6 +19,45 @@ const ID_PATTERN_ENTRY: &'static str = "pattern-entry";
 const ID_REGEXP_SUPPORTED: &'static str = "regexp-supported";
 const ID_REPLACEMENT_ENTRY: &'static str = "replacement-entry";
 const ID_CASE_SENSITIVE: &'static str = "case-sensitive";
+const ID_OCCURRENCE_COMBO_BOX: &'static str = "occurrence-combo-box";
+const ID_OCCURRENCE_SPIN_BUTTON: &'static str = "occurrence-spin-button";
+const ID_REPLACEMENT_RULES_STORE: &'static str = "replacement-rules-store";
+const ID_REPLACEMENT_RULES_VIEW: &'static str = "replacement-rules-view";
+const ID_RULE_PATTERN_RENDERER: &'static str = "rule-pattern-renderer";
+const ID_RULE_REPLACEMENT_RENDERER: &'static str = "rule-replacement-renderer";
+const ID_RULE_REGEXP_RENDERER: &'static str = "rule-regexp-renderer";
+const ID_RULE_CASE_SENSITIVE_RENDERER: &'static str = "rule-case-sensitive-renderer";
+const ID_ADD_RULE_BUTTON: &'static str = "add-rule-button";
+const ID_REMOVE_RULE_BUTTON: &'static str = "remove-rule-button";
+const ID_LOAD_RULES_BUTTON: &'static str = "load-rules-button";
+
+/// Pattern, replacement, whether the pattern is a regular expression and whether the search is
+/// case sensitive
+type ReplacementRow = (String, String, bool, bool);
+
+/// Occurrence of the matches to replace
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Occurrence {
+    /// All the matches
+    All,
+    /// The n-th (1-based) match from the front
+    Front(usize),
+    /// The n-th (1-based) match from the back
+    Back(usize),
+}
+
+impl Occurrence {
+    fn from_str_usize(method: &str, n: usize) -> Option<Self> {
+        match method {
+            "All" => Some(Self::All),
+            "First" => Some(Self::Front(1)),
+            "Last" => Some(Self::Back(1)),
+            "FromFront" => Some(Self::Front(n)),
+            "FromBack" => Some(Self::Back(n)),
+            _ => None,
+        }
+    }
+}
 
 pub struct ReplaceRenamer {
     builder: Builder,
@@ -39,6 +83,8 @@ impl ReplaceRenamer {
         let regexp_supported = self.object::<CheckButton>(ID_REGEXP_SUPPORTED);
         let replacement_entry = self.object::<Entry>(ID_REPLACEMENT_ENTRY);
         let case_insensitive = self.object::<CheckButton>(ID_CASE_SENSITIVE);
+        let occurrence_combo_box = self.object::<ComboBoxText>(ID_OCCURRENCE_COMBO_BOX);
+        let occurrence_spin_button = self.object::<SpinButton>(ID_OCCURRENCE_SPIN_BUTTON);
         let change_subject = self.change_subject.clone();
 
         pattern_entry.connect_changed(glib::clone!(
@@ -82,42 +128,218 @@ impl ReplaceRenamer {
             @weak pattern_entry => move |_| {
                 pattern_entry.emit_by_name::<()>("changed", &[]);
         }));
+
+        occurrence_combo_box.connect_changed(glib::clone!(
+            @weak pattern_entry, @weak occurrence_spin_button => move |occurrence_combo_box| {
+                let is_nth = matches!(
+                    occurrence_combo_box.active_id().as_deref(),
+                    Some("FromFront") | Some("FromBack")
+                );
+                occurrence_spin_button.set_sensitive(is_nth);
+                pattern_entry.emit_by_name::<()>("changed", &[]);
+        }));
+
+        occurrence_spin_button.connect_value_changed(glib::clone!(
+            @weak pattern_entry => move |_| {
+                pattern_entry.emit_by_name::<()>("changed", &[]);
+        }));
+
+        self.init_rules_callback();
     }
 
-    fn get_replacement_rule(&self) -> Result<(Regex, String), Error> {
+    fn init_rules_callback(&self) {
+        let rules_store = self.object::<ListStore>(ID_REPLACEMENT_RULES_STORE);
+        let rules_view = self.object::<TreeView>(ID_REPLACEMENT_RULES_VIEW);
+        let pattern_renderer = self.object::<CellRendererText>(ID_RULE_PATTERN_RENDERER);
+        let replacement_renderer = self.object::<CellRendererText>(ID_RULE_REPLACEMENT_RENDERER);
+        let regexp_renderer = self.object::<CellRendererToggle>(ID_RULE_REGEXP_RENDERER);
+        let case_sensitive_renderer =
+            self.object::<CellRendererToggle>(ID_RULE_CASE_SENSITIVE_RENDERER);
+        let add_rule_button = self.object::<Button>(ID_ADD_RULE_BUTTON);
+        let remove_rule_button = self.object::<Button>(ID_REMOVE_RULE_BUTTON);
+        let load_rules_button = self.object::<Button>(ID_LOAD_RULES_BUTTON);
+
+        for (renderer, column) in &[(pattern_renderer, 0), (replacement_renderer, 1)] {
+            let column = *column;
+            renderer.connect_edited(glib::clone!(@weak rules_store => move |_, path, text| {
+                if let Some(iter) = rules_store.iter(&path) {
+                    rules_store.set_value(&iter, column, &text.to_value());
+                }
+            }));
+        }
+        for (renderer, column) in &[(regexp_renderer, 2), (case_sensitive_renderer, 3)] {
+            let column = *column;
+            renderer.connect_toggled(glib::clone!(@weak rules_store => move |_, path| {
+                if let Some(iter) = rules_store.iter(&path) {
+                    let active = rules_store
+                        .value(&iter, column as i32)
+                        .get::<bool>()
+                        .unwrap_or(false);
+                    rules_store.set_value(&iter, column, &(!active).to_value());
+                }
+            }));
+        }
+
+        let change_subject = self.change_subject.clone();
+        rules_store.connect_row_changed(move |_, _, _| {
+            change_subject
+                .notify((RenamerType::Replace, ()))
+                .unwrap_or_default();
+        });
+        let change_subject = self.change_subject.clone();
+        rules_store.connect_row_inserted(move |_, _, _| {
+            change_subject
+                .notify((RenamerType::Replace, ()))
+                .unwrap_or_default();
+        });
+        let change_subject = self.change_subject.clone();
+        rules_store.connect_row_deleted(move |_, _| {
+            change_subject
+                .notify((RenamerType::Replace, ()))
+                .unwrap_or_default();
+        });
+
+        add_rule_button.connect_clicked(glib::clone!(
+            @weak rules_store, @weak rules_view => move |_| {
+            let iter = rules_store.insert_with_values(
+                None,
+                &[(0, &""), (1, &""), (2, &false), (3, &false)],
+            );
+            // Start entering the pattern of the new row.
+            let path = rules_store.path(&iter);
+            rules_view.set_cursor(&path, rules_view.column(0).as_ref(), true);
+        }));
+
+        remove_rule_button.connect_clicked(glib::clone!(
+            @weak rules_store, @weak rules_view => move |_| {
+            if let Some((_, iter)) = rules_view.selection().selected() {
+                rules_store.remove(&iter);
+            }
+        }));
+
+        rules_view.selection().connect_changed(glib::clone!(
+            @weak remove_rule_button => move |selection| {
+            remove_rule_button.set_sensitive(selection.count_selected_rows() > 0);
+        }));
+
+        load_rules_button.connect_clicked(glib::clone!(
+            @weak rules_store => move |load_rules_button| {
+            let parent = load_rules_button
+                .toplevel()
+                .and_then(|v| v.downcast::<Window>().ok());
+            let dialog = FileChooserDialog::builder()
+                .title("Load replacements")
+                .mnemonics_visible(true)
+                .action(FileChooserAction::Open)
+                .build();
+            dialog.set_transient_for(parent.as_ref());
+            dialog.add_buttons(&[
+                ("_Cancel", ResponseType::Cancel),
+                ("_OK", ResponseType::Accept),
+            ]);
+            let result = dialog.run();
+            dialog.close();
+
+            if result != ResponseType::Accept {
+                return;
+            }
+            if let Some(path) = dialog.filename() {
+                match fs::read_to_string(&path) {
+                    Ok(text) => {
+                        rules_store.clear();
+                        for (pattern, replacement, is_regexp, is_case_sensitive) in
+                            parse_rules(text.as_str())
+                        {
+                            rules_store.insert_with_values(
+                                None,
+                                &[
+                                    (0, &pattern),
+                                    (1, &replacement),
+                                    (2, &is_regexp),
+                                    (3, &is_case_sensitive),
+                                ],
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        let dialog = MessageDialog::builder()
+                            .buttons(ButtonsType::Ok)
+                            .message_type(MessageType::Error)
+                            .text("Failed to load replacements")
+                            .secondary_text(e.to_string().as_str())
+                            .build();
+                        dialog.set_transient_for(parent.as_ref());
+                        dialog.run();
+                        dialog.close();
+                    }
+                }
+            }
+        }));
+    }
+
+    fn get_replacement_rule(&self) -> Result<(Regex, ReplaceTemplate), Error> {
         let pattern = self.object::<Entry>(ID_PATTERN_ENTRY).text();
         let replacement = self.object::<Entry>(ID_REPLACEMENT_ENTRY).text();
         let is_regexp_supported = self.object::<CheckButton>(ID_REGEXP_SUPPORTED).is_active();
         let is_case_sensitive = self.object::<CheckButton>(ID_CASE_SENSITIVE).is_active();
 
-        let (pattern, replacement) = if is_regexp_supported {
-            (pattern.to_string(), replacement.to_string())
-        } else {
-            (
-                regex::escape(pattern.as_str()),
-                replacement.replace("$", "$$"),
-            )
-        };
-        let matcher = RegexBuilder::new(pattern.as_str())
-            .case_insensitive(!is_case_sensitive)
-            .build()?;
+        build_rule(
+            pattern.as_str(),
+            replacement.as_str(),
+            is_regexp_supported,
+            is_case_sensitive,
+        )
+    }
 
-        Ok((matcher, replacement.to_string()))
+    /// Returns the rule of the entries followed by the rules of the table, in order.
+    ///
+    /// The rows of the table with empty pattern are ignored.
+    fn get_replacement_rules(&self) -> Result<Vec<(Regex, ReplaceTemplate)>, Error> {
+        let mut rules = vec![self.get_replacement_rule()?];
+        let rules_store = self.object::<ListStore>(ID_REPLACEMENT_RULES_STORE);
+        for row in list_store_data_iter(&rules_store) {
+            let pattern = value2string(&row[0]);
+            if pattern.is_empty() {
+                continue;
+            }
+            rules.push(build_rule(
+                pattern.as_str(),
+                value2string(&row[1]).as_str(),
+                row[2].get::<bool>().unwrap_or(false),
+                row[3].get::<bool>().unwrap_or(false),
+            )?);
+        }
+        Ok(rules)
+    }
+
+    fn get_occurrence(&self) -> Occurrence {
+        let method = self
+            .object::<ComboBoxText>(ID_OCCURRENCE_COMBO_BOX)
+            .active_id();
+        let n = self
+            .object::<SpinButton>(ID_OCCURRENCE_SPIN_BUTTON)
+            .value_as_int();
+
+        method
+            .and_then(|method| Occurrence::from_str_usize(method.as_str(), n as usize))
+            .unwrap_or(Occurrence::All)
     }
 
     fn apply_replace_with(
-        matcher: &Regex,
-        replacement: &str,
+        rules: &[(Regex, ReplaceTemplate)],
+        occurrence: Occurrence,
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> IntoIter<(String, String)> {
         files
             .iter()
-            .map(|(file_name, dir_name)| {
+            .enumerate()
+            .map(|(i, (file_name, dir_name))| {
+                let counter = i + 1;
                 let new_file_name = match target {
                     RenamerTarget::Name => {
                         let (stem, extension) = split_file_at_dot(file_name.as_str());
-                        let new_stem = matcher.replace_all(stem, replacement).to_string();
+                        let new_stem = replace_all_with(rules, occurrence, stem, counter);
                         if let Some(suffix) = extension {
                             [new_stem.as_str(), suffix].join(".").to_string()
                         } else {
@@ -126,14 +348,14 @@ impl ReplaceRenamer {
                     }
                     RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
                         (stem, Some(suffix)) => {
-                            let new_suffix = matcher.replace_all(suffix, replacement).to_string();
+                            let new_suffix = replace_all_with(rules, occurrence, suffix, counter);
                             [stem, new_suffix.as_str()].join(".").to_string()
                         }
                         (stem, None) => stem.to_string(),
                     },
-                    RenamerTarget::All => matcher
-                        .replace_all(file_name.as_str(), replacement)
-                        .to_string(),
+                    RenamerTarget::All => {
+                        replace_all_with(rules, occurrence, file_name.as_str(), counter)
+                    }
                 };
                 (new_file_name.to_string(), dir_name.clone())
             })
@@ -156,10 +378,11 @@ impl Renamer for ReplaceRenamer {
         files: &[(String, String)],
         target: RenamerTarget,
     ) -> Result<IntoIter<(String, String)>, Error> {
-        let (matcher, replacement) = self.get_replacement_rule()?;
+        let rules = self.get_replacement_rules()?;
+        let occurrence = self.get_occurrence();
         Ok(Self::apply_replace_with(
-            &matcher,
-            replacement.as_str(),
+            rules.as_slice(),
+            occurrence,
             files,
             target,
         ))
@@ -170,6 +393,91 @@ impl Renamer for ReplaceRenamer {
     }
 }
 
+/// Build the matcher and the replacement template of the rule.
+fn build_rule(
+    pattern: &str,
+    replacement: &str,
+    is_regexp_supported: bool,
+    is_case_sensitive: bool,
+) -> Result<(Regex, ReplaceTemplate), Error> {
+    let (pattern, template) = if is_regexp_supported {
+        (pattern.to_string(), ReplaceTemplate::parse(replacement))
+    } else {
+        (
+            regex::escape(pattern),
+            ReplaceTemplate::literal(replacement),
+        )
+    };
+    let matcher = RegexBuilder::new(pattern.as_str())
+        .case_insensitive(!is_case_sensitive)
+        .build()?;
+
+    Ok((matcher, template))
+}
+
+/// Apply the rules to `occurrence` of the matches in `text` in order.
+///
+/// `counter` is the 1-based number of the file for `${n:03}`.
+fn replace_all_with(
+    rules: &[(Regex, ReplaceTemplate)],
+    occurrence: Occurrence,
+    text: &str,
+    counter: usize,
+) -> String {
+    rules
+        .iter()
+        .fold(text.to_string(), |text, (matcher, template)| {
+            if occurrence == Occurrence::All {
+                return matcher
+                    .replace_all(text.as_str(), |caps: &Captures| {
+                        template.expand(caps, counter)
+                    })
+                    .to_string();
+            }
+
+            let captures = matcher.captures_iter(text.as_str()).collect::<Vec<_>>();
+            let index = match occurrence {
+                Occurrence::All => None,
+                Occurrence::Front(n) => n.checked_sub(1),
+                Occurrence::Back(n) => captures.len().checked_sub(n),
+            };
+            let replaced = index.and_then(|i| captures.get(i)).map(|caps| {
+                let m = caps.get(0).unwrap();
+                [
+                    &text[..m.start()],
+                    template.expand(caps, counter).as_str(),
+                    &text[m.end()..],
+                ]
+                .concat()
+            });
+            replaced.unwrap_or(text)
+        })
+}
+
+/// Parse the replacement rules, one per line.
+///
+/// Each line has the pattern, the replacement and the flags separated by tabs. The flags are
+/// optional: `r` for a regular expression and `c` for case sensitive search. Empty lines and lines
+/// starting with `#` are ignored.
+fn parse_rules(text: &str) -> Vec<ReplacementRow> {
+    text.trim_start_matches('\u{feff}')
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let pattern = fields.next().unwrap_or_default().to_string();
+            let replacement = fields.next().unwrap_or_default().to_string();
+            let flags = fields.next().unwrap_or_default();
+            (
+                pattern,
+                replacement,
+                flags.contains('r'),
+                flags.contains('c'),
+            )
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -225,8 +533,8 @@ mod test {
 
         assert_eq!(
             ReplaceRenamer::apply_replace_with(
-                &matcher,
-                "x_$1",
+                &[(matcher.clone(), ReplaceTemplate::parse("x_$1"))],
+                Occurrence::All,
                 &[
                     ("a_1.txt".to_string(), "/tmp".to_string()),
                     ("a_1.a_2".to_string(), "/tmp".to_string()),
@@ -246,8 +554,8 @@ mod test {
 
         assert_eq!(
             ReplaceRenamer::apply_replace_with(
-                &matcher,
-                "x_$1",
+                &[(matcher.clone(), ReplaceTemplate::parse("x_$1"))],
+                Occurrence::All,
                 &[
                     ("a_1.txt".to_string(), "/tmp".to_string()),
                     ("a_1.a_2".to_string(), "/tmp".to_string()),
@@ -263,8 +571,8 @@ mod test {
 
         assert_eq!(
             ReplaceRenamer::apply_replace_with(
-                &matcher,
-                "x_$1",
+                &[(matcher.clone(), ReplaceTemplate::parse("x_$1"))],
+                Occurrence::All,
                 &[
                     ("a_1.txt".to_string(), "/tmp".to_string()),
                     ("a_1.a_2".to_string(), "/tmp".to_string()),
@@ -279,6 +587,200 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_replace_renamer_apply_replacement_with_rules() {
+        let rules = vec![
+            build_rule("%20", " ", false, false).unwrap(),
+            build_rule("_+", "_", true, false).unwrap(),
+            build_rule("^scan_", "", true, false).unwrap(),
+        ];
+
+        assert_eq!(
+            ReplaceRenamer::apply_replace_with(
+                &rules,
+                Occurrence::All,
+                &[
+                    ("scan__page%201.pdf".to_string(), "/tmp".to_string()),
+                    ("SCAN_b.pdf".to_string(), "/tmp".to_string()),
+                    ("a__b.pdf".to_string(), "/tmp".to_string()),
+                ],
+                RenamerTarget::All,
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("page 1.pdf".to_string(), "/tmp".to_string()),
+                ("b.pdf".to_string(), "/tmp".to_string()),
+                ("a_b.pdf".to_string(), "/tmp".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_renamer_apply_replacement_with_escapes() {
+        let rules = vec![
+            build_rule(r"^track (\d+) - (.*)$", r"${1:pad=2}_\L$2", true, false).unwrap(),
+            build_rule(r"^", r"${n:03}_", true, false).unwrap(),
+            build_rule(r"_", r"\U$$", false, false).unwrap(),
+        ];
+
+        assert_eq!(
+            ReplaceRenamer::apply_replace_with(
+                &rules,
+                Occurrence::All,
+                &[
+                    ("track 7 - Title.mp3".to_string(), "/tmp".to_string()),
+                    ("track 12 - Other Title.mp3".to_string(), "/tmp".to_string()),
+                ],
+                RenamerTarget::Name,
+            )
+            .collect::<Vec<_>>(),
+            vec![
+                ("001\\U$$07\\U$$title.mp3".to_string(), "/tmp".to_string()),
+                (
+                    "002\\U$$12\\U$$other title.mp3".to_string(),
+                    "/tmp".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_renamer_apply_replacement_with_occurrence() {
+        let rules = vec![build_rule(r"_v\d+", "", true, false).unwrap()];
+        let files = [
+            ("a_v1_v2.txt".to_string(), "/tmp".to_string()),
+            ("b_v1.txt".to_string(), "/tmp".to_string()),
+            ("c.txt".to_string(), "/tmp".to_string()),
+        ];
+        let apply = |occurrence| {
+            ReplaceRenamer::apply_replace_with(&rules, occurrence, &files, RenamerTarget::Name)
+                .map(|(file_name, _)| file_name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(apply(Occurrence::All), vec!["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(
+            apply(Occurrence::Front(1)),
+            vec!["a_v2.txt", "b.txt", "c.txt"]
+        );
+        assert_eq!(
+            apply(Occurrence::Back(1)),
+            vec!["a_v1.txt", "b.txt", "c.txt"]
+        );
+        assert_eq!(
+            apply(Occurrence::Front(2)),
+            vec!["a_v1.txt", "b_v1.txt", "c.txt"]
+        );
+        assert_eq!(
+            apply(Occurrence::Back(2)),
+            vec!["a_v2.txt", "b_v1.txt", "c.txt"]
+        );
+        assert_eq!(
+            apply(Occurrence::Front(0)),
+            vec!["a_v1_v2.txt", "b_v1.txt", "c.txt"]
+        );
+        assert_eq!(
+            Occurrence::from_str_usize("Last", 3),
+            Some(Occurrence::Back(1))
+        );
+        assert_eq!(
+            Occurrence::from_str_usize("FromBack", 3),
+            Some(Occurrence::Back(3))
+        );
+    }
+
+    #[test]
+    fn test_replace_renamer_get_occurrence() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let replace_renamer = ReplaceRenamer::new();
+            let occurrence_combo_box =
+                replace_renamer.object::<ComboBoxText>(ID_OCCURRENCE_COMBO_BOX);
+            let occurrence_spin_button =
+                replace_renamer.object::<SpinButton>(ID_OCCURRENCE_SPIN_BUTTON);
+
+            replace_renamer.attach_change(counter_observer.clone());
+
+            assert_eq!(replace_renamer.get_occurrence(), Occurrence::All);
+            assert!(!occurrence_spin_button.is_sensitive());
+
+            counter_observer.reset();
+            occurrence_combo_box.set_active_id(Some("Last"));
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(replace_renamer.get_occurrence(), Occurrence::Back(1));
+            assert!(!occurrence_spin_button.is_sensitive());
+
+            counter_observer.reset();
+            occurrence_combo_box.set_active_id(Some("FromFront"));
+            occurrence_spin_button.set_value(3.0);
+            assert_eq!(counter_observer.count(), 2);
+            assert_eq!(replace_renamer.get_occurrence(), Occurrence::Front(3));
+            assert!(occurrence_spin_button.is_sensitive());
+        });
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(
+            parse_rules("\u{feff}# comment\n%20\t \n\n_+\t_\tr\nVendor\t\trc\nTODO\n"),
+            vec![
+                ("%20".to_string(), " ".to_string(), false, false),
+                ("_+".to_string(), "_".to_string(), true, false),
+                ("Vendor".to_string(), "".to_string(), true, true),
+                ("TODO".to_string(), "".to_string(), false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_renamer_get_replacement_rules() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let replace_renamer = ReplaceRenamer::new();
+            let pattern_entry = replace_renamer.object::<Entry>(ID_PATTERN_ENTRY);
+            let rules_store = replace_renamer.object::<ListStore>(ID_REPLACEMENT_RULES_STORE);
+            let add_rule_button = replace_renamer.object::<Button>(ID_ADD_RULE_BUTTON);
+
+            replace_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&replace_renamer.get_panel())
+                .build()
+                .show_all();
+
+            pattern_entry.set_text("a");
+            assert_eq!(replace_renamer.get_replacement_rules().unwrap().len(), 1);
+
+            // The empty rows are ignored.
+            counter_observer.reset();
+            gtk_test::click(&add_rule_button);
+            gtk_test::wait(1);
+            assert_eq!(rules_store.iter_n_children(None), 1);
+            assert_eq!(replace_renamer.get_replacement_rules().unwrap().len(), 1);
+            assert!(counter_observer.count() > 0);
+
+            counter_observer.reset();
+            let iter = rules_store.iter_first().unwrap();
+            rules_store.set(&iter, &[(0, &"b+"), (1, &"c"), (2, &true)]);
+            assert!(counter_observer.count() > 0);
+            let rules = replace_renamer.get_replacement_rules().unwrap();
+            assert_eq!(rules.len(), 2);
+            assert_eq!(rules[1].0.as_str(), "b+");
+            assert_eq!(
+                replace_all_with(&rules, Occurrence::All, "abb.txt", 1),
+                "c.txt"
+            );
+
+            rules_store.set(&iter, &[(0, &"b(")]);
+            assert!(replace_renamer.get_replacement_rules().is_err());
+
+            counter_observer.reset();
+            rules_store.clear();
+            assert_eq!(counter_observer.count(), 1);
+            assert_eq!(replace_renamer.get_replacement_rules().unwrap().len(), 1);
+        });
+    }
+
     #[test]
     fn test_replace_renamer_get_replacement_rule_and_apply_replacement() {
         test_synced(move || {
@@ -295,28 +797,28 @@ mod test {
             case_insensitive.set_active(false);
             let (matcher, replacement) = replace_renamer.get_replacement_rule().unwrap();
             assert_eq!(matcher.as_str(), "a\\+bC\\(1\\)");
-            assert_eq!(replacement.as_str(), "def$$1");
+            assert_eq!(replacement, ReplaceTemplate::literal("def$1"));
             assert!(matcher.is_match("A+BC(1)"));
 
             regexp_supported.set_active(false);
             case_insensitive.set_active(true);
             let (matcher, replacement) = replace_renamer.get_replacement_rule().unwrap();
             assert_eq!(matcher.as_str(), "a\\+bC\\(1\\)");
-            assert_eq!(replacement.as_str(), "def$$1");
+            assert_eq!(replacement, ReplaceTemplate::literal("def$1"));
             assert!(!matcher.is_match("A+BC(1)"));
 
             regexp_supported.set_active(true);
             case_insensitive.set_active(false);
             let (matcher, replacement) = replace_renamer.get_replacement_rule().unwrap();
             assert_eq!(matcher.as_str(), "a+bC(1)");
-            assert_eq!(replacement.as_str(), "def$1");
+            assert_eq!(replacement, ReplaceTemplate::parse("def$1"));
             assert!(matcher.is_match("AaBC1"));
 
             regexp_supported.set_active(true);
             case_insensitive.set_active(true);
             let (matcher, replacement) = replace_renamer.get_replacement_rule().unwrap();
             assert_eq!(matcher.as_str(), "a+bC(1)");
-            assert_eq!(replacement.as_str(), "def$1");
+            assert_eq!(replacement, ReplaceTemplate::parse("def$1"));
             assert!(!matcher.is_match("AaBC1"));
         });
     }
@@ -0,0 +1,357 @@
+use crate::error::Error;
+use crate::utils::split_file_at_dot;
+use crate::utils::{Observer, SubjectImpl};
+use crate::win::provider::{Renamer, RenamerObserverArg, RenamerTarget, RenamerType};
+use crate::win::resource::resource_path;
+use deunicode::deunicode_char;
+use gtk::prelude::*;
+use gtk::{Builder, CheckButton, ComboBoxText, Container, Entry};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use strum_macros::EnumString;
+
+const ID_TRANSLITERATE_RENAMER_PANEL: &'static str = "transliterate-renamer-panel";
+const ID_PROFILE_COMBO_BOX: &'static str = "transliterate-profile-combo-box";
+const ID_UNSAFE_REPLACEMENT_ENTRY: &'static str = "unsafe-replacement-entry";
+const ID_COLLAPSE_SEPARATORS_CHECK_BUTTON: &'static str = "collapse-separators-check-button";
+
+/// Characters treated as the separators of the words
+const SEPARATORS: &[char] = &[' ', '_', '-', '.'];
+
+/// Set of the characters allowed in the file names
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString)]
+enum TransliterateProfile {
+    /// POSIX portable file name character set: `A-Z`, `a-z`, `0-9`, `.`, `_` and `-`
+    Posix,
+    /// Printable ASCII except the characters reserved on FAT/NTFS
+    Windows,
+    /// Lowercase `a-z`, `0-9` and `-`
+    Slug,
+}
+
+impl TransliterateProfile {
+    fn is_safe(&self, c: char) -> bool {
+        match self {
+            TransliterateProfile::Posix => c.is_ascii_alphanumeric() || "._-".contains(c),
+            TransliterateProfile::Windows => {
+                (c.is_ascii_graphic() || c == ' ') && !"<>:\"/\\|?*".contains(c)
+            }
+            TransliterateProfile::Slug => c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-',
+        }
+    }
+}
+
+/// Options of the transliteration
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct TransliterateRule {
+    profile: TransliterateProfile,
+    /// Replacement of the characters outside the profile; the slug always uses `-`
+    unsafe_replacement: String,
+    /// Collapse the runs of the separators and trim them at both ends
+    collapse_separators: bool,
+}
+
+impl TransliterateRule {
+    pub fn apply(&self, text: &str) -> String {
+        let replacement = match self.profile {
+            TransliterateProfile::Slug => "-",
+            _ => self.unsafe_replacement.as_str(),
+        };
+
+        let mut new_text = String::with_capacity(text.len());
+        for c in text.chars() {
+            // Remove the diacritics and transliterate to Latin.
+            let ascii = if c.is_ascii() {
+                None
+            } else {
+                deunicode_char(c)
+            };
+            let ascii = ascii
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| c.to_string());
+            for c in ascii.chars() {
+                let c = match self.profile {
+                    TransliterateProfile::Slug => c.to_ascii_lowercase(),
+                    _ => c,
+                };
+                if self.profile.is_safe(c) {
+                    new_text.push(c);
+                } else {
+                    new_text.push_str(replacement);
+                }
+            }
+        }
+
+        if self.collapse_separators {
+            new_text = collapse_separators(new_text.as_str());
+        }
+        if self.profile == TransliterateProfile::Windows {
+            // Windows drops the trailing dots and spaces.
+            new_text = new_text.trim_end_matches(&['.', ' '][..]).to_string();
+        }
+        new_text
+    }
+}
+
+/// Collapse the runs of the separators into the first one and trim the separators except `.` at
+/// both ends.
+fn collapse_separators(text: &str) -> String {
+    let mut new_text = String::with_capacity(text.len());
+    let mut last_is_separator = false;
+    for c in text.chars() {
+        let is_separator = SEPARATORS.contains(&c);
+        if !(is_separator && last_is_separator) {
+            new_text.push(c);
+        }
+        last_is_separator = is_separator;
+    }
+    new_text.trim_matches(&[' ', '_', '-'][..]).to_string()
+}
+
+pub struct TransliterateRenamer {
+    builder: Builder,
+    change_subject: Rc<SubjectImpl<RenamerObserverArg, Error>>,
+}
+
+impl TransliterateRenamer {
+    pub fn new() -> Self {
+        let builder =
+            Builder::from_resource(&resource_path("provider/transliterate_renamer.glade"));
+        let change_subject = Rc::new(SubjectImpl::new());
+        let renamer = Self {
+            builder,
+            change_subject,
+        };
+
+        renamer.init_callback();
+
+        renamer
+    }
+
+    fn init_callback(&self) {
+        let renamer_type = RenamerType::Transliterate;
+        let profile_combo_box = self.object::<ComboBoxText>(ID_PROFILE_COMBO_BOX);
+        let unsafe_replacement_entry = self.object::<Entry>(ID_UNSAFE_REPLACEMENT_ENTRY);
+        let collapse_separators_check_button =
+            self.object::<CheckButton>(ID_COLLAPSE_SEPARATORS_CHECK_BUTTON);
+
+        let change_subject = self.change_subject.clone();
+        profile_combo_box.connect_changed(glib::clone!(
+            @weak unsafe_replacement_entry => move |profile_combo_box| {
+            // The slug always replaces with `-`.
+            unsafe_replacement_entry
+                .set_sensitive(profile_combo_box.active_id().as_deref() != Some("Slug"));
+
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        }));
+
+        let change_subject = self.change_subject.clone();
+        unsafe_replacement_entry.connect_changed(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+
+        let change_subject = self.change_subject.clone();
+        collapse_separators_check_button.connect_toggled(move |_| {
+            change_subject
+                .notify((renamer_type, ()))
+                .unwrap_or_default();
+        });
+    }
+
+    fn get_replacement_rule(&self) -> Option<TransliterateRule> {
+        let profile = self
+            .object::<ComboBoxText>(ID_PROFILE_COMBO_BOX)
+            .active_id()
+            .and_then(|id| TransliterateProfile::from_str(id.as_str()).ok())?;
+        let unsafe_replacement = self
+            .object::<Entry>(ID_UNSAFE_REPLACEMENT_ENTRY)
+            .text()
+            .to_string();
+        let collapse_separators = self
+            .object::<CheckButton>(ID_COLLAPSE_SEPARATORS_CHECK_BUTTON)
+            .is_active();
+
+        Some(TransliterateRule {
+            profile,
+            unsafe_replacement,
+            collapse_separators,
+        })
+    }
+
+    fn apply_replace_with(
+        rule: &TransliterateRule,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> IntoIter<(String, String)> {
+        files
+            .iter()
+            .map(|(file_name, dir_name)| {
+                let new_file_name = match target {
+                    RenamerTarget::Name => {
+                        let (stem, extension) = split_file_at_dot(file_name.as_str());
+                        let new_stem = rule.apply(stem);
+                        if let Some(suffix) = extension {
+                            [new_stem.as_str(), suffix].join(".").to_string()
+                        } else {
+                            new_stem
+                        }
+                    }
+                    RenamerTarget::Suffix => match split_file_at_dot(file_name.as_str()) {
+                        (stem, Some(suffix)) => {
+                            let new_suffix = rule.apply(suffix);
+                            [stem, new_suffix.as_str()].join(".").to_string()
+                        }
+                        (stem, None) => stem.to_string(),
+                    },
+                    RenamerTarget::All => rule.apply(file_name.as_str()),
+                };
+                (new_file_name.to_string(), dir_name.clone())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+impl Renamer for TransliterateRenamer {
+    fn get_panel(&self) -> Container {
+        self.object::<Container>(ID_TRANSLITERATE_RENAMER_PANEL)
+    }
+
+    fn apply_replacement(
+        &self,
+        files: &[(String, String)],
+        target: RenamerTarget,
+    ) -> Result<IntoIter<(String, String)>, Error> {
+        let rule = self.get_replacement_rule().unwrap();
+        Ok(Self::apply_replace_with(&rule, files, target))
+    }
+
+    fn attach_change(&self, observer: Rc<dyn Observer<RenamerObserverArg, Error>>) {
+        self.change_subject.attach(observer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::test_synced;
+    use crate::utils::CounterObserver;
+    use gtk::Window;
+
+    fn rule(profile: TransliterateProfile, collapse_separators: bool) -> TransliterateRule {
+        TransliterateRule {
+            profile,
+            unsafe_replacement: "_".to_string(),
+            collapse_separators,
+        }
+    }
+
+    #[test]
+    fn test_transliterate_renamer_callback() {
+        test_synced(move || {
+            let counter_observer = Rc::new(CounterObserver::new());
+            let transliterate_renamer = TransliterateRenamer::new();
+            let profile_combo_box =
+                transliterate_renamer.object::<ComboBoxText>(ID_PROFILE_COMBO_BOX);
+            let unsafe_replacement_entry =
+                transliterate_renamer.object::<Entry>(ID_UNSAFE_REPLACEMENT_ENTRY);
+            let collapse_separators_check_button =
+                transliterate_renamer.object::<CheckButton>(ID_COLLAPSE_SEPARATORS_CHECK_BUTTON);
+
+            transliterate_renamer.attach_change(counter_observer.clone());
+
+            Window::builder()
+                .child(&transliterate_renamer.get_panel())
+                .build()
+                .show_all();
+
+            counter_observer.reset();
+            profile_combo_box.set_active_id(Some("Slug"));
+            assert_eq!(counter_observer.count(), 1);
+            assert!(!unsafe_replacement_entry.is_sensitive());
+            profile_combo_box.set_active_id(Some("Posix"));
+            assert_eq!(counter_observer.count(), 2);
+            assert!(unsafe_replacement_entry.is_sensitive());
+
+            counter_observer.reset();
+            unsafe_replacement_entry.set_text("-");
+            assert_eq!(counter_observer.count(), 1);
+
+            counter_observer.reset();
+            gtk_test::click(&collapse_separators_check_button);
+            assert_eq!(counter_observer.count(), 1);
+
+            assert_eq!(
+                transliterate_renamer.get_replacement_rule(),
+                Some(TransliterateRule {
+                    profile: TransliterateProfile::Posix,
+                    unsafe_replacement: "-".to_string(),
+                    collapse_separators: collapse_separators_check_button.is_active(),
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn test_transliterate_rule() {
+        let posix = rule(TransliterateProfile::Posix, true);
+        assert_eq!(posix.apply("Café crème"), "Cafe_creme");
+        assert_eq!(posix.apply("Москва 2019"), "Moskva_2019");
+        assert_eq!(posix.apply("Ελλάδα"), "Ellada");
+        assert_eq!(posix.apply("ひらがな"), "hiragana");
+        assert_eq!(posix.apply("北京"), "Bei_Jing");
+        assert_eq!(posix.apply(" a -- b?! "), "a_b");
+        assert_eq!(
+            rule(TransliterateProfile::Posix, false).apply(" a -- b?! "),
+            "_a_--_b___"
+        );
+
+        let windows = rule(TransliterateProfile::Windows, true);
+        assert_eq!(windows.apply("Ärger: a/b?"), "Arger_a_b");
+        assert_eq!(windows.apply("Naïve <x>..."), "Naive x");
+        assert_eq!(windows.apply("北京"), "Bei Jing");
+        assert_eq!(
+            rule(TransliterateProfile::Windows, false).apply("end. "),
+            "end"
+        );
+
+        let slug = rule(TransliterateProfile::Slug, true);
+        assert_eq!(slug.apply("Hello, Wörld!  2019"), "hello-world-2019");
+        assert_eq!(slug.apply("Straße_(Final)"), "strasse-final");
+    }
+
+    #[test]
+    fn test_transliterate_renamer_apply_replacement_with() {
+        let posix = rule(TransliterateProfile::Posix, true);
+        let files = [
+            ("Résumé final.pdf".to_string(), "/tmp".to_string()),
+            ("noext".to_string(), "/tmp".to_string()),
+        ];
+
+        assert_eq!(
+            TransliterateRenamer::apply_replace_with(&posix, &files, RenamerTarget::Name)
+                .collect::<Vec<_>>(),
+            vec![
+                ("Resume_final.pdf".to_string(), "/tmp".to_string()),
+                ("noext".to_string(), "/tmp".to_string()),
+            ]
+        );
+        assert_eq!(
+            TransliterateRenamer::apply_replace_with(&posix, &files, RenamerTarget::All)
+                .collect::<Vec<_>>(),
+            vec![
+                ("Resume_final.pdf".to_string(), "/tmp".to_string()),
+                ("noext".to_string(), "/tmp".to_string()),
+            ]
+        );
+    }
+}
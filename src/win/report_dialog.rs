@@ -0,0 +1,190 @@
+use crate::basic_bulk_renamer::{RenameMapPair, RenamePairResult};
+use crate::win::resource::resource_path;
+use gtk::prelude::*;
+use gtk::{Builder, Button, Clipboard, Dialog, Label, ListStore, ResponseType, Window};
+
+const ID_REPORT_DIALOG: &'static str = "report-dialog";
+const ID_REPORT_LIST_STORE: &'static str = "report-list-store";
+const ID_REPORT_SUMMARY_LABEL: &'static str = "report-summary-label";
+const ID_COPY_REPORT_BUTTON: &'static str = "copy-report-button";
+
+const RESPONSE_RETRY_FAILED: ResponseType = ResponseType::Other(1);
+const RESPONSE_UNDO_SUCCEEDED: ResponseType = ResponseType::Other(2);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(super) enum ReportResponse {
+    RetryFailed,
+    UndoSucceeded,
+    Close,
+}
+
+/// Dialog to show the result of each file
+pub(super) struct ReportDialog {
+    builder: Builder,
+}
+
+impl ReportDialog {
+    pub fn new<P: IsA<Window>>(
+        parent: Option<&P>,
+        results: &[(RenameMapPair, RenamePairResult)],
+    ) -> Self {
+        let builder = Builder::from_resource(&resource_path("report_dialog.glade"));
+        let report_dialog = Self { builder };
+
+        let dialog = report_dialog.dialog();
+        dialog.set_transient_for(parent);
+        report_dialog.set_results(results);
+
+        report_dialog
+    }
+
+    fn set_results(&self, results: &[(RenameMapPair, RenamePairResult)]) {
+        let dialog = self.dialog();
+        let report_list_store = self.object::<ListStore>(ID_REPORT_LIST_STORE);
+        let report_summary_label = self.object::<Label>(ID_REPORT_SUMMARY_LABEL);
+        let copy_report_button = self.object::<Button>(ID_COPY_REPORT_BUTTON);
+
+        report_list_store.clear();
+        for (pair, result) in results.iter() {
+            let (status, name, new_name, reason) = report_columns(pair, result);
+            let iter = report_list_store.append();
+            report_list_store.set(
+                &iter,
+                &[(0, &status), (1, &name), (2, &new_name), (3, &reason)],
+            );
+        }
+
+        let (renamed, skipped, failed) = count_results(results);
+        report_summary_label.set_text(
+            format!(
+                "{} renamed, {} skipped, {} failed",
+                renamed, skipped, failed
+            )
+            .as_str(),
+        );
+        dialog.set_response_sensitive(RESPONSE_RETRY_FAILED, failed > 0);
+        dialog.set_response_sensitive(RESPONSE_UNDO_SUCCEEDED, renamed > 0);
+
+        let report = format_report(results);
+        copy_report_button.connect_clicked(move |_| {
+            let clipboard = Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+            clipboard.set_text(report.as_str());
+        });
+    }
+
+    pub fn run(&self) -> ReportResponse {
+        let dialog = self.dialog();
+        let response = dialog.run();
+        dialog.close();
+
+        match response {
+            RESPONSE_RETRY_FAILED => ReportResponse::RetryFailed,
+            RESPONSE_UNDO_SUCCEEDED => ReportResponse::UndoSucceeded,
+            _ => ReportResponse::Close,
+        }
+    }
+
+    fn dialog(&self) -> Dialog {
+        self.object(ID_REPORT_DIALOG)
+    }
+
+    fn object<T: IsA<glib::Object>>(&self, name: &str) -> T {
+        self.builder.object(name).unwrap()
+    }
+}
+
+/// Returns the numbers of the renamed, skipped and failed files.
+fn count_results(results: &[(RenameMapPair, RenamePairResult)]) -> (usize, usize, usize) {
+    results.iter().fold(
+        (0, 0, 0),
+        |(renamed, skipped, failed), (_, result)| match result {
+            RenamePairResult::Renamed(_) => (renamed + 1, skipped, failed),
+            RenamePairResult::Skipped => (renamed, skipped + 1, failed),
+            RenamePairResult::Failed(_) => (renamed, skipped, failed + 1),
+        },
+    )
+}
+
+/// Returns the status, the name, the new name and the reason of the result.
+fn report_columns(
+    pair: &RenameMapPair,
+    result: &RenamePairResult,
+) -> (String, String, String, String) {
+    let (source, target) = pair;
+    let (status, target, reason) = match result {
+        RenamePairResult::Renamed(path) => ("OK", path, String::new()),
+        RenamePairResult::Skipped => ("Skipped", target, String::new()),
+        RenamePairResult::Failed(error) => ("Failed", target, error.to_string()),
+    };
+    (
+        status.to_string(),
+        source.display().to_string(),
+        target.display().to_string(),
+        reason,
+    )
+}
+
+/// Format the results as tab separated text.
+pub(super) fn format_report(results: &[(RenameMapPair, RenamePairResult)]) -> String {
+    results
+        .iter()
+        .map(|(pair, result)| {
+            let (status, name, new_name, reason) = report_columns(pair, result);
+            [status, name, new_name, reason].join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::basic_bulk_renamer::RenameError;
+    use crate::test::test_synced;
+    use std::path::PathBuf;
+
+    fn results() -> Vec<(RenameMapPair, RenamePairResult)> {
+        let pair1 = (PathBuf::from("/tmp/1.txt"), PathBuf::from("/tmp/2.txt"));
+        let pair2 = (PathBuf::from("/tmp/3.txt"), PathBuf::from("/tmp/3.txt"));
+        let pair3 = (PathBuf::from("/tmp/4.txt"), PathBuf::from("/tmp/5.txt"));
+        vec![
+            (
+                pair1.clone(),
+                RenamePairResult::Renamed(PathBuf::from("/tmp/2.txt")),
+            ),
+            (pair2, RenamePairResult::Skipped),
+            (
+                pair3.clone(),
+                RenamePairResult::Failed(RenameError::TargetFileAlreadyExists(pair3)),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_format_report() {
+        assert_eq!(
+            format_report(&results()),
+            [
+                "OK\t/tmp/1.txt\t/tmp/2.txt\t",
+                "Skipped\t/tmp/3.txt\t/tmp/3.txt\t",
+                "Failed\t/tmp/4.txt\t/tmp/5.txt\tTarget File Already Exists: /tmp/5.txt",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_report_dialog() {
+        test_synced(move || {
+            let report_dialog = ReportDialog::new::<Window>(None, &results());
+            let report_list_store = report_dialog.object::<ListStore>(ID_REPORT_LIST_STORE);
+            let report_summary_label = report_dialog.object::<Label>(ID_REPORT_SUMMARY_LABEL);
+
+            assert_eq!(report_list_store.iter_n_children(None), 3);
+            assert_eq!(
+                report_summary_label.text().as_str(),
+                "1 renamed, 1 skipped, 1 failed"
+            );
+        });
+    }
+}
@@ -1,35 +1,65 @@
-use crate::basic_bulk_renamer::{BulkRename, RenameError, RenameOverwriteMode};
+use crate::basic_bulk_renamer::{
+    export_plan, BulkRename, PlanFormat, RenameError, RenameMapPair, RenameOperation,
+    RenameOverwriteMode, RenamePairResult, RenameProgress,
+};
 use crate::error::Error;
 use crate::utils::get_path_from_selection_data;
 use crate::utils::Observer;
+use crate::win::editor::{confirm_name_changes, edit_names, EditorError};
 use crate::win::file_list::{
-    add_files_to_file_list, apply_renamer_to_file_list, get_files_from_file_list,
-    reset_renaming_of_file_list, set_files_to_file_list, RenamerTarget,
+    add_files_to_file_list, apply_renamer_to_file_list, get_companion_pairs,
+    get_files_from_file_list, get_new_names_from_file_list, has_pinned_new_names,
+    parse_companion_extensions, pin_new_names_of_file_list, reset_renaming_of_file_list,
+    set_companions_to_file_list, set_files_to_file_list, unpin_new_names_of_file_list,
+    RenamerTarget, DEFAULT_COMPANION_EXTENSIONS,
 };
+use crate::win::progress_dialog::ProgressDialog;
 use crate::win::provider::{Provider, RenamerObserverArg, RenamerType};
+use crate::win::report_dialog::{ReportDialog, ReportResponse};
 use crate::win::resource::{init_resource, resource_path};
 use gdk::DragAction;
 use gio::prelude::*;
 use gio::SimpleAction;
 use gtk::prelude::*;
 use gtk::{
-    Application, ApplicationWindow, Builder, ButtonsType, ComboBoxText, DestDefaults,
-    FileChooserAction, FileChooserDialog, ListStore, MessageDialog, MessageType, ResponseType,
-    Stack, TargetEntry, TargetFlags, TreeView,
+    Application, ApplicationWindow, Builder, ButtonsType, CheckButton, ComboBoxText, DestDefaults,
+    Entry, FileChooserAction, FileChooserButton, FileChooserDialog, FileFilter, ListStore,
+    MessageDialog, MessageType, ResponseType, Stack, TargetEntry, TargetFlags, TreeView,
 };
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use strum::IntoEnumIterator;
 
 const ACTION_ADD: &'static str = "add-action";
 const ACTION_REMOVE: &'static str = "remove-action";
 const ACTION_CLEAR: &'static str = "clear-action";
 const ACTION_EXECUTE: &'static str = "execute-action";
+const ACTION_EXPORT: &'static str = "export-action";
+const ACTION_EDIT: &'static str = "edit-action";
+const ACTION_UNPIN: &'static str = "unpin-action";
+
+/// Minimum interval between the progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
 
+const ID_ALLOW_FOLDERS_CHECK_BUTTON: &'static str = "allow-folders-check-button";
+const ID_COMPANION_EXTENSIONS_ENTRY: &'static str = "companion-extensions-entry";
+const ID_COMPANION_FILES_CHECK_BUTTON: &'static str = "companion-files-check-button";
+const ID_CONTINUE_ON_ERROR_CHECK_BUTTON: &'static str = "continue-on-error-check-button";
 const ID_FILE_LIST: &'static str = "file-list";
 const ID_FILE_LIST_STORE: &'static str = "file-list-store";
 const ID_MAIN_WINDOW: &'static str = "main-window";
+const ID_OPERATION_COMBO_BOX: &'static str = "operation-combo-box";
+const ID_OUTPUT_DIRECTORY_CHECK_BUTTON: &'static str = "output-directory-check-button";
+const ID_OUTPUT_DIRECTORY_CHOOSER_BUTTON: &'static str = "output-directory-chooser-button";
 const ID_RENAME_TARGET_COMBO_BOX: &'static str = "rename-target-combo-box";
 const ID_PROVIDER_STACK: &'static str = "provider-stack";
 const ID_PROVIDER_SWITCHER_COMBO_BOX: &'static str = "provider-switcher-combo-box";
@@ -76,7 +106,16 @@ impl Window {
         let selection = file_list.clone().selection();
         let rename_target_combo_box = self.object::<ComboBoxText>(ID_RENAME_TARGET_COMBO_BOX);
         let provider_stack = self.object::<Stack>(ID_PROVIDER_STACK);
-
+        let output_directory_check_button =
+            self.object::<CheckButton>(ID_OUTPUT_DIRECTORY_CHECK_BUTTON);
+        let output_directory_chooser_button =
+            self.object::<FileChooserButton>(ID_OUTPUT_DIRECTORY_CHOOSER_BUTTON);
+        let companion_files_check_button =
+            self.object::<CheckButton>(ID_COMPANION_FILES_CHECK_BUTTON);
+        let companion_extensions_entry = self.object::<Entry>(ID_COMPANION_EXTENSIONS_ENTRY);
+        companion_extensions_entry.set_text(DEFAULT_COMPANION_EXTENSIONS);
+
+        let builder = self.builder.clone();
         let renamer_change_observer = Rc::new(RenamerChangeObserver {
             builder: self.builder.clone(),
             provider: self.provider.clone(),
@@ -122,10 +161,11 @@ impl Window {
 
         let remove_action = SimpleAction::new(ACTION_REMOVE, None);
         remove_action.connect_activate(
-            glib::clone!(@weak file_list_store, @weak selection => move |_, _| {
+            glib::clone!(@weak file_list_store, @weak selection, @weak builder => move |_, _| {
                 selection.selected_foreach(|_, _, iter| {
                     file_list_store.remove(iter);
                 });
+                update_companions_of_file_list(&builder);
             }),
         );
         main_window.add_action(&remove_action);
@@ -139,57 +179,192 @@ impl Window {
         let execute_action = SimpleAction::new(ACTION_EXECUTE, None);
         execute_action.connect_activate(glib::clone!(
             @weak main_window,
+            @weak file_list,
             @weak file_list_store,
             @weak provider_stack,
+            @weak builder,
             @weak renamer_change_observer => move |_, _| {
-            let files = get_files_from_file_list(&file_list_store).collect::<Vec<_>>();
-            let mut renamer = BulkRename::new(files.clone());
-            renamer
-                .execute(RenameOverwriteMode::Error)
-                .map_err(|e| Error::Rename(e))
-                .and_then(|_| {
-                    let new_files = files.iter().map(|v| v.1.clone()).collect::<Vec<_>>();
-                    file_list_store.clear();
-                    add_files_to_file_list(&file_list_store, &new_files);
-                    let renamer_type = provider_stack
-                        .visible_child_name()
-                        .and_then(|v| RenamerType::from_str(v.as_str()).ok())
-                        .unwrap_or(RenamerType::Replace);
-                    renamer_change_observer.update(&(renamer_type, ()))
-                })
-                .or_else(|e| {
-                    let undo_error = renamer
-                        .undo_bulk_rename()
-                        .ok_or(RenameError::IllegalOperation)
-                        .and_then(|mut undo_renamer| {
-                            undo_renamer.execute(RenameOverwriteMode::Error)
+            let renamer = match new_bulk_rename(&builder) {
+                Ok(renamer) => renamer,
+                Err(e) => return show_error_dialog(&main_window, "Failed to rename", e),
+            };
+            let files_len = get_files_from_file_list(&file_list_store).count();
+            set_file_list_locked(&main_window, &file_list, true);
+            execute_in_background(&main_window, renamer, glib::clone!(
+                @weak main_window,
+                @weak file_list,
+                @weak file_list_store,
+                @weak provider_stack,
+                @weak renamer_change_observer => move |renamer, result, rollback_result| {
+                let operation = renamer.operation;
+                let report_window = main_window.clone();
+                let finish = move |result: Result<Vec<PathBuf>, RenameError>,
+                                   rollback_result: Result<(), RenameError>| {
+                    set_file_list_locked(&main_window, &file_list, false);
+                    result
+                        .map_err(|e| Error::Rename(e))
+                        .and_then(|renamed_files| {
+                            if operation == RenameOperation::Move {
+                                // The companion files follow the files in the list.
+                                let new_files = renamed_files[..files_len].to_vec();
+                                file_list_store.clear();
+                                add_files_to_file_list(&file_list_store, &new_files);
+                            }
+                            let renamer_type = provider_stack
+                                .visible_child_name()
+                                .and_then(|v| RenamerType::from_str(v.as_str()).ok())
+                                .unwrap_or(RenamerType::Replace);
+                            renamer_change_observer.update(&(renamer_type, ()))
+                        })
+                        .or_else(|e| {
+                            if let (Error::Rename(RenameError::Cancelled), Ok(_)) = (&e, &rollback_result) {
+                                // Cancelled by the user and restored successfully
+                                return Err(());
+                            }
+                            show_error_dialog(
+                                &main_window,
+                                "Failed to rename",
+                                rename_failure_message(&e, &rollback_result).as_str(),
+                            );
+                            Err(())
+                        })
+                        .unwrap_or_default();
+                };
+                match result {
+                    Err(RenameError::PartiallyFailed(results)) => {
+                        report_partial_failure(&report_window, renamer, results, move |locations| {
+                            finish(Ok(locations), Ok(()))
+                        })
+                    }
+                    result => {
+                        let result = result.map(|_| {
+                            renamer.renamed_files().map(|v| v.to_vec()).unwrap_or_else(|| {
+                                renamer.pairs.iter().map(|v| v.1.clone()).collect()
+                            })
                         });
-                    let detailed_message = format!(
-                        "{}\n{}",
-                        e.to_string(),
-                        match undo_error {
-                            Ok(_) => "Rename is not applied".to_string(),
-                            Err(undo_rename_error) => format!(
-                                "Rename is interrupted: {}",
-                                undo_rename_error.to_string()
-                            ),
-                        }
+                        finish(result, rollback_result)
+                    }
+                }
+            }));
+        }));
+        main_window.add_action(&execute_action);
+
+        let export_action = SimpleAction::new(ACTION_EXPORT, None);
+        export_action.connect_activate(glib::clone!(
+            @weak main_window,
+            @weak builder => move |_, _| {
+            // Export the plan of the same renamer as executing.
+            let renamer = match new_bulk_rename(&builder) {
+                Ok(renamer) => renamer,
+                Err(e) => return show_error_dialog(&main_window, "Failed to export", e),
+            };
+            let dialog = FileChooserDialog::builder()
+                .title("Export Plan")
+                .application(&main_window.application().unwrap())
+                .mnemonics_visible(true)
+                .do_overwrite_confirmation(true)
+                .action(FileChooserAction::Save)
+                .build();
+            dialog.add_buttons(&[
+                ("_Cancel", ResponseType::Cancel),
+                ("_Save", ResponseType::Accept),
+            ]);
+            for format in PlanFormat::iter() {
+                let filter = FileFilter::new();
+                filter.set_name(Some(plan_format_label(format)));
+                filter.add_pattern(format!("*.{}", format.extension()).as_str());
+                dialog.add_filter(&filter);
+            }
+            dialog.set_current_name("rename-plan.sh");
+            let result = dialog.run();
+            dialog.close();
+
+            if let (ResponseType::Accept, Some(path)) = (result, dialog.filename()) {
+                // The format follows the extension, or the selected filter.
+                let format = PlanFormat::from_path(&path)
+                    .or_else(|| {
+                        let filter_name = dialog.filter().and_then(|filter| filter.name())?;
+                        PlanFormat::iter()
+                            .find(|&format| plan_format_label(format) == filter_name.as_str())
+                    })
+                    .unwrap_or(PlanFormat::Shell);
+                write_plan(&path, &export_plan(&renamer, format), format).unwrap_or_else(|e| {
+                    show_error_dialog(
+                        &main_window,
+                        "Failed to export",
+                        format!("{}: {}", path.display(), e).as_str(),
                     );
+                });
+            }
+        }));
+        main_window.add_action(&export_action);
 
-                    let dialog = MessageDialog::builder()
-                        .application(&main_window.application().unwrap())
-                        .buttons(ButtonsType::Ok)
-                        .message_type(MessageType::Error)
-                        .text("Failed to rename")
-                        .secondary_text(detailed_message.as_str())
-                        .build();
-                    dialog.run();
-                    dialog.close();
-                    Err(())
-                })
-                .unwrap_or_default();
+        let edit_action = SimpleAction::new(ACTION_EDIT, None);
+        edit_action.connect_activate(glib::clone!(
+            @weak main_window,
+            @weak file_list,
+            @weak file_list_store,
+            @weak builder => move |_, _| {
+            let old_names = get_new_names_from_file_list(&file_list_store);
+            set_file_list_locked(&main_window, &file_list, true);
+            edit_names(&main_window, &old_names, glib::clone!(
+                @weak main_window,
+                @weak file_list,
+                @weak file_list_store,
+                @weak builder => move |result| {
+                set_file_list_locked(&main_window, &file_list, false);
+                match result {
+                    Ok(new_names) => {
+                        if confirm_name_changes(&main_window, &old_names, &new_names) {
+                            pin_new_names_of_file_list(&file_list_store, &new_names);
+                            update_companions_of_file_list(&builder);
+                            file_list.selection().emit_by_name::<()>("changed", &[]);
+                        }
+                    }
+                    Err(EditorError::Cancelled) => {}
+                    Err(e) => {
+                        show_error_dialog(&main_window, "Failed to edit", e.to_string().as_str())
+                    }
+                }
+            }));
+        }));
+        main_window.add_action(&edit_action);
+
+        let unpin_action = SimpleAction::new(ACTION_UNPIN, None);
+        unpin_action.connect_activate(glib::clone!(
+            @weak file_list,
+            @weak file_list_store,
+            @weak provider_stack,
+            @weak renamer_change_observer => move |_, _| {
+            unpin_new_names_of_file_list(&file_list_store);
+            let renamer_type = provider_stack
+                .visible_child_name()
+                .and_then(|v| RenamerType::from_str(v.as_str()).ok())
+                .unwrap_or(RenamerType::Replace);
+            renamer_change_observer
+                .update(&(renamer_type, ()))
+                .unwrap_or_else(|_| {
+                    reset_renaming_of_file_list(&file_list_store);
+                });
+            file_list.selection().emit_by_name::<()>("changed", &[]);
+        }));
+        main_window.add_action(&unpin_action);
+
+        companion_files_check_button.connect_toggled(glib::clone!(
+            @weak companion_extensions_entry,
+            @weak builder => move |companion_files_check_button| {
+            companion_extensions_entry.set_sensitive(companion_files_check_button.is_active());
+            update_companions_of_file_list(&builder);
+        }));
+
+        companion_extensions_entry.connect_changed(glib::clone!(@weak builder => move |_| {
+            update_companions_of_file_list(&builder);
+        }));
+
+        output_directory_check_button.connect_toggled(glib::clone!(
+            @weak output_directory_chooser_button => move |output_directory_check_button| {
+            output_directory_chooser_button.set_sensitive(output_directory_check_button.is_active());
         }));
-        main_window.add_action(&execute_action);
 
         selection.connect_changed(glib::clone!(
             @weak file_list_store,
@@ -197,7 +372,10 @@ impl Window {
             @weak selection,
             @weak remove_action,
             @weak clear_action,
-            @weak execute_action => move |_| {
+            @weak execute_action,
+            @weak export_action,
+            @weak edit_action,
+            @weak unpin_action => move |_| {
             let file_list_store_count = file_list_store.iter_n_children(None);
             if file_list_store_count == 0 {
                 file_list.columns_autosize();
@@ -205,6 +383,9 @@ impl Window {
             remove_action.set_enabled(selection.count_selected_rows() > 0);
             clear_action.set_enabled(file_list_store_count > 0);
             execute_action.set_enabled(file_list_store_count > 0);
+            export_action.set_enabled(file_list_store_count > 0);
+            edit_action.set_enabled(file_list_store_count > 0);
+            unpin_action.set_enabled(has_pinned_new_names(&file_list_store));
         }));
         file_list_store.connect_row_inserted(glib::clone!(@weak selection => move |_, _, _| {
             selection.emit_by_name::<()>("changed", &[]);
@@ -293,6 +474,360 @@ impl Window {
     }
 }
 
+/// Create the renamer of the files in the file list with the options chosen in the main window.
+///
+/// The companion files of the files are appended to the pairs if enabled.
+/// Returns the error message if the options are incomplete.
+fn new_bulk_rename(builder: &Builder) -> Result<BulkRename, &'static str> {
+    let file_list_store = builder.object::<ListStore>(ID_FILE_LIST_STORE).unwrap();
+    let is_active = |name: &str| builder.object::<CheckButton>(name).unwrap().is_active();
+
+    let files = get_files_from_file_list(&file_list_store).collect::<Vec<_>>();
+    let mut pairs = files.clone();
+    let extensions = companion_extensions_of(builder);
+    if !extensions.is_empty() {
+        pairs.extend(get_companion_pairs(&files, &extensions));
+    }
+    let mut renamer = BulkRename::new(pairs);
+    renamer.create_directories = is_active(ID_ALLOW_FOLDERS_CHECK_BUTTON);
+    renamer.continue_on_error = is_active(ID_CONTINUE_ON_ERROR_CHECK_BUTTON);
+    renamer.operation = builder
+        .object::<ComboBoxText>(ID_OPERATION_COMBO_BOX)
+        .unwrap()
+        .active_id()
+        .and_then(|id| RenameOperation::from_str(id.as_str()).ok())
+        .unwrap_or_default();
+    if is_active(ID_OUTPUT_DIRECTORY_CHECK_BUTTON) {
+        let output_directory = builder
+            .object::<FileChooserButton>(ID_OUTPUT_DIRECTORY_CHOOSER_BUTTON)
+            .unwrap()
+            .filename()
+            .ok_or("No output directory is chosen")?;
+        renamer.output_directory = Some(output_directory);
+    }
+    Ok(renamer)
+}
+
+/// Returns the extensions of the companion files, or nothing if the companion files are disabled.
+fn companion_extensions_of(builder: &Builder) -> Vec<String> {
+    let companion_files_check_button = builder
+        .object::<CheckButton>(ID_COMPANION_FILES_CHECK_BUTTON)
+        .unwrap();
+    if companion_files_check_button.is_active() {
+        let companion_extensions_entry = builder
+            .object::<Entry>(ID_COMPANION_EXTENSIONS_ENTRY)
+            .unwrap();
+        parse_companion_extensions(companion_extensions_entry.text().as_str())
+    } else {
+        Vec::new()
+    }
+}
+
+/// Show the companion files under their files in the file list if enabled.
+fn update_companions_of_file_list(builder: &Builder) {
+    let file_list_store = builder.object::<ListStore>(ID_FILE_LIST_STORE).unwrap();
+    set_companions_to_file_list(&file_list_store, &companion_extensions_of(builder));
+}
+
+/// Lock the file list and the actions on it while renaming, or unlock them.
+fn set_file_list_locked(main_window: &ApplicationWindow, file_list: &TreeView, locked: bool) {
+    file_list.set_sensitive(!locked);
+    for name in &[
+        ACTION_ADD,
+        ACTION_REMOVE,
+        ACTION_CLEAR,
+        ACTION_EXECUTE,
+        ACTION_EXPORT,
+        ACTION_EDIT,
+        ACTION_UNPIN,
+    ] {
+        if let Some(action) = main_window
+            .lookup_action(name)
+            .and_then(|action| action.downcast::<SimpleAction>().ok())
+        {
+            action.set_enabled(!locked);
+        }
+    }
+    if !locked {
+        // Restore the actions depending on the file list.
+        file_list.selection().emit_by_name::<()>("changed", &[]);
+    }
+}
+
+/// Message from the worker thread of renaming
+enum RenameMessage {
+    /// Progress of renaming, or of rolling back if the flag is `true`
+    Progress(RenameProgress, bool),
+    /// Renamer, its result and the result of the rollback
+    Finished(
+        Box<BulkRename>,
+        Result<(), RenameError>,
+        Result<(), RenameError>,
+    ),
+}
+
+/// Execute renaming on a worker thread with showing the progress dialog.
+///
+/// The renaming is rolled back on failure or cancellation unless it is partially failed.
+/// `on_finished` is called on the main thread with the renamer, its result and the result of the rollback.
+fn execute_in_background<F>(
+    main_window: &ApplicationWindow,
+    mut renamer: BulkRename,
+    on_finished: F,
+) where
+    F: FnOnce(BulkRename, Result<(), RenameError>, Result<(), RenameError>) + 'static,
+{
+    let progress_dialog = ProgressDialog::new(Some(main_window));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    progress_dialog.connect_cancel(glib::clone!(@strong cancelled => move || {
+        cancelled.store(true, Ordering::SeqCst);
+    }));
+
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    thread::spawn(move || {
+        let mut last_reported: Option<Instant> = None;
+        let mut report = |progress: &RenameProgress, rollback: bool| {
+            // Throttle the reports not to flood the main loop.
+            if last_reported.map_or(true, |v| v.elapsed() >= PROGRESS_INTERVAL) {
+                last_reported = Some(Instant::now());
+                sender
+                    .send(RenameMessage::Progress(progress.clone(), rollback))
+                    .unwrap_or_default();
+            }
+        };
+        let result = renamer.execute_with_progress(RenameOverwriteMode::Error, &mut |progress| {
+            report(progress, false);
+            !cancelled.load(Ordering::SeqCst)
+        });
+        let rollback_result = match result {
+            Ok(_) | Err(RenameError::PartiallyFailed(_)) => Ok(()),
+            Err(_) => renamer
+                .undo_bulk_rename()
+                .ok_or(RenameError::IllegalOperation)
+                .and_then(|mut undo_renamer| {
+                    undo_renamer.execute_with_progress(
+                        RenameOverwriteMode::Error,
+                        &mut |progress| {
+                            report(progress, true);
+                            true
+                        },
+                    )
+                }),
+        };
+        sender
+            .send(RenameMessage::Finished(
+                Box::new(renamer),
+                result,
+                rollback_result,
+            ))
+            .unwrap_or_default();
+    });
+
+    let mut on_finished = Some(on_finished);
+    receiver.attach(None, move |message| match message {
+        RenameMessage::Progress(progress, rollback) => {
+            progress_dialog.set_progress(&progress, rollback);
+            glib::Continue(true)
+        }
+        RenameMessage::Finished(renamer, result, rollback_result) => {
+            progress_dialog.close();
+            if let Some(on_finished) = on_finished.take() {
+                on_finished(*renamer, result, rollback_result);
+            }
+            glib::Continue(false)
+        }
+    });
+    progress_dialog.show();
+}
+
+/// Label of the file filter of the plan format
+fn plan_format_label(format: PlanFormat) -> &'static str {
+    match format {
+        PlanFormat::Shell => "Shell script",
+        PlanFormat::Csv => "CSV",
+        PlanFormat::Json => "JSON",
+    }
+}
+
+/// Write the exported plan to `path`. The shell script is made executable.
+fn write_plan(path: &Path, plan: &str, format: PlanFormat) -> io::Result<()> {
+    fs::write(path, plan)?;
+    if format == PlanFormat::Shell {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Show the error dialog with the detailed message.
+fn show_error_dialog(main_window: &ApplicationWindow, text: &str, detailed_message: &str) {
+    let dialog = MessageDialog::builder()
+        .application(&main_window.application().unwrap())
+        .buttons(ButtonsType::Ok)
+        .message_type(MessageType::Error)
+        .text(text)
+        .secondary_text(detailed_message)
+        .build();
+    dialog.run();
+    dialog.close();
+}
+
+/// Detailed message of the failed renaming including the result of its rollback
+fn rename_failure_message(
+    error: &dyn Display,
+    rollback_result: &Result<(), RenameError>,
+) -> String {
+    format!(
+        "{}\n{}",
+        error,
+        match rollback_result {
+            Ok(_) => "Rename is not applied".to_string(),
+            Err(undo_rename_error) => format!("Rename is interrupted: {}", undo_rename_error),
+        }
+    )
+}
+
+/// Progress of the partially failed renaming and its retries
+struct PartialFailure {
+    /// Source files in the order of the pairs of the first run
+    sources: Vec<PathBuf>,
+    /// Current locations of the files in the order of the pairs of the first run
+    locations: Vec<PathBuf>,
+    /// Indices of `locations` handled by the last run
+    indices: Vec<usize>,
+    /// First run and its retries
+    runs: Vec<BulkRename>,
+}
+
+/// Show the report of the partially failed renaming, and retry or undo it in background as requested.
+///
+/// `on_finished` is called with the locations of the files in the order of `renamer.pairs`.
+fn report_partial_failure<F>(
+    main_window: &ApplicationWindow,
+    renamer: BulkRename,
+    results: Vec<(RenameMapPair, RenamePairResult)>,
+    on_finished: F,
+) where
+    F: FnOnce(Vec<PathBuf>) + 'static,
+{
+    let sources = renamer
+        .pairs
+        .iter()
+        .map(|(source, _)| source.clone())
+        .collect::<Vec<_>>();
+    let locations = renamer
+        .renamed_files()
+        .map(|v| v.to_vec())
+        .unwrap_or_else(|| sources.clone());
+    let failure = PartialFailure {
+        indices: (0..locations.len()).collect(),
+        sources,
+        locations,
+        runs: vec![renamer],
+    };
+    show_partial_failure_report(main_window, failure, results, on_finished);
+}
+
+fn show_partial_failure_report<F>(
+    main_window: &ApplicationWindow,
+    mut failure: PartialFailure,
+    results: Vec<(RenameMapPair, RenamePairResult)>,
+    on_finished: F,
+) where
+    F: FnOnce(Vec<PathBuf>) + 'static,
+{
+    match ReportDialog::new(Some(main_window), &results).run() {
+        ReportResponse::RetryFailed => {
+            let retry = match failure.runs.last().and_then(|run| run.retry_bulk_rename()) {
+                Some(retry) => retry,
+                None => return on_finished(failure.locations),
+            };
+            failure.indices = failure
+                .indices
+                .iter()
+                .zip(results.iter())
+                .filter(|(_, (_, result))| matches!(result, RenamePairResult::Failed(_)))
+                .map(|(&index, _)| index)
+                .collect();
+            execute_in_background(
+                main_window,
+                retry,
+                glib::clone!(
+                    @weak main_window => move |retry, result, rollback_result| {
+                    if let Some(renamed_files) = retry.renamed_files() {
+                        for (&index, location) in failure.indices.iter().zip(renamed_files.iter()) {
+                            failure.locations[index] = location.clone();
+                        }
+                    }
+                    failure.runs.push(retry);
+                    match result {
+                        Ok(_) => on_finished(failure.locations),
+                        Err(RenameError::PartiallyFailed(retry_results)) => {
+                            show_partial_failure_report(&main_window, failure, retry_results, on_finished)
+                        }
+                        Err(e) => {
+                            if !matches!((&e, &rollback_result), (RenameError::Cancelled, Ok(_))) {
+                                show_error_dialog(
+                                    &main_window,
+                                    "Failed to rename",
+                                    rename_failure_message(&e, &rollback_result).as_str(),
+                                );
+                            }
+                            on_finished(failure.locations)
+                        }
+                    }
+                }),
+            );
+        }
+        ReportResponse::UndoSucceeded => {
+            let runs = std::mem::take(&mut failure.runs);
+            undo_runs(
+                main_window,
+                runs,
+                glib::clone!(@weak main_window => move |result| {
+                    match result {
+                        Ok(_) => on_finished(failure.sources),
+                        Err(e) => {
+                            show_error_dialog(
+                                &main_window,
+                                "Failed to rename",
+                                format!("Rename is interrupted: {}", e).as_str(),
+                            );
+                            on_finished(failure.locations)
+                        }
+                    }
+                }),
+            );
+        }
+        ReportResponse::Close => on_finished(failure.locations),
+    }
+}
+
+/// Undo `runs` one by one in background, the last one first.
+fn undo_runs<F>(main_window: &ApplicationWindow, mut runs: Vec<BulkRename>, on_finished: F)
+where
+    F: FnOnce(Result<(), RenameError>) + 'static,
+{
+    // Undo the retries first as they are done after the preceding runs.
+    let undo_renamer = match runs.pop() {
+        Some(run) => run.undo_bulk_rename(),
+        None => return on_finished(Ok(())),
+    };
+    match undo_renamer {
+        Some(undo_renamer) => execute_in_background(
+            main_window,
+            undo_renamer,
+            glib::clone!(
+                @weak main_window => move |_, result, _| {
+                match result {
+                    Ok(_) => undo_runs(&main_window, runs, on_finished),
+                    Err(e) => on_finished(Err(e)),
+                }
+            }),
+        ),
+        None => on_finished(Err(RenameError::IllegalOperation)),
+    }
+}
+
 struct RenamerChangeObserver {
     builder: Builder,
     provider: Rc<Provider>,
@@ -314,7 +849,9 @@ impl Observer<RenamerObserverArg, Error> for RenamerChangeObserver {
             .active_id()
             .and_then(|id| RenamerTarget::from_str(id.as_str()).ok())
             .unwrap_or(RenamerTarget::All);
-        apply_renamer_to_file_list(&file_list_store, target, renamer)
+        let result = apply_renamer_to_file_list(&file_list_store, target, renamer);
+        update_companions_of_file_list(&self.builder);
+        result
     }
 }
 
@@ -339,6 +876,7 @@ mod test {
             assert_eq!(win.simple_action(ACTION_REMOVE).is_enabled(), false);
             assert_eq!(win.simple_action(ACTION_CLEAR).is_enabled(), false);
             assert_eq!(win.simple_action(ACTION_EXECUTE).is_enabled(), false);
+            assert_eq!(win.simple_action(ACTION_EXPORT).is_enabled(), false);
 
             win.set_files(&[PathBuf::from("test")]);
             assert_eq!(
@@ -351,6 +889,7 @@ mod test {
             assert_eq!(win.simple_action(ACTION_REMOVE).is_enabled(), false);
             assert_eq!(win.simple_action(ACTION_CLEAR).is_enabled(), true);
             assert_eq!(win.simple_action(ACTION_EXECUTE).is_enabled(), true);
+            assert_eq!(win.simple_action(ACTION_EXPORT).is_enabled(), true);
 
             gtk_test::click(&win.object::<TreeView>(ID_FILE_LIST));
             assert_eq!(
@@ -364,6 +903,31 @@ mod test {
             assert_eq!(win.simple_action(ACTION_REMOVE).is_enabled(), true);
             assert_eq!(win.simple_action(ACTION_CLEAR).is_enabled(), true);
             assert_eq!(win.simple_action(ACTION_EXECUTE).is_enabled(), true);
+            assert_eq!(win.simple_action(ACTION_EXPORT).is_enabled(), true);
+
+            set_file_list_locked(
+                &win.main_window(),
+                &win.object::<TreeView>(ID_FILE_LIST),
+                true,
+            );
+            assert_eq!(win.object::<TreeView>(ID_FILE_LIST).is_sensitive(), false);
+            assert_eq!(win.simple_action(ACTION_ADD).is_enabled(), false);
+            assert_eq!(win.simple_action(ACTION_REMOVE).is_enabled(), false);
+            assert_eq!(win.simple_action(ACTION_CLEAR).is_enabled(), false);
+            assert_eq!(win.simple_action(ACTION_EXECUTE).is_enabled(), false);
+            assert_eq!(win.simple_action(ACTION_EXPORT).is_enabled(), false);
+
+            set_file_list_locked(
+                &win.main_window(),
+                &win.object::<TreeView>(ID_FILE_LIST),
+                false,
+            );
+            assert_eq!(win.object::<TreeView>(ID_FILE_LIST).is_sensitive(), true);
+            assert_eq!(win.simple_action(ACTION_ADD).is_enabled(), true);
+            assert_eq!(win.simple_action(ACTION_REMOVE).is_enabled(), true);
+            assert_eq!(win.simple_action(ACTION_CLEAR).is_enabled(), true);
+            assert_eq!(win.simple_action(ACTION_EXECUTE).is_enabled(), true);
+            assert_eq!(win.simple_action(ACTION_EXPORT).is_enabled(), true);
         });
     }
 }